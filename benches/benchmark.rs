@@ -41,6 +41,43 @@ fn create_array(c: &mut Criterion) {
     });
 }
 
+fn copy_array_into_slice(c: &mut Criterion) {
+    let lua = Lua::new();
+    let table = lua
+        .create_sequence_from((0..100_000).map(|i| i as f64))
+        .unwrap();
+    let mut buf = vec![0.0; 100_000];
+
+    c.bench_function("copy [array] 100000 into slice", |b| {
+        b.iter(|| table.copy_into_slice(&mut buf).unwrap());
+    });
+
+    c.bench_function("convert [array] 100000 into Vec", |b| {
+        b.iter(|| {
+            lua.unpack::<Vec<f64>>(LuaValue::Table(table.clone()))
+                .unwrap()
+        });
+    });
+}
+
+fn create_string_cached(c: &mut Criterion) {
+    let lua = Lua::new();
+    lua.enable_string_cache();
+
+    c.bench_function("create [string cached] 10000", |b| {
+        b.iter_batched(
+            || collect_gc_twice(&lua),
+            |_| {
+                for _ in 0..10_000 {
+                    lua.create_string("a string that is too long to be interned by the Lua VM")
+                        .unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        );
+    });
+}
+
 fn create_string_table(c: &mut Criterion) {
     let lua = Lua::new();
 
@@ -96,6 +133,29 @@ fn call_lua_function(c: &mut Criterion) {
     });
 }
 
+fn call_lua_function_into(c: &mut Criterion) {
+    let lua = Lua::new();
+
+    c.bench_function("call Lua function into buffer [sum] 3 10", |b| {
+        b.iter_batched_ref(
+            || {
+                collect_gc_twice(&lua);
+                lua.load("function(a, b, c) return a + b + c end")
+                    .eval::<LuaFunction>()
+                    .unwrap()
+            },
+            |function| {
+                let mut results = LuaMultiValue::new();
+                for i in 0..10 {
+                    results.clear();
+                    function.call_into((i, i + 1, i + 2), &mut results).unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        );
+    });
+}
+
 fn call_sum_callback(c: &mut Criterion) {
     let lua = Lua::new();
     let callback = lua
@@ -119,6 +179,26 @@ fn call_sum_callback(c: &mut Criterion) {
     });
 }
 
+fn call_after_warmup(c: &mut Criterion) {
+    c.bench_function("call Rust callback [first call] after warmup", |b| {
+        b.iter_batched(
+            || {
+                let lua = Lua::new();
+                lua.warmup().unwrap();
+                lua
+            },
+            |lua| {
+                {
+                    let callback = lua.create_function(|_, a: i64| Ok(a + 1)).unwrap();
+                    callback.call::<_, i64>(1).unwrap();
+                }
+                lua
+            },
+            BatchSize::SmallInput,
+        );
+    });
+}
+
 fn call_async_sum_callback(c: &mut Criterion) {
     let options = LuaOptions::new().thread_cache_size(1024);
     let lua = Lua::new_with(LuaStdLib::ALL_SAFE, options).unwrap();
@@ -302,10 +382,14 @@ criterion_group! {
     targets =
         create_table,
         create_array,
+        copy_array_into_slice,
         create_string_table,
+        create_string_cached,
         create_function,
         call_lua_function,
+        call_lua_function_into,
         call_sum_callback,
+        call_after_warmup,
         call_async_sum_callback,
         call_concat_callback,
         create_registry_values,
@@ -3,11 +3,13 @@ use std::ffi::CString;
 use std::io::Result as IoResult;
 use std::path::{Path, PathBuf};
 use std::string::String as StdString;
+use std::sync::Arc;
 
 use crate::error::{Error, Result};
 use crate::ffi;
 use crate::function::Function;
 use crate::lua::Lua;
+use crate::types::{MaybeSend, SourceMapCallback};
 use crate::value::{FromLuaMulti, ToLua, ToLuaMulti, Value};
 
 #[cfg(feature = "async")]
@@ -93,6 +95,8 @@ pub struct Chunk<'lua, 'a> {
     pub(crate) name: Option<StdString>,
     pub(crate) env: Result<Option<Value<'lua>>>,
     pub(crate) mode: Option<ChunkMode>,
+    pub(crate) source_map: Option<SourceMapCallback>,
+    pub(crate) transform_source: bool,
     #[cfg(feature = "luau")]
     pub(crate) compiler: Option<Compiler>,
 }
@@ -254,6 +258,9 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
     /// All global variables (including the standard library!) are looked up in `_ENV`, so it may be
     /// necessary to populate the environment in order for scripts using custom environments to be
     /// useful.
+    ///
+    /// Functions defined inside the chunk (at any nesting level) capture `_ENV` from the enclosing
+    /// chunk when they are created, so they see the same environment without extra setup.
     pub fn set_environment<V: ToLua<'lua>>(mut self, env: V) -> Result<Self> {
         // Prefer to propagate errors here and wrap to `Ok`
         self.env = Ok(Some(env.to_lua(self.lua)?));
@@ -269,6 +276,27 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
         self
     }
 
+    /// Sets a source map used to remap line numbers of this chunk in error messages.
+    ///
+    /// This is useful for code transpiled to Lua from other languages (eg. Teal or Fennel),
+    /// when errors should point to lines in the original source.
+    /// The map receives a line number in the loaded chunk and returns the original file name and
+    /// line number. Remapping is applied to runtime errors and to tracebacks of [`CallbackError`]s.
+    ///
+    /// The source map is kept as long as the chunk function is alive (eg. while it is running, or
+    /// when the function is kept using [`into_function`]), and matched by the chunk name, so
+    /// the chunk should have a unique name.
+    ///
+    /// [`CallbackError`]: crate::Error::CallbackError
+    /// [`into_function`]: #method.into_function
+    pub fn set_source_map<F>(mut self, source_map: F) -> Self
+    where
+        F: 'static + MaybeSend + Fn(u32) -> (StdString, u32),
+    {
+        self.source_map = Some(Arc::new(source_map));
+        self
+    }
+
     /// Sets or overwrites a Luau compiler used for this chunk.
     ///
     /// See [`Compiler`] for details and possible options.
@@ -285,7 +313,7 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
     ///
     /// This is equivalent to calling the chunk function with no arguments and no return values.
     pub fn exec(self) -> Result<()> {
-        self.call(())?;
+        self.call::<_, ()>(())?;
         Ok(())
     }
 
@@ -380,6 +408,15 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
     /// This simply compiles the chunk without actually executing it.
     #[cfg_attr(not(feature = "luau"), allow(unused_mut))]
     pub fn into_function(mut self) -> Result<Function<'lua>> {
+        if let Ok(ref source) = self.source {
+            if self.transform_source && self.detect_mode() == ChunkMode::Text {
+                let name = self.name.as_deref().unwrap_or_default();
+                if let Some(source) = self.lua.transform_source(name, source)? {
+                    self.source = Ok(Cow::Owned(source));
+                }
+            }
+        }
+
         #[cfg(feature = "luau")]
         if self.compiler.is_some() {
             // We don't need to compile source if no compiler set
@@ -387,8 +424,13 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
         }
 
         let name = self.convert_name()?;
-        self.lua
-            .load_chunk(self.source?.as_ref(), name.as_deref(), self.env?, self.mode)
+        let source_map = self.source_map.take();
+        let func =
+            (self.lua).load_chunk(self.source?.as_ref(), name.as_deref(), self.env?, self.mode)?;
+        if let Some(source_map) = source_map {
+            self.lua.set_source_map(&func, source_map)?;
+        }
+        Ok(func)
     }
 
     /// Compiles the chunk and changes mode to binary.
@@ -412,7 +454,12 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
         // We assume that mode is Text
         let source = self.source.as_ref();
         let source = source.map_err(|err| Error::RuntimeError(err.to_string()))?;
-        let source = Self::expression_source(source);
+        let name = self.name.as_deref().unwrap_or_default();
+        let transformed = match self.transform_source {
+            true => self.lua.transform_source(name, source)?,
+            false => None,
+        };
+        let source = Self::expression_source(transformed.as_deref().unwrap_or(source));
         // We don't need to compile source if no compiler options set
         #[cfg(feature = "luau")]
         let source = self
@@ -422,8 +469,11 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
             .unwrap_or(source);
 
         let name = self.convert_name()?;
-        self.lua
-            .load_chunk(&source, name.as_deref(), self.env.clone()?, None)
+        let func = (self.lua).load_chunk(&source, name.as_deref(), self.env.clone()?, None)?;
+        if let Some(source_map) = &self.source_map {
+            self.lua.set_source_map(&func, source_map.clone())?;
+        }
+        Ok(func)
     }
 
     fn detect_mode(&self) -> ChunkMode {
@@ -1,11 +1,13 @@
 #![allow(clippy::wrong_self_convention)]
 
 use std::borrow::Cow;
-use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::convert::TryInto;
 use std::ffi::{CStr, CString};
 use std::hash::{BuildHasher, Hash};
+use std::rc::Rc;
 use std::string::String as StdString;
+use std::sync::Arc;
 
 use bstr::{BStr, BString};
 use num_traits::cast;
@@ -44,13 +46,7 @@ impl<'lua> ToLua<'lua> for String<'lua> {
 impl<'lua> FromLua<'lua> for String<'lua> {
     #[inline]
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<String<'lua>> {
-        let ty = value.type_name();
-        lua.coerce_string(value)?
-            .ok_or_else(|| Error::FromLuaConversionError {
-                from: ty,
-                to: "String",
-                message: Some("expected string or number".to_string()),
-            })
+        lua.convert_string(value, "String")
     }
 }
 
@@ -229,16 +225,7 @@ impl<'lua> ToLua<'lua> for StdString {
 impl<'lua> FromLua<'lua> for StdString {
     #[inline]
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
-        let ty = value.type_name();
-        Ok(lua
-            .coerce_string(value)?
-            .ok_or_else(|| Error::FromLuaConversionError {
-                from: ty,
-                to: "String",
-                message: Some("expected string or number".to_string()),
-            })?
-            .to_str()?
-            .to_owned())
+        Ok(lua.convert_string(value, "String")?.to_str()?.to_owned())
     }
 }
 
@@ -263,14 +250,8 @@ impl<'lua> ToLua<'lua> for Box<str> {
 
 impl<'lua> FromLua<'lua> for Box<str> {
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
-        let ty = value.type_name();
         Ok(lua
-            .coerce_string(value)?
-            .ok_or_else(|| Error::FromLuaConversionError {
-                from: ty,
-                to: "Box<str>",
-                message: Some("expected string or number".to_string()),
-            })?
+            .convert_string(value, "Box<str>")?
             .to_str()?
             .to_owned()
             .into_boxed_str())
@@ -286,13 +267,7 @@ impl<'lua> ToLua<'lua> for CString {
 impl<'lua> FromLua<'lua> for CString {
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         let ty = value.type_name();
-        let string = lua
-            .coerce_string(value)?
-            .ok_or_else(|| Error::FromLuaConversionError {
-                from: ty,
-                to: "CString",
-                message: Some("expected string or number".to_string()),
-            })?;
+        let string = lua.convert_string(value, "CString")?;
 
         match CStr::from_bytes_with_nul(string.as_bytes_with_nul()) {
             Ok(s) => Ok(s.into()),
@@ -325,16 +300,8 @@ impl<'lua> ToLua<'lua> for BString {
 
 impl<'lua> FromLua<'lua> for BString {
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
-        let ty = value.type_name();
         Ok(BString::from(
-            lua.coerce_string(value)?
-                .ok_or_else(|| Error::FromLuaConversionError {
-                    from: ty,
-                    to: "String",
-                    message: Some("expected string or number".to_string()),
-                })?
-                .as_bytes()
-                .to_vec(),
+            lua.convert_string(value, "BString")?.as_bytes().to_vec(),
         ))
     }
 }
@@ -532,6 +499,25 @@ impl<'lua, T: FromLua<'lua>> FromLua<'lua> for Vec<T> {
     }
 }
 
+impl<'lua, T: ToLua<'lua>> ToLua<'lua> for VecDeque<T> {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Table(lua.create_sequence_from(self)?))
+    }
+}
+
+impl<'lua, T: FromLua<'lua>> FromLua<'lua> for VecDeque<T> {
+    fn from_lua(value: Value<'lua>, _lua: &'lua Lua) -> Result<Self> {
+        match value {
+            Value::Table(table) => table.sequence_values().collect(),
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "VecDeque",
+                message: Some("expected table".to_string()),
+            }),
+        }
+    }
+}
+
 impl<'lua, K: Eq + Hash + ToLua<'lua>, V: ToLua<'lua>, S: BuildHasher> ToLua<'lua>
     for HashMap<K, V, S>
 {
@@ -588,9 +574,13 @@ impl<'lua, T: Eq + Hash + FromLua<'lua>, S: BuildHasher + Default> FromLua<'lua>
     fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
         match value {
             Value::Table(table) if table.len()? > 0 => table.sequence_values().collect(),
+            // Set idiom `{[elem] = true}`, elements mapped to `false` are not members
             Value::Table(table) => table
                 .pairs::<T, Value<'lua>>()
-                .map(|res| res.map(|(k, _)| k))
+                .filter_map(|res| match res {
+                    Ok((_, Value::Boolean(false))) => None,
+                    res => Some(res.map(|(k, _)| k)),
+                })
                 .collect(),
             _ => Err(Error::FromLuaConversionError {
                 from: value.type_name(),
@@ -613,9 +603,13 @@ impl<'lua, T: Ord + FromLua<'lua>> FromLua<'lua> for BTreeSet<T> {
     fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
         match value {
             Value::Table(table) if table.len()? > 0 => table.sequence_values().collect(),
+            // Set idiom `{[elem] = true}`, elements mapped to `false` are not members
             Value::Table(table) => table
                 .pairs::<T, Value<'lua>>()
-                .map(|res| res.map(|(k, _)| k))
+                .filter_map(|res| match res {
+                    Ok((_, Value::Boolean(false))) => None,
+                    res => Some(res.map(|(k, _)| k)),
+                })
                 .collect(),
             _ => Err(Error::FromLuaConversionError {
                 from: value.type_name(),
@@ -645,3 +639,72 @@ impl<'lua, T: FromLua<'lua>> FromLua<'lua> for Option<T> {
         }
     }
 }
+
+impl<'lua, T: Clone + ToLua<'lua>> ToLua<'lua> for Rc<T> {
+    #[inline]
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Rc::try_unwrap(self)
+            .unwrap_or_else(|rc| (*rc).clone())
+            .to_lua(lua)
+    }
+}
+
+impl<'lua, T: FromLua<'lua>> FromLua<'lua> for Rc<T> {
+    #[inline]
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        T::from_lua(value, lua).map(Rc::new)
+    }
+}
+
+// `Box` is a fundamental type, so a generic `impl<T: ToLua> ToLua for Box<T>` would conflict with
+// the `UserData` blanket impls (downstream crates are allowed to implement `UserData` for
+// `Box<TheirType>`). Instead we provide conversions for boxed std types only.
+macro_rules! lua_convert_box {
+    ($($x:ty),*) => {
+        $(
+            impl<'lua> ToLua<'lua> for Box<$x> {
+                #[inline]
+                fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+                    (*self).to_lua(lua)
+                }
+            }
+
+            impl<'lua> FromLua<'lua> for Box<$x> {
+                #[inline]
+                fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+                    <$x>::from_lua(value, lua).map(Box::new)
+                }
+            }
+        )*
+    };
+}
+
+lua_convert_box!(bool, StdString, BString, CString);
+lua_convert_box!(i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize, f32, f64);
+
+// Generic `Arc<T>` conversions would conflict with the `UserData` blanket impls as well
+// (`Arc<Mutex<T>>` and friends implement `UserData`), so only std types are covered.
+macro_rules! lua_convert_arc {
+    ($($x:ty),*) => {
+        $(
+            impl<'lua> ToLua<'lua> for Arc<$x> {
+                #[inline]
+                fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+                    Arc::try_unwrap(self)
+                        .unwrap_or_else(|arc| (*arc).clone())
+                        .to_lua(lua)
+                }
+            }
+
+            impl<'lua> FromLua<'lua> for Arc<$x> {
+                #[inline]
+                fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+                    <$x>::from_lua(value, lua).map(Arc::new)
+                }
+            }
+        )*
+    };
+}
+
+lua_convert_arc!(bool, StdString, BString, CString);
+lua_convert_arc!(i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize, f32, f64);
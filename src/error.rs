@@ -47,6 +47,12 @@ pub enum Error {
     /// This error can only happen when Lua state was not created by us and does not have the
     /// custom allocator attached.
     MemoryLimitNotAvailable,
+    /// The limit on the number of allocations made during a single call into Lua was reached.
+    ///
+    /// See [`Lua::set_resource_limit`] for details.
+    ///
+    /// [`Lua::set_resource_limit`]: crate::Lua::set_resource_limit
+    ResourceLimit,
     /// Main thread is not available.
     ///
     /// This error can only happen in Lua5.1/LuaJIT module mode, when module loaded within a coroutine.
@@ -71,6 +77,17 @@ pub enum Error {
     StackError,
     /// Too many arguments to `Function::bind`
     BindError,
+    /// A Lua function returned more values than allowed.
+    ///
+    /// See [`Lua::set_max_results`] for details.
+    ///
+    /// [`Lua::set_max_results`]: crate::Lua::set_max_results
+    TooManyResults {
+        /// Number of values returned by the function.
+        count: usize,
+        /// The configured limit.
+        limit: usize,
+    },
     /// A Rust value could not be converted to a Lua value.
     ToLuaConversionError {
         /// Name of the Rust type that could not be converted.
@@ -162,6 +179,20 @@ pub enum Error {
     /// This error can occur only when a Rust panic resumed previously was recovered
     /// and returned again.
     PreviouslyResumedPanic,
+    /// An I/O error occurred while reading a Lua source file.
+    FileError {
+        /// Path to the file.
+        path: StdString,
+        /// Underlying I/O error.
+        cause: Arc<IoError>,
+    },
+    /// An error to be reported at the given call stack level, like Lua's `error(message, level)`.
+    ///
+    /// When returned from a Rust callback, the error message is prefixed with the position of
+    /// the function at `level` in the call stack, as `luaL_error` does. Level 1 is the callback
+    /// itself (which has no position), level 2 is the Lua function that called the callback,
+    /// and so on. Level 0 (or negative) adds no position.
+    WithLevel(Box<Error>, i32),
     /// Serialization error.
     #[cfg(feature = "serialize")]
     #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
@@ -202,6 +233,9 @@ impl fmt::Display for Error {
             Error::MemoryLimitNotAvailable => {
                 write!(fmt, "setting memory limit is not available")
             }
+            Error::ResourceLimit => {
+                write!(fmt, "resource limit reached: too many allocations during a single call")
+            }
             Error::MainThreadNotAvailable => {
                 write!(fmt, "main thread is not available in Lua 5.1")
             }
@@ -218,6 +252,11 @@ impl fmt::Display for Error {
                 fmt,
                 "too many arguments to Function::bind"
             ),
+            Error::TooManyResults { count, limit } => write!(
+                fmt,
+                "too many results returned from a function ({}, limit is {})",
+                count, limit
+            ),
             Error::ToLuaConversionError { from, to, ref message } => {
                 write!(fmt, "error converting {} to Lua {}", from, to)?;
                 match *message {
@@ -274,6 +313,10 @@ impl fmt::Display for Error {
             Error::PreviouslyResumedPanic => {
                 write!(fmt, "previously resumed panic returned again")
             }
+            Error::FileError { ref path, ref cause } => {
+                write!(fmt, "cannot read file '{}': {}", path, cause)
+            }
+            Error::WithLevel(ref err, _) => write!(fmt, "{}", err),
             #[cfg(feature = "serialize")]
             Error::SerializeError(ref err) => {
                 write!(fmt, "serialize error: {}", err)
@@ -305,6 +348,66 @@ impl Error {
     pub fn external<T: Into<Box<dyn StdError + Send + Sync>>>(err: T) -> Error {
         Error::ExternalError(err.into().into())
     }
+
+    /// Formats the error for displaying in a terminal.
+    ///
+    /// With `feature = "color"` the error messages are highlighted with ANSI escape codes, as well
+    /// as the sources of the stack traceback frames. Otherwise (or if the `NO_COLOR` environment
+    /// variable is set) this is the same as the [`Display`] output.
+    ///
+    /// [`Display`]: std::fmt::Display
+    pub fn format_colored(&self) -> StdString {
+        let plain = self.to_string();
+        #[cfg(feature = "color")]
+        if std::env::var_os("NO_COLOR").is_none() {
+            return colorize(&plain);
+        }
+        plain
+    }
+}
+
+// Highlights error messages and traceback frame sources in the `Display` output of an error
+#[cfg(feature = "color")]
+fn colorize(plain: &str) -> StdString {
+    const RESET: &str = "\x1b[0m";
+    const BOLD: &str = "\x1b[1m";
+    const RED: &str = "\x1b[1;31m";
+    const YELLOW: &str = "\x1b[33m";
+    const CYAN: &str = "\x1b[36m";
+
+    let mut out = StdString::with_capacity(plain.len() * 2);
+    for (i, line) in plain.lines().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        if line.starts_with("stack traceback:") || line == "callback error" {
+            out.push_str(&format!("{}{}{}", BOLD, line, RESET));
+        } else if line.starts_with('\t') || line.starts_with('>') {
+            // Traceback frame, eg. `\t[string "chunk"]:3: in main chunk`
+            // The frame marked with `>` is where the error was raised
+            let (marker, frame) = match line.strip_prefix('>') {
+                Some(frame) => (format!("{}>{}", YELLOW, RESET), frame),
+                None => (StdString::new(), line),
+            };
+            let indent_len = frame.len() - frame.trim_start().len();
+            let (indent, frame) = frame.split_at(indent_len);
+            match frame.find(": ") {
+                Some(pos) => out.push_str(&format!(
+                    "{}{}{}{}{}{}",
+                    marker,
+                    indent,
+                    CYAN,
+                    &frame[..pos],
+                    RESET,
+                    &frame[pos..]
+                )),
+                None => out.push_str(&format!("{}{}{}", marker, indent, frame)),
+            }
+        } else {
+            out.push_str(&format!("{}{}{}", RED, line, RESET));
+        }
+    }
+    out
 }
 
 pub trait ExternalError {
@@ -64,7 +64,9 @@ pub unsafe fn lua_tointegerx(L: *mut lua_State, i: c_int, isnum: *mut c_int) ->
     let mut ok = 0;
     let n = lua_tonumberx(L, i, &mut ok);
     let n_int = n as lua_Integer;
-    if ok != 0 && (n - n_int as lua_Number).abs() < lua_Number::EPSILON {
+    // Floats outside of the integer range are saturated by the cast, they are not convertible
+    let in_range = n >= lua_Integer::MIN as lua_Number && n < -(lua_Integer::MIN as lua_Number);
+    if ok != 0 && in_range && (n - n_int as lua_Number).abs() < lua_Number::EPSILON {
         if !isnum.is_null() {
             *isnum = 1;
         }
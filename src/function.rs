@@ -1,19 +1,46 @@
 use std::os::raw::c_int;
 use std::ptr;
+use std::result::Result as StdResult;
+use std::string::String as StdString;
+use std::time::{Duration, Instant};
 
 use crate::error::{Error, Result};
 use crate::ffi;
 use crate::types::LuaRef;
 use crate::util::{assert_stack, check_stack, error_traceback, pop_error, StackGuard};
-use crate::value::{FromLuaMulti, ToLuaMulti};
+use crate::value::{FromLua, FromLuaMulti, MultiValue, ToLuaMulti, Value};
 
 #[cfg(feature = "async")]
 use {futures_core::future::LocalBoxFuture, futures_util::future};
 
+#[cfg(all(feature = "serialize", not(feature = "luau")))]
+use {
+    crate::{chunk::ChunkMode, lua::Lua},
+    serde::de::{self, Deserializer, SeqAccess, Visitor},
+    serde::ser::{self, Serialize, Serializer},
+    std::fmt,
+};
+
 /// Handle to an internal Lua function.
 #[derive(Clone, Debug)]
 pub struct Function<'lua>(pub(crate) LuaRef<'lua>);
 
+/// Declared parameters of a function, returned by [`Function::params`].
+///
+/// Requires `feature = "lua54/lua53/lua52"`
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionParams {
+    /// Number of fixed parameters, or `None` for Rust and C functions.
+    pub arity: Option<usize>,
+    /// Whether the function accepts a variable number of arguments (`...`).
+    ///
+    /// Always `true` for Rust and C functions.
+    pub is_vararg: bool,
+    /// Names of the fixed parameters, empty if debug information is stripped or unknown.
+    pub names: Vec<StdString>,
+}
+
 impl<'lua> Function<'lua> {
     /// Calls the function, passing `args` as function arguments.
     ///
@@ -60,31 +87,262 @@ impl<'lua> Function<'lua> {
 
         let mut args = args.to_lua_multi(lua)?;
         let nargs = args.len() as c_int;
+        lua.trace_api(|| {
+            let args = args.iter().map(Value::to_trace_string).collect::<Vec<_>>();
+            format!("Function::call({})", args.join(", "))
+        });
 
-        let results = unsafe {
+        // If the conversion uses a fixed number of values (eg. a single value), request exactly that
+        // many results, so the extra ones are not even pushed onto the stack.
+        let nresults = R::MAX_ARGS.map(|n| n as c_int);
+
+        unsafe {
             let _sg = StackGuard::new(lua.state);
-            check_stack(lua.state, nargs + 3)?;
+            let nresults = self.call_on_stack(nargs, nresults, || {
+                for arg in args.drain_all() {
+                    lua.push_value(arg)?;
+                }
+                Ok(())
+            })?;
+            if R::MAX_ARGS == Some(1) {
+                // Single value fast path: convert the value directly off the stack
+                lua.cache_multivalue(args);
+                return R::from_lua_single(lua.pop_value(), lua);
+            }
+            let mut results = args; // Reuse MultiValue container
+            for _ in 0..nresults {
+                results.push_front(lua.pop_value());
+            }
+            R::from_lua_multi(results, lua)
+        }
+    }
 
-            ffi::lua_pushcfunction(lua.state, error_traceback);
-            let stack_start = ffi::lua_gettop(lua.state);
-            lua.push_ref(&self.0);
-            for arg in args.drain_all() {
-                lua.push_value(arg)?;
+    /// Calls the function, passing `args` as function arguments, and appends the function's
+    /// return values to the `results` buffer.
+    ///
+    /// Unlike [`call`], this method does not allocate a new container for the results, so a single
+    /// buffer can be reused across many calls (eg. in a hot dispatch loop). Clearing the buffer
+    /// before each call is the cheapest, appending to a non-empty buffer moves its existing values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, MultiValue, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let sum: Function = lua.load("function(a, b) return a + b end").eval()?;
+    ///
+    /// let mut results = MultiValue::new();
+    /// for i in 0..10 {
+    ///     results.clear();
+    ///     sum.call_into((i, 1), &mut results)?;
+    ///     assert_eq!(results.iter().next(), Some(&Value::Integer(i + 1)));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`call`]: #method.call
+    pub fn call_into<A: ToLuaMulti<'lua>>(
+        &self,
+        args: A,
+        results: &mut MultiValue<'lua>,
+    ) -> Result<()> {
+        let lua = self.0.lua;
+
+        let mut args = args.to_lua_multi(lua)?;
+        let nargs = args.len() as c_int;
+
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            let nresults = self.call_on_stack(nargs, None, || {
+                for arg in args.drain_all() {
+                    lua.push_value(arg)?;
+                }
+                Ok(())
+            })?;
+            lua.cache_multivalue(args);
+
+            let appended = !results.is_empty();
+            results.reserve(nresults as usize);
+            for _ in 0..nresults {
+                results.push_front(lua.pop_value());
             }
-            let ret = ffi::lua_pcall(lua.state, nargs, ffi::LUA_MULTRET, stack_start);
-            if ret != ffi::LUA_OK {
-                return Err(pop_error(lua.state, ret));
+            if appended {
+                // Move the new values after the existing ones
+                results.rotate_left(nresults as usize);
             }
-            let nresults = ffi::lua_gettop(lua.state) - stack_start;
-            let mut results = args; // Reuse MultiValue container
-            assert_stack(lua.state, 2);
+        }
+        Ok(())
+    }
+
+    /// Calls the function like [`call`], also returning the wall-clock time spent in the call.
+    ///
+    /// The measured time includes converting the arguments and results, as well as time spent
+    /// in Rust callbacks invoked by the function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let sum: Function = lua.load("function(a, b) return a + b end").eval()?;
+    /// let (result, elapsed) = sum.call_timed::<_, i64>((1, 2))?;
+    /// println!("sum returned {} in {:?}", result, elapsed);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`call`]: #method.call
+    pub fn call_timed<A: ToLuaMulti<'lua>, R: FromLuaMulti<'lua>>(
+        &self,
+        args: A,
+    ) -> Result<(R, Duration)> {
+        let start = Instant::now();
+        let result = self.call(args)?;
+        Ok((result, start.elapsed()))
+    }
+
+    /// Calls a function following the `ok, err = f()` convention.
+    ///
+    /// If the first returned value is `true`, the second value is converted to `T` and returned
+    /// as `Ok`. Any other truthy first value is itself converted to `T` (as for `io.open`, which
+    /// returns the file). If the first value is `false` or `nil`, the second value (the error) is
+    /// returned unconverted as `Err`.
+    ///
+    /// Errors raised by the function or during conversion are returned as the outer error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let check: Function = lua.load(r#"
+    ///     function(n)
+    ///         if n > 0 then return true, n * 2 end
+    ///         return false, "not positive"
+    ///     end
+    /// "#).eval()?;
+    /// assert_eq!(check.call_ok_err::<_, i64>(2)?, Ok(4));
+    /// match check.call_ok_err::<_, i64>(-1)? {
+    ///     Err(Value::String(msg)) => assert_eq!(msg, "not positive"),
+    ///     r => panic!("unexpected result: {:?}", r),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_ok_err<A, T>(&self, args: A) -> Result<StdResult<T, Value<'lua>>>
+    where
+        A: ToLuaMulti<'lua>,
+        T: FromLua<'lua>,
+    {
+        let lua = self.0.lua;
+        let mut results = self.call::<_, MultiValue>(args)?.into_iter();
+        let first = results.next().unwrap_or(Value::Nil);
+        let second = results.next().unwrap_or(Value::Nil);
+        match first {
+            Value::Nil | Value::Boolean(false) => Ok(Err(second)),
+            Value::Boolean(true) => T::from_lua(second, lua).map(Ok),
+            value => T::from_lua(value, lua).map(Ok),
+        }
+    }
+
+    /// Wraps the function into a Rust closure that retries failed calls.
+    ///
+    /// If a call returns an error for which `predicate` returns `true` (e.g. by matching the error
+    /// message or downcasting an external error), the function is called again with the same
+    /// arguments, up to `attempts` more times. The last error, or an error not matching the
+    /// predicate, is returned as is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let flaky: Function = lua.load(r#"
+    ///     local calls = 0
+    ///     return function(n)
+    ///         calls = calls + 1
+    ///         if calls < 3 then error("busy") end
+    ///         return n * 2
+    ///     end
+    /// "#).eval()?;
+    ///
+    /// let call = flaky.with_retry::<_, i64, _>(2, |err| err.to_string().contains("busy"));
+    /// assert_eq!(call(21)?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_retry<A, R, P>(
+        &self,
+        attempts: usize,
+        predicate: P,
+    ) -> impl Fn(A) -> Result<R> + 'lua
+    where
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+        P: Fn(&Error) -> bool + 'lua,
+    {
+        let func = self.clone();
+        move |args| {
+            let args = args.to_lua_multi(func.0.lua)?;
+            let mut retries = 0;
+            loop {
+                match func.call(args.clone()) {
+                    Err(err) if retries < attempts && predicate(&err) => retries += 1,
+                    res => return res,
+                }
+            }
+        }
+    }
+
+    /// Calls the function, passing the values borrowed from `args` as function arguments.
+    ///
+    /// Unlike [`call`], the arguments are not consumed: references (strings, tables, etc.) are
+    /// pushed to the Lua stack directly from the borrowed values, without cloning them first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let sum: Function = lua.load("function(a, b, c) return a + b + c end").eval()?;
+    ///
+    /// let args = [Value::Integer(1), Value::Integer(2), Value::Integer(3)];
+    /// assert_eq!(sum.call_slice::<i64>(&args)?, 6);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`call`]: #method.call
+    pub fn call_slice<R: FromLuaMulti<'lua>>(&self, args: &[Value<'lua>]) -> Result<R> {
+        let lua = self.0.lua;
+
+        let nargs = args.len() as c_int;
+        let nresults = R::MAX_ARGS.map(|n| n as c_int);
+
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            let nresults = self.call_on_stack(nargs, nresults, || {
+                for arg in args {
+                    lua.push_value_ref(arg)?;
+                }
+                Ok(())
+            })?;
+            if R::MAX_ARGS == Some(1) {
+                // Single value fast path: convert the value directly off the stack
+                return R::from_lua_single(lua.pop_value(), lua);
+            }
+            let mut results = lua.new_or_cached_multivalue();
             for _ in 0..nresults {
                 results.push_front(lua.pop_value());
             }
-            ffi::lua_pop(lua.state, 1);
-            results
-        };
-        R::from_lua_multi(results, lua)
+            R::from_lua_multi(results, lua)
+        }
     }
 
     /// Returns a Feature that, when polled, calls `self`, passing `args` as function arguments,
@@ -250,6 +508,276 @@ impl<'lua> Function<'lua> {
 
         data
     }
+
+    /// Returns the approximate compiled size of the function in bytes.
+    ///
+    /// The size is measured by dumping the function as a stripped binary chunk (see [`dump`]),
+    /// so it does not include debug information.
+    ///
+    /// Returns an error if the function cannot be dumped (eg. it's a Rust or C function).
+    ///
+    /// [`dump`]: #method.dump
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn bytecode_size(&self) -> Result<usize> {
+        match self.dump(true).len() {
+            0 => Err(Error::RuntimeError(
+                "unable to dump function bytecode".to_string(),
+            )),
+            size => Ok(size),
+        }
+    }
+
+    /// Checks whether two functions were compiled from the same source code.
+    ///
+    /// Unlike `==`, which compares function identity, this compares the stripped bytecode of
+    /// both functions (see [`dump`]), so different instances of the same source are equal.
+    /// Upvalue values are not taken into account.
+    ///
+    /// Returns an error if any of the functions cannot be dumped (eg. it's a Rust or C function).
+    ///
+    /// [`dump`]: #method.dump
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn same_source(&self, other: &Function) -> Result<bool> {
+        let (this, other) = (self.dump(true), other.dump(true));
+        if this.is_empty() || other.is_empty() {
+            return Err(Error::RuntimeError(
+                "unable to dump function bytecode".to_string(),
+            ));
+        }
+        Ok(this == other)
+    }
+
+    /// Returns the declared parameters of the function.
+    ///
+    /// The number of parameters and whether the function is vararg are always available for Lua
+    /// functions, parameter names require debug information (which is absent in stripped
+    /// binary chunks). For Rust and C functions the arity is unknown.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let func: Function = lua.load("function(a, b, ...) end").eval()?;
+    /// let params = func.params()?;
+    /// assert_eq!(params.arity, Some(2));
+    /// assert!(params.is_vararg);
+    /// assert_eq!(params.names, vec!["a", "b"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Requires `feature = "lua54/lua53/lua52"`
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    pub fn params(&self) -> Result<FunctionParams> {
+        use std::ffi::CStr;
+        use std::mem;
+
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, 2)?;
+
+            lua.push_ref(&self.0);
+            ffi::lua_pushvalue(lua.state, -1);
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            if ffi::lua_getinfo(lua.state, cstr!(">Su"), &mut ar) == 0 {
+                return Err(Error::RuntimeError("lua_getinfo failed".to_string()));
+            }
+
+            if !ar.what.is_null() && CStr::from_ptr(ar.what).to_bytes() == b"C" {
+                return Ok(FunctionParams {
+                    arity: None,
+                    is_vararg: true,
+                    names: Vec::new(),
+                });
+            }
+
+            // With a NULL activation record `lua_getlocal` returns parameter names of the
+            // function on top of the stack
+            let mut names = Vec::with_capacity(ar.nparams as usize);
+            for n in 1..=ar.nparams as c_int {
+                let name = ffi::lua_getlocal(lua.state, ptr::null(), n);
+                if name.is_null() {
+                    names.clear();
+                    break;
+                }
+                names.push(CStr::from_ptr(name).to_string_lossy().into_owned());
+            }
+
+            Ok(FunctionParams {
+                arity: Some(ar.nparams as usize),
+                is_vararg: ar.isvararg != 0,
+                names,
+            })
+        }
+    }
+}
+
+#[cfg(all(feature = "serialize", not(feature = "luau")))]
+impl<'lua> Function<'lua> {
+    /// Deserializes a function serialized with its [`Serialize`] implementation, loading the
+    /// bytecode into the given Lua state.
+    ///
+    /// Upvalues of the original function are not restored (apart from `_ENV`, which is set to
+    /// the globals table).
+    ///
+    /// Requires `feature = "serialize"`
+    ///
+    /// [`Serialize`]: serde::Serialize
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "serialize", not(feature = "luau")))))]
+    pub fn deserialize<'de, D>(lua: &'lua Lua, deserializer: D) -> StdResult<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BytecodeVisitor;
+
+        impl<'de> Visitor<'de> for BytecodeVisitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("function bytecode")
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> StdResult<Self::Value, E> {
+                Ok(v.to_vec())
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> StdResult<Self::Value, E> {
+                Ok(v)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> StdResult<Self::Value, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(bytes)
+            }
+        }
+
+        let bytecode = deserializer.deserialize_byte_buf(BytecodeVisitor)?;
+        lua.load(&bytecode)
+            .set_mode(ChunkMode::Binary)
+            .into_function()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Serializes a Lua function as its stripped bytecode (see [`Function::dump`]).
+///
+/// Rust and C functions cannot be serialized. Use [`Function::deserialize`] to load the
+/// function back.
+///
+/// Requires `feature = "serialize"`
+#[cfg(all(feature = "serialize", not(feature = "luau")))]
+impl<'lua> Serialize for Function<'lua> {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let bytecode = self.dump(true);
+        if bytecode.is_empty() {
+            return Err(ser::Error::custom(
+                "cannot serialize <function>: unable to dump function bytecode",
+            ));
+        }
+        serializer.serialize_bytes(&bytecode)
+    }
+}
+
+impl<'lua> Function<'lua> {
+    // Pushes the function and its `nargs` arguments (using `push_args`) and calls it in protected
+    // mode, requesting `nresults` results (or all of them if `None`).
+    // On success returns the number of results, which are left on top of the stack.
+    // Must be called inside a `StackGuard`.
+    unsafe fn call_on_stack(
+        &self,
+        nargs: c_int,
+        nresults: Option<c_int>,
+        push_args: impl FnOnce() -> Result<()>,
+    ) -> Result<c_int> {
+        let lua = self.0.lua;
+        check_stack(lua.state, nargs.max(nresults.unwrap_or(0)) + 3)?;
+
+        ffi::lua_pushcfunction(lua.state, error_traceback);
+        let stack_start = ffi::lua_gettop(lua.state);
+        lua.push_ref(&self.0);
+        push_args()?;
+        let _cg = lua.enter_call();
+        let ret = self.pcall(nargs, nresults.unwrap_or(ffi::LUA_MULTRET), stack_start);
+        if ret != ffi::LUA_OK {
+            let err = lua.resource_limit_error(pop_error(lua.state, ret));
+            return Err(lua.remap_error_lines(err));
+        }
+        let nresults = ffi::lua_gettop(lua.state) - stack_start;
+        if let Some(limit) = lua.max_results() {
+            if nresults as usize > limit {
+                return Err(Error::TooManyResults {
+                    count: nresults as usize,
+                    limit,
+                });
+            }
+        }
+        assert_stack(lua.state, 2);
+        Ok(nresults)
+    }
+
+    // Calls the function (with arguments) on top of the stack, reporting slow calls
+    unsafe fn pcall(&self, nargs: c_int, nresults: c_int, msgh: c_int) -> c_int {
+        let lua = self.0.lua;
+        let threshold = match lua.slow_call_threshold() {
+            Some(threshold) => threshold,
+            None => return ffi::lua_pcall(lua.state, nargs, nresults, msgh),
+        };
+
+        let started = Instant::now();
+        let ret = ffi::lua_pcall(lua.state, nargs, nresults, msgh);
+        let elapsed = started.elapsed();
+        if elapsed > threshold {
+            lua.report_slow_call(&self.source_name(), elapsed);
+        }
+        ret
+    }
+
+    // Returns the function source location, eg. `[string "script"]:10` or `[C]`
+    fn source_name(&self) -> StdString {
+        match self.source_info() {
+            Some((short_src, linedefined)) if linedefined > 0 => {
+                format!("{}:{}", short_src, linedefined)
+            }
+            Some((short_src, _)) => short_src,
+            None => "?".to_string(),
+        }
+    }
+
+    // Returns the `short_src` and `linedefined` debug fields of the function
+    pub(crate) fn source_info(&self) -> Option<(StdString, c_int)> {
+        use std::ffi::CStr;
+        use std::mem;
+
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 1);
+
+            lua.push_ref(&self.0);
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            #[cfg(not(feature = "luau"))]
+            let ok = ffi::lua_getinfo(lua.state, cstr!(">S"), &mut ar) != 0;
+            #[cfg(feature = "luau")]
+            let ok = ffi::lua_getinfo(lua.state, -1, cstr!("s"), &mut ar) != 0;
+            if !ok {
+                return None;
+            }
+
+            let short_src = CStr::from_ptr(ar.short_src.as_ptr()).to_string_lossy();
+            Some((short_src.into_owned(), ar.linedefined as c_int))
+        }
+    }
 }
 
 impl<'lua> PartialEq for Function<'lua> {
@@ -110,25 +110,35 @@ pub use crate::chunk::{AsChunk, Chunk, ChunkMode};
 pub use crate::error::{Error, ExternalError, ExternalResult, Result};
 pub use crate::function::Function;
 pub use crate::hook::{Debug, DebugEvent, DebugNames, DebugSource, DebugStack};
-pub use crate::lua::{GCMode, Lua, LuaOptions};
+pub use crate::lua::{AllocAction, GCMode, GcPhase, Lua, LuaOptions, StringCoercion};
 pub use crate::multi::Variadic;
 pub use crate::scope::Scope;
 pub use crate::stdlib::StdLib;
 pub use crate::string::String;
-pub use crate::table::{Table, TableExt, TablePairs, TableSequence};
+pub use crate::table::{NullableTable, Table, TableExt, TablePairs, TableSequence};
 pub use crate::thread::{Thread, ThreadStatus};
-pub use crate::types::{Integer, LightUserData, Number, RegistryKey};
+pub use crate::types::{Integer, LightUserData, Number, RegistryKey, RegistryRef};
 pub use crate::userdata::{
-    AnyUserData, MetaMethod, UserData, UserDataFields, UserDataMetatable, UserDataMethods,
+    AnyUserData, MetaMethod, TypedMetaHandler, UserData, UserDataFields, UserDataMetatable,
+    UserDataMethods,
+};
+pub use crate::value::{
+    FromLua, FromLuaMulti, Key, MultiValue, Nil, OwnedValue, ToLua, ToLuaMulti, Type, Value,
 };
-pub use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti, Value};
 
 #[cfg(not(feature = "luau"))]
 pub use crate::hook::HookTriggers;
 
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+pub use crate::function::FunctionParams;
+
 #[cfg(any(feature = "luau", doc))]
 #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
-pub use crate::{chunk::Compiler, types::VmState};
+pub use crate::chunk::Compiler;
+
+#[cfg(any(feature = "luau", feature = "lua54", doc))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "luau", feature = "lua54"))))]
+pub use crate::types::VmState;
 
 #[cfg(feature = "async")]
 pub use crate::thread::AsyncThread;
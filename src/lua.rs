@@ -8,7 +8,10 @@ use std::mem::ManuallyDrop;
 use std::ops::{Deref, DerefMut};
 use std::os::raw::{c_char, c_int, c_void};
 use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe, Location};
+use std::path::Path;
+use std::string::String as StdString;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use std::{mem, ptr, str};
 
 use rustc_hash::FxHashMap;
@@ -21,13 +24,14 @@ use crate::hook::Debug;
 use crate::scope::Scope;
 use crate::stdlib::StdLib;
 use crate::string::String;
-use crate::table::Table;
-use crate::thread::Thread;
+use crate::table::{Table, ORDERED_TABLE_KEYS};
+use crate::thread::{Thread, ThreadStatus};
 use crate::types::{
-    Callback, CallbackUpvalue, DestructedUserdataMT, Integer, LightUserData, LuaRef, MaybeSend,
-    Number, RegistryKey,
+    AllocFailureCallback, Callback, CallbackUpvalue, DestructedUserdataMT, GcObserverCallback,
+    GlobalResolverCallback, Integer, LightUserData, LuaRef, MaybeSend, Number, RegistryKey,
+    RegistryRef, SlowCallCallback, SourceMapCallback, SourceTransformCallback, TableCreateCallback,
 };
-use crate::userdata::{AnyUserData, UserData, UserDataCell};
+use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataCell, UserDataMethods};
 use crate::userdata_impl::{StaticUserDataFields, StaticUserDataMethods};
 use crate::util::{
     self, assert_stack, callback_error, check_stack, get_destructed_userdata_metatable,
@@ -35,7 +39,9 @@ use crate::util::{
     init_gc_metatable, init_userdata_metatable, pop_error, push_gc_userdata, push_string,
     push_table, rawset_field, safe_pcall, safe_xpcall, StackGuard, WrappedFailure,
 };
-use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti, Value};
+use crate::value::{
+    FromLua, FromLuaMulti, MultiValue, Nil, OwnedValue, ToLua, ToLuaMulti, Type, Value,
+};
 
 #[cfg(not(feature = "lua54"))]
 use crate::util::push_userdata;
@@ -45,10 +51,15 @@ use crate::{types::WarnCallback, userdata::USER_VALUE_MAXSLOT, util::push_userda
 #[cfg(not(feature = "luau"))]
 use crate::{hook::HookTriggers, types::HookCallback};
 
+#[cfg(any(feature = "luau", doc))]
+use crate::chunk::Compiler;
 #[cfg(feature = "luau")]
 use crate::types::InterruptCallback;
-#[cfg(any(feature = "luau", doc))]
-use crate::{chunk::Compiler, types::VmState};
+
+#[cfg(feature = "trace")]
+use crate::types::ApiTraceCallback;
+#[cfg(any(feature = "luau", feature = "lua54", doc))]
+use crate::types::VmState;
 
 #[cfg(feature = "async")]
 use {
@@ -62,9 +73,13 @@ use {
 };
 
 #[cfg(feature = "serialize")]
-use serde::Serialize;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Top level Lua struct which represents an instance of Lua VM.
+///
+/// Dropping the last reference to the Lua instance closes the state, which runs `__gc`
+/// finalizers of all remaining userdata, so their `Drop` impls are executed. Panics raised by
+/// `Drop` impls during finalization cannot propagate through Lua and are silently discarded.
 #[repr(transparent)]
 pub struct Lua(Arc<UnsafeCell<LuaInner>>);
 
@@ -106,6 +121,11 @@ pub(crate) struct ExtraData {
     wrapped_failures_cache: Vec<c_int>,
     // Cache of recycled `MultiValue` containers
     multivalue_cache: Vec<MultiValue<'static>>,
+    // Cache of small strings on the ref thread (enabled by `Lua::enable_string_cache`)
+    string_cache: Option<FxHashMap<Box<[u8]>, c_int>>,
+    // Source maps by id, the ids of live chunk functions are stored in a weak-keyed table
+    source_maps: FxHashMap<Integer, (StdString, SourceMapCallback)>,
+    last_source_map_id: Integer,
     // Cache of recycled `Thread`s (coroutines)
     #[cfg(feature = "async")]
     recycled_thread_cache: Vec<c_int>,
@@ -117,7 +137,30 @@ pub(crate) struct ExtraData {
     #[cfg(not(feature = "luau"))]
     hook_callback: Option<HookCallback>,
     #[cfg(feature = "lua54")]
+    hook_yield: bool,
+    #[cfg(feature = "lua54")]
     warn_callback: Option<WarnCallback>,
+    #[cfg(feature = "lua54")]
+    warn_unused_args: bool,
+    max_results: Option<usize>,
+    last_panic_location: Option<StdString>,
+    string_coercion: StringCoercion,
+    max_string_len: usize,
+    table_create_callback: Option<TableCreateCallback>,
+    slow_call: Option<(Duration, SlowCallCallback)>,
+    // `None` until the first global module is added
+    global_modules: Option<Vec<(StdString, GlobalResolverCallback)>>,
+    gc_observer: Option<GcObserverCallback>,
+    source_transform: Option<SourceTransformCallback>,
+    // Registry ids allocated during `Lua::transaction` (`LUA_NOREF` marks released ones)
+    transaction_registry_ids: Option<Vec<c_int>>,
+    // Register threads created by `Lua::create_thread` (enabled by `Lua::enable_thread_tracking`)
+    track_threads: bool,
+    // End of the last observed GC cycle, `None` if no GC sentinel is armed
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    gc_cycle_end: Option<Instant>,
+    #[cfg(feature = "trace")]
+    api_trace: Option<ApiTraceCallback>,
     #[cfg(feature = "luau")]
     interrupt_callback: Option<InterruptCallback>,
 
@@ -129,6 +172,23 @@ pub(crate) struct ExtraData {
 struct MemoryInfo {
     used_memory: isize,
     memory_limit: isize,
+    // Number of allocations made during the current (outermost) call into Lua
+    allocations: usize,
+    allocation_limit: usize,
+    call_depth: usize,
+    max_string_len: usize,
+    alloc_failure_handler: Option<AllocFailureCallback>,
+}
+
+// Tracks nested calls into Lua to reset the allocation counter on the outermost one.
+pub(crate) struct CallGuard(Option<ptr::NonNull<MemoryInfo>>);
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        if let Some(mut mem_info) = self.0 {
+            unsafe { mem_info.as_mut().call_depth -= 1 };
+        }
+    }
 }
 
 /// Mode of the Lua garbage collector (GC).
@@ -148,6 +208,50 @@ pub enum GCMode {
     Generational,
 }
 
+/// Garbage collector (GC) activity reported to an observer.
+///
+/// Set an observer with [`Lua::set_gc_observer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GcPhase {
+    /// A step performed with [`Lua::gc_step`] or [`Lua::gc_step_kbytes`].
+    Step,
+    /// A full collection performed with [`Lua::gc_collect`].
+    Collect,
+    /// A collection cycle has finished, either automatically or by an explicit request.
+    ///
+    /// Requires `feature = "lua54/lua53/lua52"`
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(feature = "lua54", feature = "lua53", feature = "lua52")))
+    )]
+    CycleEnd,
+}
+
+/// Policy used when converting Lua values to Rust strings.
+///
+/// Set with [`Lua::set_string_coercion`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StringCoercion {
+    /// Only Lua strings are accepted.
+    ///
+    /// This is the default.
+    Strict,
+    /// Lua strings and numbers are accepted, numbers are converted like Lua's `tostring` does.
+    Coercive,
+}
+
+/// Action taken when a memory allocation inside Lua fails.
+///
+/// Returned by the handler set with [`Lua::on_alloc_failure`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocAction {
+    /// Abort the process, like Rust does on allocation failure.
+    Abort,
+    /// Fail the allocation, raising `Error::MemoryError` in Lua.
+    ReturnError,
+}
+
 /// Controls Lua interpreter behavior such as Rust panics handling.
 #[derive(Clone, Debug)]
 #[non_exhaustive]
@@ -160,10 +264,15 @@ pub struct LuaOptions {
     /// If enabled, keeps [`pcall`]/[`xpcall`] unmodified.
     /// Panics are still automatically resumed if returned to the Rust side.
     ///
+    /// While a resumed panic unwinds through mlua, the Lua stack is restored to the state it had
+    /// before the call, so the same `Lua` instance remains usable after the panic is caught
+    /// with [`catch_unwind`].
+    ///
     /// Default: **true**
     ///
     /// [`pcall`]: https://www.lua.org/manual/5.4/manual.html#pdf-pcall
     /// [`xpcall`]: https://www.lua.org/manual/5.4/manual.html#pdf-xpcall
+    /// [`catch_unwind`]: std::panic::catch_unwind
     pub catch_rust_panics: bool,
 
     /// Max size of thread (coroutine) object cache used to execute asynchronous functions.
@@ -219,8 +328,18 @@ impl LuaOptions {
 #[cfg(feature = "async")]
 pub(crate) static ASYNC_POLL_PENDING: u8 = 0;
 pub(crate) static EXTRA_REGISTRY_KEY: u8 = 0;
+static USERDATA_TAGS_KEY: u8 = 0;
+static ACTIVE_THREADS_KEY: u8 = 0;
+static ASSOCIATED_DATA_KEY: u8 = 0;
+static SOURCE_MAPS_KEY: u8 = 0;
+// Occupies registry slots of keys released by a failed `Lua::transaction`
+static ROLLED_BACK_VALUE: u8 = 0;
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+static USERDATA_FINALIZERS_KEY: u8 = 0;
 
 const WRAPPED_FAILURES_CACHE_SIZE: usize = 32;
+const STRING_CACHE_SIZE: usize = 128;
+const STRING_CACHE_MAX_LEN: usize = 64;
 const MULTIVALUE_CACHE_SIZE: usize = 32;
 
 /// Requires `feature = "send"`
@@ -241,6 +360,11 @@ impl Drop for LuaInner {
                 ffi::lua_replace(extra.ref_thread, index);
                 extra.ref_free.push(index);
             }
+            for (_, index) in extra.string_cache.take().into_iter().flatten() {
+                ffi::lua_pushnil(extra.ref_thread);
+                ffi::lua_replace(extra.ref_thread, index);
+                extra.ref_free.push(index);
+            }
             #[cfg(feature = "async")]
             {
                 // Destroy Waker slot
@@ -377,6 +501,49 @@ impl Lua {
         Self::inner_new(libs, options)
     }
 
+    /// Creates a new independent Lua state with a copy of the primitive globals of this one.
+    ///
+    /// The new state loads the same standard libraries (in the same safe or unsafe mode), and
+    /// every global whose key and value can be detached (nil, booleans, numbers, strings and
+    /// plain tables of them, see [`OwnedValue::from_table`]) is copied into it. Reference types such
+    /// as functions, userdata, threads and tables containing them are **not** copied, nor are
+    /// other settings like hooks or limits. The two states share nothing afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.globals().set("config", "production")?;
+    ///
+    /// let child = lua.fork()?;
+    /// assert_eq!(child.globals().get::<_, String>("config")?, "production");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`OwnedValue::from_table`]: crate::OwnedValue::from_table
+    pub fn fork(&self) -> Result<Lua> {
+        let libs = unsafe { (*self.extra.get()).libs };
+        let lua = if self.safe {
+            Lua::new_with(libs, LuaOptions::default())?
+        } else {
+            unsafe { Lua::unsafe_new_with(libs, LuaOptions::default()) }
+        };
+
+        let globals = lua.globals();
+        for pair in self.globals().pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            if let (Ok(key), Ok(value)) = (OwnedValue::detach(key), OwnedValue::detach(value)) {
+                globals.raw_set(lua.transfer_value(&key)?, lua.transfer_value(&value)?)?;
+            }
+        }
+        drop(globals);
+
+        Ok(lua)
+    }
+
     unsafe fn inner_new(libs: StdLib, options: LuaOptions) -> Lua {
         #[cfg_attr(
             any(feature = "lua51", feature = "luajit", feature = "luau"),
@@ -392,6 +559,18 @@ impl Lua {
 
             let mem_info = &mut *(extra_data as *mut MemoryInfo);
 
+            // Decides whether a failed allocation can be reported back to Lua
+            let alloc_failed = |mem_info: &MemoryInfo, layout: alloc::Layout| {
+                if let Some(handler) = mem_info.alloc_failure_handler.as_ref() {
+                    // Unwinding through the allocator is not allowed
+                    let action = catch_unwind(AssertUnwindSafe(handler));
+                    if action.unwrap_or(AllocAction::Abort) == AllocAction::Abort {
+                        alloc::handle_alloc_error(layout);
+                    }
+                }
+                ptr::null_mut()
+            };
+
             if nsize == 0 {
                 // Free memory
                 if !ptr.is_null() {
@@ -409,17 +588,39 @@ impl Lua {
                 mem_diff -= osize as isize;
             }
             let new_used_memory = mem_info.used_memory + mem_diff;
+            let new_layout = alloc::Layout::from_size_align_unchecked(nsize, ffi::SYS_MIN_ALIGN);
             if mem_info.memory_limit > 0 && new_used_memory > mem_info.memory_limit {
-                return ptr::null_mut();
+                return alloc_failed(mem_info, new_layout);
             }
 
-            let new_layout = alloc::Layout::from_size_align_unchecked(nsize, ffi::SYS_MIN_ALIGN);
-
             if ptr.is_null() {
+                // Are we fit to the string length limit? (`osize` encodes the object type)
+                // Strings are allocated together with their header, the slack accounts for it.
+                const STRING_HEADER_SLACK: usize = 64;
+                if mem_info.max_string_len > 0
+                    && osize == ffi::LUA_TSTRING as usize
+                    && nsize > mem_info.max_string_len + STRING_HEADER_SLACK
+                {
+                    return alloc_failed(mem_info, new_layout);
+                }
+
+                // Are we fit to the allocations limit? (counted only inside calls)
+                let count_allocation = mem_info.call_depth > 0;
+                if count_allocation
+                    && mem_info.allocation_limit > 0
+                    && mem_info.allocations >= mem_info.allocation_limit
+                {
+                    return alloc_failed(mem_info, new_layout);
+                }
+
                 // Allocate new memory
                 let new_ptr = alloc::alloc(new_layout) as *mut c_void;
-                if !new_ptr.is_null() {
-                    mem_info.used_memory += mem_diff;
+                if new_ptr.is_null() {
+                    return alloc_failed(mem_info, new_layout);
+                }
+                mem_info.used_memory += mem_diff;
+                if count_allocation {
+                    mem_info.allocations += 1;
                 }
                 return new_ptr;
             }
@@ -433,6 +634,8 @@ impl Lua {
             } else if !ptr.is_null() && nsize < osize {
                 // Should not happen
                 alloc::handle_alloc_error(new_layout);
+            } else {
+                return alloc_failed(mem_info, new_layout);
             }
 
             new_ptr
@@ -442,6 +645,11 @@ impl Lua {
         let mem_info = Box::into_raw(Box::new(MemoryInfo {
             used_memory: 0,
             memory_limit: 0,
+            allocations: 0,
+            allocation_limit: 0,
+            call_depth: 0,
+            max_string_len: 0,
+            alloc_failure_handler: None,
         }));
 
         #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
@@ -499,6 +707,90 @@ impl Lua {
         lua
     }
 
+    /// Returns the raw pointer to the underlying Lua state.
+    ///
+    /// This is an escape hatch for interoperability with C libraries and Lua C API functions
+    /// that are not covered by the high-level API.
+    ///
+    /// # Safety
+    ///
+    /// The caller must leave the Lua stack balanced, ie. pop every value it pushes (and not pop
+    /// anything it did not push), before calling any other `Lua` method. mlua stores internal
+    /// state on the stack and in the registry, which must not be modified. Stack space is not
+    /// reserved, so `lua_checkstack` must be used before pushing values. Lua errors raised by
+    /// C API calls (eg. memory errors) must not escape to Rust code, so use protected calls
+    /// where needed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::Lua;
+    /// let lua = Lua::new();
+    /// let state = unsafe { lua.state() };
+    /// assert!(!state.is_null());
+    /// ```
+    pub unsafe fn state(&self) -> *mut ffi::lua_State {
+        self.state
+    }
+
+    /// Runs `f` checking that it changes the size of the Lua stack by exactly `change` values.
+    ///
+    /// This exposes the stack guard used internally by mlua to code manipulating the stack of
+    /// [`Lua::state`] directly. If `f` succeeds, any values it pushed beyond `change` are removed
+    /// (keeping the topmost `change` ones) and if it popped more values than allowed, this
+    /// function panics. If `f` fails, the stack is restored to its previous size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `f` returns `Ok` and the stack has less than `change` more values than before.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.with_stack_guard(0, || {
+    ///     // Values pushed here with the C API are popped on return
+    ///     Ok(())
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Lua::state`]: #method.state
+    pub fn with_stack_guard<R, F>(&self, change: c_int, f: F) -> Result<R>
+    where
+        F: FnOnce() -> Result<R>,
+    {
+        unsafe {
+            let top = ffi::lua_gettop(self.state);
+            let guard = StackGuard::new_extra(self.state, change);
+            match f() {
+                Ok(r) => {
+                    let new_top = ffi::lua_gettop(self.state);
+                    if new_top < top + change {
+                        // Don't let the guard panic again
+                        mem::forget(guard);
+                        panic!(
+                            "stack guard: {} too many stack values popped",
+                            top + change - new_top
+                        );
+                    }
+                    drop(guard);
+                    Ok(r)
+                }
+                Err(err) => {
+                    mem::forget(guard);
+                    if ffi::lua_gettop(self.state) > top {
+                        ffi::lua_settop(self.state, top);
+                    }
+                    Err(err)
+                }
+            }
+        }
+    }
+
     /// Constructs a new Lua instance from an existing raw state.
     ///
     /// Once called, a returned Lua state is cached in the registry and can be retrieved
@@ -577,7 +869,10 @@ impl Lua {
             ref_stack_top,
             ref_free: Vec::new(),
             wrapped_failures_cache: Vec::with_capacity(WRAPPED_FAILURES_CACHE_SIZE),
+            string_cache: None,
             multivalue_cache: Vec::with_capacity(MULTIVALUE_CACHE_SIZE),
+            source_maps: FxHashMap::default(),
+            last_source_map_id: 0,
             #[cfg(feature = "async")]
             recycled_thread_cache: Vec::new(),
             #[cfg(feature = "async")]
@@ -585,7 +880,26 @@ impl Lua {
             #[cfg(not(feature = "luau"))]
             hook_callback: None,
             #[cfg(feature = "lua54")]
+            hook_yield: false,
+            #[cfg(feature = "lua54")]
             warn_callback: None,
+            #[cfg(feature = "lua54")]
+            warn_unused_args: false,
+            max_results: None,
+            last_panic_location: None,
+            string_coercion: StringCoercion::Strict,
+            max_string_len: 0,
+            table_create_callback: None,
+            slow_call: None,
+            global_modules: None,
+            gc_observer: None,
+            source_transform: None,
+            transaction_registry_ids: None,
+            track_threads: false,
+            #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+            gc_cycle_end: None,
+            #[cfg(feature = "trace")]
+            api_trace: None,
             #[cfg(feature = "luau")]
             interrupt_callback: None,
             #[cfg(feature = "luau")]
@@ -941,7 +1255,17 @@ impl Lua {
                     return Ok(()); // Don't allow recursion
                 }
                 hook_cb(&lua, debug)
-            })
+            });
+
+            // Yield requested by a hook set with `set_yield_hook`.
+            // Only count and line events can yield, and only if the running thread is yieldable.
+            #[cfg(feature = "lua54")]
+            if std::mem::take(&mut (*extra).hook_yield)
+                && ((*ar).event == ffi::LUA_HOOKCOUNT || (*ar).event == ffi::LUA_HOOKLINE)
+                && ffi::lua_isyieldable(state) != 0
+            {
+                ffi::lua_yield(state, 0);
+            }
         }
 
         unsafe {
@@ -952,6 +1276,52 @@ impl Lua {
         Ok(())
     }
 
+    /// Sets a 'hook' function that can suspend the running coroutine instead of erroring.
+    ///
+    /// Works the same way as [`Lua::set_hook`], but the hook function returns a [`VmState`].
+    /// Returning [`VmState::Yield`] yields the running coroutine (with no values), returning
+    /// control back to the resumer. Resuming the coroutine continues the execution from where it
+    /// was suspended. This can be used to implement preemptive scheduling of Lua scripts.
+    ///
+    /// Only line and count events (see [`HookTriggers`]) can yield, and only when the Lua code
+    /// is running inside a coroutine. In other cases [`VmState::Yield`] is ignored.
+    ///
+    /// Requires `feature = "lua54"`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use mlua::{HookTriggers, Lua, Result, ThreadStatus, VmState};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.set_yield_hook(HookTriggers::every_nth_instruction(1000), |_lua, _debug| {
+    ///     Ok(VmState::Yield)
+    /// })?;
+    ///
+    /// let co = lua.create_thread(lua.load("while true do end").into_function()?)?;
+    /// for _ in 0..10 {
+    ///     co.resume::<_, ()>(())?;
+    ///     assert_eq!(co.status(), ThreadStatus::Resumable);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`HookTriggers`]: crate::HookTriggers
+    #[cfg(feature = "lua54")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "lua54")))]
+    pub fn set_yield_hook<F>(&self, triggers: HookTriggers, callback: F) -> Result<()>
+    where
+        F: 'static + MaybeSend + Fn(&Lua, Debug) -> Result<VmState>,
+    {
+        self.set_hook(triggers, move |lua, debug| {
+            if let VmState::Yield = callback(lua, debug)? {
+                unsafe { (*lua.extra.get()).hook_yield = true };
+            }
+            Ok(())
+        })
+    }
+
     /// Removes any hook previously set by `set_hook`.
     ///
     /// This function has no effect if a hook was not previously set.
@@ -1121,6 +1491,44 @@ impl Lua {
         Ok(())
     }
 
+    /// Enables or disables warnings about unused arguments passed to Rust functions.
+    ///
+    /// When enabled, calling a function created with [`create_function`] or
+    /// [`create_function_mut`] with more arguments than the function can use emits a warning
+    /// through the [warning function]. The call itself proceeds as usual, with the excess
+    /// arguments ignored.
+    ///
+    /// This is intended to help catch API misuse during development. Default: disabled.
+    ///
+    /// Requires `feature = "lua54"`
+    ///
+    /// [`create_function`]: #method.create_function
+    /// [`create_function_mut`]: #method.create_function_mut
+    /// [warning function]: #method.set_warning_function
+    #[cfg(feature = "lua54")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "lua54")))]
+    pub fn set_unused_args_warning(&self, enabled: bool) {
+        unsafe { (*self.extra.get()).warn_unused_args = enabled };
+    }
+
+    // Emits a warning if a Rust function was called with more arguments than it can use.
+    #[cfg(feature = "lua54")]
+    fn check_unused_args(&self, nargs: usize, max_args: Option<usize>) -> Result<()> {
+        if !unsafe { (*self.extra.get()).warn_unused_args } {
+            return Ok(());
+        }
+        match max_args {
+            Some(max_args) if nargs > max_args => self.warning(
+                format!(
+                    "function called with {} arguments, but only {} are used",
+                    nargs, max_args
+                ),
+                false,
+            ),
+            _ => Ok(()),
+        }
+    }
+
     /// Gets information about the interpreter runtime stack.
     ///
     /// This function returns [`Debug`] structure that can be used to get information about the function
@@ -1144,6 +1552,66 @@ impl Lua {
         }
     }
 
+    /// Creates a runtime error with the position information at the given `level` prepended to
+    /// the message.
+    ///
+    /// This is analogous to the Lua `error(msg, level)` function, if the current Rust callback is
+    /// considered the function raising the error. Level `1` points to the callback itself (which has
+    /// no position information), level `2` points to the function that called the callback, and so
+    /// on. Level `0` avoids the addition of position information.
+    ///
+    /// The position is resolved when the error is returned from a Rust callback, so the returned
+    /// error must be propagated out of the callback as is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let check = lua.create_function(|lua, n: i64| {
+    ///     if n < 0 {
+    ///         return Err(lua.error_at_level("expected positive number", 2));
+    ///     }
+    ///     Ok(n)
+    /// })?;
+    /// lua.globals().set("check", check)?;
+    ///
+    /// let err = lua.load("check(-1)").set_name("=example")?.exec().unwrap_err();
+    /// assert!(err.to_string().contains("example:1: expected positive number"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn error_at_level(&self, msg: &str, level: i32) -> Error {
+        Error::WithLevel(Box::new(Error::RuntimeError(msg.to_string())), level)
+    }
+
+    /// Prepares internal caches so that the first real call into Lua is not slower than the
+    /// subsequent ones.
+    ///
+    /// Several internal structures are set up lazily on first use: preallocated error
+    /// containers for Rust callbacks, recycled argument containers, and the Lua parser state.
+    /// This method populates them upfront by running a trivial chunk and a Rust callback, moving
+    /// the cost to the initialization phase of latency-sensitive applications.
+    pub fn warmup(&self) -> Result<()> {
+        let _ = self.globals();
+
+        // Preallocated containers for arguments and results
+        for _ in self.multivalue_cache_len()..MULTIVALUE_CACHE_SIZE {
+            self.cache_multivalue(MultiValue::from_vec(Vec::with_capacity(8)));
+        }
+
+        // Runs the parser and a Rust callback, which leaves a preallocated `WrappedFailure` in
+        // the cache
+        let callback = self.create_function(|_, args: MultiValue| Ok(args))?;
+        let warmup = self
+            .load_internal("local f = ... return f(1, 'x')")
+            .into_function()?;
+        warmup.call::<_, ()>(callback)?;
+
+        Ok(())
+    }
+
     /// Returns the amount of memory (in bytes) currently used inside this Lua state.
     pub fn used_memory(&self) -> usize {
         unsafe {
@@ -1182,72 +1650,271 @@ impl Lua {
         }
     }
 
-    /// Returns true if the garbage collector is currently running automatically.
+    /// Sets a limit (in bytes) on the length of strings created in this Lua state.
     ///
-    /// Requires `feature = "lua54/lua53/lua52/luau"`
-    #[cfg(any(
-        feature = "lua54",
-        feature = "lua53",
-        feature = "lua52",
-        feature = "luau"
-    ))]
-    pub fn gc_is_running(&self) -> bool {
-        unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCISRUNNING, 0) != 0 }
-    }
-
-    /// Stop the Lua GC from running
-    pub fn gc_stop(&self) {
-        unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCSTOP, 0) };
-    }
-
-    /// Restarts the Lua GC if it is not running
-    pub fn gc_restart(&self) {
-        unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCRESTART, 0) };
-    }
-
-    /// Perform a full garbage-collection cycle.
+    /// Creating a longer string from Rust with [`create_string`] (or any conversion to a Lua
+    /// string) fails with `Error::MemoryError`. On Lua 5.4/5.3/5.2 the limit is also enforced by
+    /// the allocator for strings created by Lua code (eg. with `string.rep` or concatenation),
+    /// which raise a memory error. The allocator check is approximate and may let strings
+    /// exceed the limit by a few bytes, as it also accounts for the string header.
     ///
-    /// It may be necessary to call this function twice to collect all currently unreachable
-    /// objects. Once to finish the current gc cycle, and once to start and finish the next cycle.
-    pub fn gc_collect(&self) -> Result<()> {
+    /// Zero means no limit (the default).
+    ///
+    /// [`create_string`]: #method.create_string
+    pub fn set_max_string_len(&self, max_len: usize) {
         unsafe {
-            check_stack(self.main_state, 2)?;
-            protect_lua!(self.main_state, 0, 0, fn(state) ffi::lua_gc(state, ffi::LUA_GCCOLLECT, 0))
+            let extra = &mut *self.extra.get();
+            extra.max_string_len = max_len;
+            if let Some(mut mem_info) = extra.mem_info {
+                mem_info.as_mut().max_string_len = max_len;
+            }
         }
     }
 
-    /// Steps the garbage collector one indivisible step.
+    /// Sets a limit on the number of allocations (strings, tables, etc.) made during a single call
+    /// into Lua.
     ///
-    /// Returns true if this has finished a collection cycle.
-    pub fn gc_step(&self) -> Result<bool> {
-        self.gc_step_kbytes(0)
-    }
-
-    /// Steps the garbage collector as though memory had been allocated.
+    /// Only allocations made while Lua code is running are counted. The counter is reset when
+    /// entering the outermost [`Function::call`], so nested calls made from Rust callbacks share
+    /// the limit of the enclosing call. Once the limit is reached, any further allocation fails
+    /// with `Error::ResourceLimit`.
+    /// Returns previous limit (zero means no limit).
     ///
-    /// if `kbytes` is 0, then this is the same as calling `gc_step`. Returns true if this step has
-    /// finished a collection cycle.
-    pub fn gc_step_kbytes(&self, kbytes: c_int) -> Result<bool> {
+    /// This is coarser than [`set_memory_limit`] but catches scripts that create large amounts of
+    /// short-lived objects.
+    ///
+    /// Does not work on module mode where Lua state is managed externally.
+    ///
+    /// Requires `feature = "lua54/lua53/lua52"`
+    ///
+    /// [`Function::call`]: crate::Function::call
+    /// [`set_memory_limit`]: #method.set_memory_limit
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    pub fn set_resource_limit(&self, limit: usize) -> Result<usize> {
         unsafe {
-            check_stack(self.main_state, 3)?;
-            protect_lua!(self.main_state, 0, 0, |state| {
-                ffi::lua_gc(state, ffi::LUA_GCSTEP, kbytes) != 0
-            })
+            match (*self.extra.get()).mem_info.map(|mut x| x.as_mut()) {
+                Some(mem_info) => {
+                    let prev_limit = mem_info.allocation_limit;
+                    mem_info.allocation_limit = limit;
+                    mem_info.allocations = 0;
+                    Ok(prev_limit)
+                }
+                None => Err(Error::MemoryLimitNotAvailable),
+            }
         }
     }
 
-    /// Sets the 'pause' value of the collector.
+    /// Sets a handler deciding what to do when a memory allocation inside Lua fails.
     ///
-    /// Returns the previous value of 'pause'. More information can be found in the Lua
-    /// [documentation].
+    /// The handler is called both when the system allocator fails and when an allocation is
+    /// rejected because of [`set_memory_limit`] or [`set_resource_limit`]. Returning
+    /// [`AllocAction::ReturnError`] fails the allocation, which is reported as
+    /// `Error::MemoryError` (or `Error::ResourceLimit`), while [`AllocAction::Abort`] aborts the
+    /// process. Without a handler, allocation failures are always reported as errors.
     ///
-    /// For Luau this parameter sets GC goal
+    /// The handler must not call into Lua. If it panics, the process is aborted.
     ///
-    /// [documentation]: https://www.lua.org/manual/5.4/manual.html#2.5
-    pub fn gc_set_pause(&self, pause: c_int) -> c_int {
-        unsafe {
-            #[cfg(not(feature = "luau"))]
-            return ffi::lua_gc(self.main_state, ffi::LUA_GCSETPAUSE, pause);
+    /// Does not work on module mode where Lua state is managed externally.
+    ///
+    /// Requires `feature = "lua54/lua53/lua52"`
+    ///
+    /// [`set_memory_limit`]: #method.set_memory_limit
+    /// [`set_resource_limit`]: #method.set_resource_limit
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    pub fn on_alloc_failure<F>(&self, f: F) -> Result<()>
+    where
+        F: 'static + MaybeSend + Fn() -> AllocAction,
+    {
+        unsafe {
+            match (*self.extra.get()).mem_info.map(|mut x| x.as_mut()) {
+                Some(mem_info) => {
+                    mem_info.alloc_failure_handler = Some(Box::new(f));
+                    Ok(())
+                }
+                None => Err(Error::MemoryLimitNotAvailable),
+            }
+        }
+    }
+
+    /// Enables or disables introspection of Rust error objects from Lua.
+    ///
+    /// Rust errors and panics passed to Lua are wrapped into userdata with a protected metatable,
+    /// so `getmetatable` returns `false` for them. This debug mode removes the protection and adds
+    /// a `type` field (`"error"` or `"panic"`) to the error objects, allowing Lua-side tools to
+    /// inspect them. Disabled by default and not recommended for production use.
+    pub fn set_error_introspection(&self, enabled: bool) -> Result<()> {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            check_stack(self.state, 6)?;
+            util::set_error_introspection(self.state, enabled)
+        }
+    }
+
+    /// Sets the maximum number of values a Lua function may return to [`Function::call`].
+    ///
+    /// If a called function returns more values than the limit, the results are discarded
+    /// without converting them and the call fails with [`Error::TooManyResults`]. This protects
+    /// the host from functions flooding it with return values.
+    ///
+    /// The limit only applies when results are converted to a type accepting a variable number
+    /// of values (eg. [`MultiValue`] or [`Variadic`]). For other types Lua already adjusts the
+    /// results to the required number.
+    ///
+    /// `None` (the default) means no limit.
+    ///
+    /// [`Function::call`]: crate::Function::call
+    /// [`Error::TooManyResults`]: crate::Error::TooManyResults
+    /// [`MultiValue`]: crate::MultiValue
+    /// [`Variadic`]: crate::Variadic
+    pub fn set_max_results(&self, limit: Option<usize>) {
+        unsafe { (*self.extra.get()).max_results = limit };
+    }
+
+    #[inline]
+    pub(crate) fn max_results(&self) -> Option<usize> {
+        unsafe { (*self.extra.get()).max_results }
+    }
+
+    /// Returns the position in Lua code where the most recent Rust panic crossed into Lua.
+    ///
+    /// When a Rust callback panics, the panic is transported through Lua and resumed once it gets
+    /// back to Rust, losing the information about which Lua code triggered it. This returns the
+    /// `source:line:` position (as produced by `luaL_where`, without the trailing space) of the Lua
+    /// function that called the panicking callback, so it can be logged after catching the resumed
+    /// panic.
+    ///
+    /// Returns `None` if no panic has happened, or if the callback was not called from a Lua
+    /// function with line information.
+    pub fn last_panic_location(&self) -> Option<StdString> {
+        unsafe { (*self.extra.get()).last_panic_location.clone() }
+    }
+
+    /// Sets the policy used by [`FromLua`] implementations of string types.
+    ///
+    /// With [`StringCoercion::Strict`] (the default) converting a Lua value to `String`,
+    /// [`String`], `Box<str>`, `CString` or `BString` fails unless the value is a Lua string.
+    /// With [`StringCoercion::Coercive`] numbers are accepted as well and converted in the same
+    /// way as Lua does. [`Lua::coerce_string`] is not affected by this setting.
+    ///
+    /// [`FromLua`]: crate::FromLua
+    /// [`String`]: crate::String
+    pub fn set_string_coercion(&self, policy: StringCoercion) {
+        unsafe { (*self.extra.get()).string_coercion = policy };
+    }
+
+    /// Converts a value to a Lua string following the current [`StringCoercion`] policy.
+    pub(crate) fn convert_string<'lua>(
+        &'lua self,
+        v: Value<'lua>,
+        to: &'static str,
+    ) -> Result<String<'lua>> {
+        let ty = v.type_name();
+        let (s, message) = match unsafe { (*self.extra.get()).string_coercion } {
+            StringCoercion::Strict => match v {
+                Value::String(s) => (Some(s), ""),
+                _ => (None, "expected string"),
+            },
+            StringCoercion::Coercive => (self.coerce_string(v)?, "expected string or number"),
+        };
+        s.ok_or_else(|| Error::FromLuaConversionError {
+            from: ty,
+            to,
+            message: Some(message.to_string()),
+        })
+    }
+
+    /// Returns true if the garbage collector is currently running automatically.
+    ///
+    /// The collector is stopped by [`gc_stop`] and resumed by [`gc_restart`].
+    ///
+    /// Requires `feature = "lua54/lua53/lua52/luau"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.gc_stop();
+    /// assert!(!lua.gc_is_running());
+    /// // ... hot section without automatic collection ...
+    /// lua.gc_restart();
+    /// assert!(lua.gc_is_running());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`gc_stop`]: #method.gc_stop
+    /// [`gc_restart`]: #method.gc_restart
+    #[cfg(any(
+        feature = "lua54",
+        feature = "lua53",
+        feature = "lua52",
+        feature = "luau"
+    ))]
+    pub fn gc_is_running(&self) -> bool {
+        unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCISRUNNING, 0) != 0 }
+    }
+
+    /// Stop the Lua GC from running
+    pub fn gc_stop(&self) {
+        unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCSTOP, 0) };
+    }
+
+    /// Restarts the Lua GC if it is not running
+    pub fn gc_restart(&self) {
+        unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCRESTART, 0) };
+    }
+
+    /// Perform a full garbage-collection cycle.
+    ///
+    /// It may be necessary to call this function twice to collect all currently unreachable
+    /// objects. Once to finish the current gc cycle, and once to start and finish the next cycle.
+    pub fn gc_collect(&self) -> Result<()> {
+        let start = Instant::now();
+        let result = unsafe {
+            check_stack(self.main_state, 2)?;
+            protect_lua!(self.main_state, 0, 0, fn(state) ffi::lua_gc(state, ffi::LUA_GCCOLLECT, 0))
+        };
+        self.report_gc(GcPhase::Collect, start.elapsed());
+        result
+    }
+
+    /// Steps the garbage collector one indivisible step.
+    ///
+    /// Returns true if this has finished a collection cycle.
+    pub fn gc_step(&self) -> Result<bool> {
+        self.gc_step_kbytes(0)
+    }
+
+    /// Steps the garbage collector as though memory had been allocated.
+    ///
+    /// if `kbytes` is 0, then this is the same as calling `gc_step`. Returns true if this step has
+    /// finished a collection cycle.
+    pub fn gc_step_kbytes(&self, kbytes: c_int) -> Result<bool> {
+        let start = Instant::now();
+        let result = unsafe {
+            check_stack(self.main_state, 3)?;
+            protect_lua!(self.main_state, 0, 0, |state| {
+                ffi::lua_gc(state, ffi::LUA_GCSTEP, kbytes) != 0
+            })
+        };
+        self.report_gc(GcPhase::Step, start.elapsed());
+        result
+    }
+
+    /// Sets the 'pause' value of the collector.
+    ///
+    /// Returns the previous value of 'pause'. More information can be found in the Lua
+    /// [documentation].
+    ///
+    /// For Luau this parameter sets GC goal
+    ///
+    /// [documentation]: https://www.lua.org/manual/5.4/manual.html#2.5
+    pub fn gc_set_pause(&self, pause: c_int) -> c_int {
+        unsafe {
+            #[cfg(not(feature = "luau"))]
+            return ffi::lua_gc(self.main_state, ffi::LUA_GCSETPAUSE, pause);
             #[cfg(feature = "luau")]
             return ffi::lua_gc(self.main_state, ffi::LUA_GCSETGOAL, pause);
         }
@@ -1369,11 +2036,142 @@ impl Lua {
             name: Some(name),
             env: chunk.env(self),
             mode: chunk.mode(),
+            source_map: None,
+            transform_source: true,
             #[cfg(feature = "luau")]
             compiler: self.compiler.clone(),
         }
     }
 
+    // Same as `load`, but for chunks created by mlua itself, which skip the source transform
+    #[track_caller]
+    pub(crate) fn load_internal<'lua, 'a, S>(&'lua self, chunk: &'a S) -> Chunk<'lua, 'a>
+    where
+        S: AsChunk<'lua> + ?Sized,
+    {
+        let mut chunk = self.load(chunk);
+        chunk.transform_source = false;
+        chunk
+    }
+
+    /// Loads and runs a Lua source file, returning its results.
+    ///
+    /// This is similar to the Lua `dofile` function, but does not depend on it being available
+    /// (eg. in a sandbox). The chunk is named `@path` and runs in the global environment.
+    ///
+    /// Returns [`Error::FileError`] if the file cannot be read.
+    ///
+    /// [`Error::FileError`]: crate::Error::FileError
+    pub fn run_file<P: AsRef<Path>>(&self, path: P) -> Result<MultiValue> {
+        let path = path.as_ref();
+        let source = std::fs::read(path).map_err(|err| Error::FileError {
+            path: path.display().to_string(),
+            cause: Arc::new(err),
+        })?;
+        self.load(&source)
+            .set_name(format!("@{}", path.display()))?
+            .call(())
+    }
+
+    /// Sets a function to transform the source code of every chunk before it is loaded.
+    ///
+    /// The function receives the chunk name and its source code, and returns the source to load
+    /// instead. It is applied to all text chunks loaded with [`load`] (and so by [`Chunk::exec`],
+    /// [`Chunk::eval`], etc), which makes it a central place for preprocessing like expanding
+    /// macros. Binary chunks, and chunks loaded internally by mlua (eg. by [`tracing_table`]), are
+    /// loaded as is.
+    ///
+    /// Loading a text chunk that is not valid UTF-8 fails while a transform is set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.set_source_transform(|_name, source| source.replace("DEBUG", "false"));
+    /// assert_eq!(lua.load("DEBUG or 1").eval::<i64>()?, 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`load`]: #method.load
+    /// [`Chunk::exec`]: crate::Chunk::exec
+    /// [`Chunk::eval`]: crate::Chunk::eval
+    /// [`tracing_table`]: #method.tracing_table
+    pub fn set_source_transform<F>(&self, f: F)
+    where
+        F: 'static + MaybeSend + Fn(&str, &str) -> StdString,
+    {
+        unsafe { (*self.extra.get()).source_transform = Some(Arc::new(f)) };
+    }
+
+    /// Removes the source transform previously set by [`set_source_transform`].
+    ///
+    /// [`set_source_transform`]: #method.set_source_transform
+    pub fn remove_source_transform(&self) {
+        unsafe { (*self.extra.get()).source_transform = None };
+    }
+
+    // Applies the source transform (if set) to a text chunk.
+    // Returns `None` if no transform is set.
+    pub(crate) fn transform_source(&self, name: &str, source: &[u8]) -> Result<Option<Vec<u8>>> {
+        // Clone the transform to allow it to modify `ExtraData`
+        let transform = match unsafe { (*self.extra.get()).source_transform.clone() } {
+            Some(transform) => transform,
+            None => return Ok(None),
+        };
+        let source = str::from_utf8(source).map_err(|err| {
+            Error::RuntimeError(format!("cannot transform non UTF-8 source: {}", err))
+        })?;
+        Ok(Some(transform(name, source).into_bytes()))
+    }
+
+    /// Compiles Lua source code into a function that receives the given `names` as locals.
+    ///
+    /// The returned function takes values for the locals as positional arguments, in the same
+    /// order as `names`. This is effectively the same as compiling `function(a, b) <src> end`,
+    /// but without adding an extra function level or shifting line numbers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let func = lua.load_with_locals("return x + y", &["x", "y"])?;
+    /// assert_eq!(func.call::<_, i32>((1, 2))?, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn load_with_locals(&self, src: &str, names: &[&str]) -> Result<Function> {
+        const KEYWORDS: &[&str] = &[
+            "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto",
+            "if", "in", "local", "nil", "not", "or", "repeat", "return", "then", "true", "until",
+            "while",
+        ];
+        let is_name = |name: &&str| {
+            let mut chars = name.chars();
+            matches!(chars.next(), Some(c) if c == '_' || c.is_ascii_alphabetic())
+                && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+                && !KEYWORDS.contains(name)
+        };
+        if let Some(name) = names.iter().find(|name| !is_name(name)) {
+            return Err(Error::RuntimeError(format!(
+                "invalid local variable name '{}'",
+                name
+            )));
+        }
+
+        let source = if names.is_empty() {
+            src.to_string()
+        } else {
+            format!("local {} = ...; {}", names.join(", "), src)
+        };
+        self.load(&source).into_function()
+    }
+
     pub(crate) fn load_chunk<'lua>(
         &'lua self,
         source: &[u8],
@@ -1381,6 +2179,10 @@ impl Lua {
         env: Option<Value<'lua>>,
         mode: Option<ChunkMode>,
     ) -> Result<Function<'lua>> {
+        self.trace_api(|| {
+            let name = name.map(|n| n.to_string_lossy()).unwrap_or_default();
+            format!("Lua::load({:?})", name)
+        });
         unsafe {
             let _sg = StackGuard::new(self.state);
             check_stack(self.state, 1)?;
@@ -1420,20 +2222,89 @@ impl Lua {
     where
         S: AsRef<[u8]> + ?Sized,
     {
+        let bytes = s.as_ref();
         unsafe {
+            let extra = &mut *self.extra.get();
+            if extra.max_string_len > 0 && bytes.len() > extra.max_string_len {
+                return Err(Error::MemoryError(format!(
+                    "string length ({}) exceeds the limit ({})",
+                    bytes.len(),
+                    extra.max_string_len
+                )));
+            }
+            let cached = (extra.string_cache.as_ref()).and_then(|cache| cache.get(bytes).copied());
+            if let Some(index) = cached {
+                ffi::lua_pushvalue(extra.ref_thread, index);
+                let index = ref_stack_pop(extra);
+                return Ok(String(LuaRef { lua: self, index }));
+            }
+
             let _sg = StackGuard::new(self.state);
             check_stack(self.state, 3)?;
-            push_string(self.state, s)?;
-            Ok(String(self.pop_ref()))
+            push_string(self.state, bytes).map_err(|err| self.resource_limit_error(err))?;
+            let string = String(self.pop_ref());
+
+            let extra = &mut *self.extra.get();
+            let cacheable = match extra.string_cache {
+                Some(ref cache) => {
+                    bytes.len() <= STRING_CACHE_MAX_LEN && cache.len() < STRING_CACHE_SIZE
+                }
+                None => false,
+            };
+            if cacheable {
+                // Pin a copy of the string on the ref thread for the cache
+                ffi::lua_pushvalue(extra.ref_thread, string.0.index);
+                let index = ref_stack_pop(extra);
+                if let Some(ref mut cache) = extra.string_cache {
+                    cache.insert(bytes.into(), index);
+                }
+            }
+            Ok(string)
+        }
+    }
+
+    /// Enables a cache of small strings created from Rust.
+    ///
+    /// When enabled, [`create_string`] (and so every conversion of Rust strings to Lua, including
+    /// string keys passed to [`Table::set`]) first looks up the bytes in a Rust-side cache and
+    /// reuses the previously created Lua string, skipping pushing it into Lua again. This is
+    /// useful for workloads with many repeated short strings, especially on Lua versions that do
+    /// not intern longer strings.
+    ///
+    /// Only strings up to 64 bytes are cached, and the cache holds at most 128 entries. Cached
+    /// strings are kept alive until the cache is disabled or the Lua state is dropped.
+    ///
+    /// [`create_string`]: #method.create_string
+    /// [`Table::set`]: crate::Table::set
+    pub fn enable_string_cache(&self) {
+        let extra = unsafe { &mut *self.extra.get() };
+        if extra.string_cache.is_none() {
+            extra.string_cache = Some(FxHashMap::default());
+        }
+    }
+
+    /// Disables the string cache enabled by [`enable_string_cache`] and releases cached strings.
+    ///
+    /// [`enable_string_cache`]: #method.enable_string_cache
+    pub fn disable_string_cache(&self) {
+        let extra = unsafe { &mut *self.extra.get() };
+        for (_, index) in extra.string_cache.take().into_iter().flatten() {
+            unsafe {
+                ffi::lua_pushnil(extra.ref_thread);
+                ffi::lua_replace(extra.ref_thread, index);
+            }
+            extra.ref_free.push(index);
         }
     }
 
     /// Creates and returns a new empty table.
     pub fn create_table(&self) -> Result<Table> {
+        self.notify_table_create(0, 0);
         unsafe {
             let _sg = StackGuard::new(self.state);
             check_stack(self.state, 2)?;
-            protect_lua!(self.state, 0, 1, fn(state) ffi::lua_newtable(state))?;
+            protect_lua!(self.state, 0, 1, fn(state) ffi::lua_newtable(state))
+                .map_err(|err| self.resource_limit_error(err))?;
             Ok(Table(self.pop_ref()))
         }
     }
@@ -1443,6 +2314,7 @@ impl Lua {
     /// `nrec` is a hint for how many other elements the table will have.
     /// Lua may use these hints to preallocate memory for the new table.
     pub fn create_table_with_capacity(&self, narr: c_int, nrec: c_int) -> Result<Table> {
+        self.notify_table_create(narr.max(0) as usize, nrec.max(0) as usize);
         unsafe {
             let _sg = StackGuard::new(self.state);
             check_stack(self.state, 3)?;
@@ -1464,6 +2336,7 @@ impl Lua {
 
             let iter = iter.into_iter();
             let lower_bound = iter.size_hint().0;
+            self.notify_table_create(0, lower_bound);
             push_table(self.state, 0, lower_bound as c_int)?;
             for (k, v) in iter {
                 self.push_value(k.to_lua(self)?)?;
@@ -1487,6 +2360,7 @@ impl Lua {
 
             let iter = iter.into_iter();
             let lower_bound = iter.size_hint().0;
+            self.notify_table_create(lower_bound, 0);
             push_table(self.state, lower_bound as c_int, 0)?;
             for (i, v) in iter.enumerate() {
                 self.push_value(v.to_lua(self)?)?;
@@ -1499,61 +2373,583 @@ impl Lua {
         }
     }
 
-    /// Wraps a Rust function or closure, creating a callable Lua function handle to it.
+    /// Creates a table which type-checks values assigned to its fields.
     ///
-    /// The function's return value is always a `Result`: If the function returns `Err`, the error
-    /// is raised as a Lua error, which can be caught using `(x)pcall` or bubble up to the Rust code
-    /// that invoked the Lua code. This allows using the `?` operator to propagate errors through
-    /// intermediate Lua code.
+    /// The returned table is an empty proxy with a metatable: reads are forwarded to an internal
+    /// storage table (via `__index`) and assignments go through a `__newindex` metamethod that
+    /// accepts only the fields listed in `schema`, with values of the expected [`Type`] (or
+    /// `nil`). Any other assignment raises an error and nothing is stored.
     ///
-    /// If the function returns `Ok`, the contained value will be converted to one or more Lua
-    /// values. For details on Rust-to-Lua conversions, refer to the [`ToLua`] and [`ToLuaMulti`]
-    /// traits.
+    /// As values are kept in the storage table, the proxy itself always looks empty to raw
+    /// access and to `next`.
     ///
     /// # Examples
     ///
-    /// Create a function which prints its argument:
-    ///
     /// ```
-    /// # use mlua::{Lua, Result};
+    /// # use mlua::{Lua, Result, Type};
     /// # fn main() -> Result<()> {
-    /// # let lua = Lua::new();
-    /// let greet = lua.create_function(|_, name: String| {
-    ///     println!("Hello, {}!", name);
-    ///     Ok(())
-    /// });
-    /// # let _ = greet;    // used
+    /// let lua = Lua::new();
+    /// let config = lua.create_typed_table(&[("port", Type::Integer), ("host", Type::String)])?;
+    /// lua.globals().set("config", config)?;
+    ///
+    /// lua.load("config.port = 8080").exec()?;
+    /// assert!(lua.load("config.port = 'http'").exec().is_err());
     /// # Ok(())
     /// # }
     /// ```
     ///
-    /// Use tuples to accept multiple arguments:
+    /// [`Type`]: crate::Type
+    pub fn create_typed_table(&self, schema: &[(&str, Type)]) -> Result<Table> {
+        let schema = schema
+            .iter()
+            .map(|&(key, ty)| (key.to_string(), ty))
+            .collect::<HashMap<_, _>>();
+
+        let newindex = self.create_function(move |_, (t, key, value): (Table, Value, Value)| {
+            let name = match key {
+                Value::String(ref s) => s.to_string_lossy().into_owned(),
+                _ => {
+                    return Err(Error::RuntimeError(format!(
+                        "invalid field key (expected string, got {})",
+                        key.type_name()
+                    )))
+                }
+            };
+            let expected = match schema.get(&name) {
+                Some(&expected) => expected,
+                None => return Err(Error::RuntimeError(format!("unknown field '{}'", name))),
+            };
+            if !(value == Nil || expected.accepts(value.value_type())) {
+                return Err(Error::RuntimeError(format!(
+                    "invalid type for field '{}' (expected {}, got {})",
+                    name,
+                    expected.name(),
+                    value.type_name()
+                )));
+            }
+            let mt = mlua_expect!(t.get_metatable(), "typed table has no metatable");
+            let storage: Table = mt.raw_get("__index")?;
+            storage.raw_set(key, value)
+        })?;
+
+        let mt = self.create_table_with_capacity(0, 3)?;
+        mt.raw_set("__index", self.create_table()?)?;
+        mt.raw_set("__newindex", newindex)?;
+        mt.raw_set("__metatable", false)?;
+
+        let proxy = self.create_table()?;
+        proxy.set_metatable(Some(mt));
+        Ok(proxy)
+    }
+
+    /// Creates a table that remembers the insertion order of its keys.
+    ///
+    /// The returned table is a proxy: values are kept in a hidden storage table and a
+    /// `__newindex` metamethod records each new key in a hidden sequence (assigning `nil` removes
+    /// the key from it). Use [`Table::pairs_ordered`] to iterate the entries in insertion order,
+    /// which gives stable output e.g. for serialization in tests.
+    ///
+    /// Reads work through the `__index` metamethod, but raw access and `next` on the proxy see
+    /// no entries.
+    ///
+    /// # Examples
     ///
     /// ```
     /// # use mlua::{Lua, Result};
     /// # fn main() -> Result<()> {
-    /// # let lua = Lua::new();
-    /// let print_person = lua.create_function(|_, (name, age): (String, u8)| {
-    ///     println!("{} is {} years old!", name, age);
-    ///     Ok(())
-    /// });
-    /// # let _ = print_person;    // used
+    /// let lua = Lua::new();
+    /// let table = lua.create_ordered_table()?;
+    /// table.set("z", 1)?;
+    /// table.set("a", 2)?;
+    /// let keys = table
+    ///     .pairs_ordered::<String, i64>()?
+    ///     .into_iter()
+    ///     .map(|(k, _)| k)
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(keys, vec!["z", "a"]);
     /// # Ok(())
     /// # }
     /// ```
     ///
-    /// [`ToLua`]: crate::ToLua
-    /// [`ToLuaMulti`]: crate::ToLuaMulti
-    pub fn create_function<'lua, A, R, F>(&'lua self, func: F) -> Result<Function<'lua>>
-    where
-        A: FromLuaMulti<'lua>,
-        R: ToLuaMulti<'lua>,
-        F: 'static + MaybeSend + Fn(&'lua Lua, A) -> Result<R>,
-    {
-        self.create_callback(Box::new(move |lua, args| {
-            func(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
-        }))
-    }
+    /// [`Table::pairs_ordered`]: crate::Table::pairs_ordered
+    pub fn create_ordered_table(&self) -> Result<Table> {
+        let newindex = self.create_function(|_, (t, key, value): (Table, Value, Value)| {
+            let mt = mlua_expect!(t.get_metatable(), "ordered table has no metatable");
+            let storage: Table = mt.raw_get("__index")?;
+            let keys: Table = mt.raw_get(ORDERED_TABLE_KEYS)?;
+            let exists = storage.contains_key(key.clone())?;
+            if value == Nil {
+                if exists {
+                    let pos = keys
+                        .clone()
+                        .raw_sequence_values::<Value>()
+                        .position(|k| matches!(k, Ok(ref k) if *k == key));
+                    if let Some(pos) = pos {
+                        keys.raw_remove(pos as Integer + 1)?;
+                    }
+                }
+            } else if !exists {
+                keys.raw_set(keys.raw_len() + 1, key.clone())?;
+            }
+            storage.raw_set(key, value)
+        })?;
+
+        let mt = self.create_table_with_capacity(0, 4)?;
+        mt.raw_set("__index", self.create_table()?)?;
+        mt.raw_set("__newindex", newindex)?;
+        mt.raw_set(ORDERED_TABLE_KEYS, self.create_table()?)?;
+        mt.raw_set("__metatable", false)?;
+
+        let proxy = self.create_table()?;
+        proxy.set_metatable(Some(mt));
+        Ok(proxy)
+    }
+
+    /// Creates a read-only namespace table and sets it as the global `name`.
+    ///
+    /// `build` is called with a new empty table to populate it (e.g. with functions and
+    /// constants), then the table is frozen with [`Table::freeze`] so that scripts cannot modify
+    /// it, and the frozen table is stored in the globals and returned. This is similar to how
+    /// standard library modules like `math` are exposed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.create_namespace("units", |ns| {
+    ///     ns.set("km", 1000)?;
+    ///     ns.set("to_km", lua.create_function(|_, m: f64| Ok(m / 1000.0))?)
+    /// })?;
+    /// assert_eq!(lua.load("units.to_km(2500)").eval::<f64>()?, 2.5);
+    /// assert!(lua.load("units.km = 1").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Table::freeze`]: crate::Table::freeze
+    pub fn create_namespace<'lua, F>(&'lua self, name: &str, build: F) -> Result<Table<'lua>>
+    where
+        F: FnOnce(&Table<'lua>) -> Result<()>,
+    {
+        let table = self.create_table()?;
+        build(&table)?;
+        let namespace = table.freeze()?;
+        self.globals().set(name, namespace.clone())?;
+        Ok(namespace)
+    }
+
+    /// Exposes enum variants as a read-only namespace table and sets it as the global `name`.
+    ///
+    /// Each `(variant, value)` pair becomes a field of the table, so scripts can refer to
+    /// variants like `Color.Red`. The table is created with [`create_namespace`], so it cannot
+    /// be modified from Lua. Enums defined with [`lua_int_enum!`] provide the pairs via their
+    /// generated `variants()` function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{lua_int_enum, Lua, Result};
+    /// lua_int_enum! {
+    ///     #[derive(Debug, Clone, Copy, PartialEq)]
+    ///     enum Color {
+    ///         Red = 1,
+    ///         Green = 2,
+    ///     }
+    /// }
+    ///
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.create_enum("Color", Color::variants().iter().copied())?;
+    /// assert_eq!(lua.load("Color.Green").eval::<Color>()?, Color::Green);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`create_namespace`]: #method.create_namespace
+    /// [`lua_int_enum!`]: crate::lua_int_enum
+    pub fn create_enum<'lua, I, K, V>(&'lua self, name: &str, variants: I) -> Result<Table<'lua>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: ToLua<'lua>,
+        V: ToLua<'lua>,
+    {
+        self.create_namespace(name, |ns| {
+            for (k, v) in variants {
+                ns.raw_set(k, v)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Compares two values structurally, recursing into tables.
+    ///
+    /// Tables are equal if they have the same set of keys (compared with [`Value`] equality),
+    /// mapping to deeply equal values. Integers and floats with the same numeric value are
+    /// equal. Functions, threads and userdata are compared by identity. Metamethods are not
+    /// invoked. Cyclic tables are supported: a pair of tables already being compared is
+    /// assumed to be equal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let a: Value = lua.load("{1, {x = 2}}").eval()?;
+    /// let b: Value = lua.load("{1.0, {x = 2}}").eval()?;
+    /// assert!(a != b);
+    /// assert!(lua.deep_eq(&a, &b)?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn deep_eq<'lua>(&'lua self, a: &Value<'lua>, b: &Value<'lua>) -> Result<bool> {
+        let mut visited = Vec::new();
+        deep_eq_values(a, b, &mut visited)
+    }
+
+    /// Sets a sink receiving a line for each high-level API call, for post-mortem debugging.
+    ///
+    /// Loading chunks ([`Lua::load`]), calling functions ([`Function::call`]), and reading or
+    /// writing table fields ([`Table::get`] and [`Table::set`]) are logged with their arguments
+    /// rendered as strings, eg. `Table::set("answer", 42)`. Reference types are rendered as
+    /// their type name.
+    ///
+    /// Requires `feature = "trace"`
+    ///
+    /// [`Lua::load`]: #method.load
+    /// [`Function::call`]: crate::Function::call
+    /// [`Table::get`]: crate::Table::get
+    /// [`Table::set`]: crate::Table::set
+    #[cfg(feature = "trace")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "trace")))]
+    pub fn set_api_trace<F>(&self, sink: F)
+    where
+        F: 'static + MaybeSend + Fn(&str),
+    {
+        unsafe { (*self.extra.get()).api_trace = Some(Arc::new(sink)) };
+    }
+
+    /// Removes the API trace sink previously set by [`set_api_trace`].
+    ///
+    /// Requires `feature = "trace"`
+    ///
+    /// [`set_api_trace`]: #method.set_api_trace
+    #[cfg(feature = "trace")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "trace")))]
+    pub fn remove_api_trace(&self) {
+        unsafe { (*self.extra.get()).api_trace = None };
+    }
+
+    // Passes the API call description to the trace sink (if set)
+    #[inline]
+    pub(crate) fn trace_api<F: FnOnce() -> StdString>(&self, describe: F) {
+        #[cfg(feature = "trace")]
+        unsafe {
+            if let Some(sink) = (*self.extra.get()).api_trace.clone() {
+                sink(&describe());
+            }
+        }
+        #[cfg(not(feature = "trace"))]
+        let _ = describe;
+    }
+
+    /// Sets a callback that is called whenever a table is created using the Rust API.
+    ///
+    /// The callback receives the preallocation hints of the new table: the number of sequence
+    /// elements and the number of other elements. It is called by [`create_table`],
+    /// [`create_table_with_capacity`], [`create_table_from`] and [`create_sequence_from`]
+    /// (with the iterator lower size bound as a hint), but not for tables created by Lua code.
+    ///
+    /// This can be used to profile which code paths create the most tables.
+    ///
+    /// [`create_table`]: #method.create_table
+    /// [`create_table_with_capacity`]: #method.create_table_with_capacity
+    /// [`create_table_from`]: #method.create_table_from
+    /// [`create_sequence_from`]: #method.create_sequence_from
+    pub fn on_table_create<F>(&self, f: F)
+    where
+        F: 'static + MaybeSend + Fn(usize, usize),
+    {
+        unsafe { (*self.extra.get()).table_create_callback = Some(Arc::new(f)) };
+    }
+
+    /// Sets a threshold for reporting slow function calls.
+    ///
+    /// Every call made through [`Function::call`] that takes longer than `threshold` is reported
+    /// to `sink`, along with the measured duration. The function is described by its source
+    /// location (eg. `[string "script"]:10`, or `[C]` for Rust and C functions).
+    ///
+    /// This gives visibility into latency of scripts in production. Only the time spent inside
+    /// the call is measured, not conversion of arguments and results.
+    ///
+    /// [`Function::call`]: crate::Function::call
+    pub fn set_slow_call_threshold<F>(&self, threshold: Duration, sink: F)
+    where
+        F: 'static + MaybeSend + Fn(&str, Duration),
+    {
+        unsafe { (*self.extra.get()).slow_call = Some((threshold, Arc::new(sink))) };
+    }
+
+    /// Removes the slow call threshold previously set by [`set_slow_call_threshold`].
+    ///
+    /// [`set_slow_call_threshold`]: #method.set_slow_call_threshold
+    pub fn remove_slow_call_threshold(&self) {
+        unsafe { (*self.extra.get()).slow_call = None };
+    }
+
+    #[inline]
+    pub(crate) fn slow_call_threshold(&self) -> Option<Duration> {
+        unsafe { (*self.extra.get()).slow_call.as_ref().map(|(t, _)| *t) }
+    }
+
+    // Calls the slow call sink (if set)
+    pub(crate) fn report_slow_call(&self, name: &str, elapsed: Duration) {
+        // Clone the sink to allow it to modify `ExtraData`
+        let sink = unsafe {
+            (*self.extra.get())
+                .slow_call
+                .as_ref()
+                .map(|(_, s)| s.clone())
+        };
+        if let Some(sink) = sink {
+            sink(name, elapsed);
+        }
+    }
+
+    /// Sets an observer that is notified about garbage collector (GC) activity.
+    ///
+    /// Explicit collection requests ([`gc_step`], [`gc_step_kbytes`] and [`gc_collect`]) are
+    /// timed and reported with the time they paused the program.
+    ///
+    /// On Lua 5.4/5.3/5.2 the end of every collection cycle is reported too, including cycles
+    /// run automatically during allocations. This is detected using a finalizer of a sentinel
+    /// object, so the exact pause is unknown: the reported duration is the time elapsed since the
+    /// end of the previous cycle (or since the observer was set).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{GcPhase, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.set_gc_observer(|phase: GcPhase, elapsed| println!("gc {:?}: {:?}", phase, elapsed))?;
+    /// lua.gc_collect()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`gc_step`]: #method.gc_step
+    /// [`gc_step_kbytes`]: #method.gc_step_kbytes
+    /// [`gc_collect`]: #method.gc_collect
+    pub fn set_gc_observer<F>(&self, f: F) -> Result<()>
+    where
+        F: 'static + MaybeSend + Fn(GcPhase, Duration),
+    {
+        unsafe { (*self.extra.get()).gc_observer = Some(Arc::new(f)) };
+        #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+        unsafe {
+            let extra = self.extra.get();
+            if (*extra).gc_cycle_end.is_none() {
+                self.arm_gc_sentinel()?;
+                (*extra).gc_cycle_end = Some(Instant::now());
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the observer previously set by [`set_gc_observer`].
+    ///
+    /// [`set_gc_observer`]: #method.set_gc_observer
+    pub fn remove_gc_observer(&self) {
+        unsafe { (*self.extra.get()).gc_observer = None };
+    }
+
+    // Creates a garbage table that reports the end of a GC cycle when it's finalized.
+    // The sentinel is re-armed until the observer is removed.
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    fn arm_gc_sentinel(&self) -> Result<()> {
+        let sentinel = self.create_table()?;
+        sentinel.set_gc_callback(|lua| unsafe {
+            let extra = lua.extra.get();
+            let last_cycle_end = (*extra).gc_cycle_end.take();
+            if let (Some(observer), Some(last_cycle_end)) =
+                ((*extra).gc_observer.clone(), last_cycle_end)
+            {
+                let now = Instant::now();
+                if lua.arm_gc_sentinel().is_ok() {
+                    (*extra).gc_cycle_end = Some(now);
+                }
+                observer(GcPhase::CycleEnd, now - last_cycle_end);
+            }
+        })
+    }
+
+    // Calls the GC observer (if set)
+    fn report_gc(&self, phase: GcPhase, elapsed: Duration) {
+        // Clone the observer to allow it to modify `ExtraData`
+        let observer = unsafe { (*self.extra.get()).gc_observer.clone() };
+        if let Some(observer) = observer {
+            observer(phase, elapsed);
+        }
+    }
+
+    // Calls the table creation callback (if set)
+    fn notify_table_create(&self, narr: usize, nrec: usize) {
+        // Clone the callback to allow it to modify `ExtraData`
+        let callback = unsafe { (*self.extra.get()).table_create_callback.clone() };
+        if let Some(callback) = callback {
+            callback(narr, nrec);
+        }
+    }
+
+    /// Reconstructs a detached [`OwnedValue`] as a [`Value`] in this Lua state.
+    ///
+    /// Together with [`Value::into_owned`] and [`OwnedValue::from_table`] this allows moving
+    /// primitive values and plain tables of them between separate `Lua` instances. Strings and tables are copied, so the resulting
+    /// value does not share anything with the state it originated from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, OwnedValue, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// let lua_a = Lua::new();
+    /// let lua_b = Lua::new();
+    ///
+    /// let owned = OwnedValue::from_table(lua_a.load("{ x = 1, y = { 2, 3 } }").eval::<Table>()?)?;
+    /// lua_b.globals().set("data", lua_b.transfer_value(&owned)?)?;
+    /// assert_eq!(lua_b.load("data.y[2]").eval::<i64>()?, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`OwnedValue`]: crate::OwnedValue
+    /// [`OwnedValue::from_table`]: crate::OwnedValue::from_table
+    pub fn transfer_value<'lua>(&'lua self, from_value: &OwnedValue) -> Result<Value<'lua>> {
+        Ok(match from_value {
+            OwnedValue::Nil => Value::Nil,
+            OwnedValue::Boolean(b) => Value::Boolean(*b),
+            OwnedValue::Integer(i) => Value::Integer(*i),
+            OwnedValue::Number(n) => Value::Number(*n),
+            #[cfg(feature = "luau")]
+            OwnedValue::Vector(x, y, z) => Value::Vector(*x, *y, *z),
+            OwnedValue::String(s) => Value::String(self.create_string(s)?),
+            OwnedValue::Table(entries) => {
+                let table = self.create_table_with_capacity(0, entries.len() as c_int)?;
+                for (key, value) in entries {
+                    table.raw_set(self.transfer_value(key)?, self.transfer_value(value)?)?;
+                }
+                Value::Table(table)
+            }
+        })
+    }
+
+    /// Creates a proxy table that logs every field read before forwarding it to `inner`.
+    ///
+    /// The `__index` metamethod of the proxy passes the accessed key (converted using Lua
+    /// `tostring`) to the `log` function and then returns `inner[key]`. Assignments to the proxy
+    /// are forwarded to `inner` without logging.
+    ///
+    /// This is useful for finding out which fields (eg. configuration keys) scripts actually read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config = lua.create_table()?;
+    /// config.set("width", 800)?;
+    ///
+    /// let proxy = lua.tracing_table(config, |key| println!("config key read: {}", key))?;
+    /// lua.globals().set("config", proxy)?;
+    /// lua.load("assert(config.width == 800)").exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tracing_table<'lua, F>(&'lua self, inner: Table<'lua>, log: F) -> Result<Table<'lua>>
+    where
+        F: 'static + MaybeSend + Fn(&str),
+    {
+        let log = self.create_function(move |_, key: String| {
+            log(&key.to_string_lossy());
+            Ok(())
+        })?;
+        self.load_internal(
+            r#"
+            local inner, log = ...
+            return setmetatable({}, {
+                __index = function(_, key)
+                    log(tostring(key))
+                    return inner[key]
+                end,
+                __newindex = inner,
+            })
+            "#,
+        )
+        .set_name("=__mlua_tracing_table")?
+        .call((inner, log))
+    }
+
+    /// Wraps a Rust function or closure, creating a callable Lua function handle to it.
+    ///
+    /// The function's return value is always a `Result`: If the function returns `Err`, the error
+    /// is raised as a Lua error, which can be caught using `(x)pcall` or bubble up to the Rust code
+    /// that invoked the Lua code. This allows using the `?` operator to propagate errors through
+    /// intermediate Lua code.
+    ///
+    /// If the function returns `Ok`, the contained value will be converted to one or more Lua
+    /// values. For details on Rust-to-Lua conversions, refer to the [`ToLua`] and [`ToLuaMulti`]
+    /// traits.
+    ///
+    /// # Examples
+    ///
+    /// Create a function which prints its argument:
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let greet = lua.create_function(|_, name: String| {
+    ///     println!("Hello, {}!", name);
+    ///     Ok(())
+    /// });
+    /// # let _ = greet;    // used
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Use tuples to accept multiple arguments:
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let print_person = lua.create_function(|_, (name, age): (String, u8)| {
+    ///     println!("{} is {} years old!", name, age);
+    ///     Ok(())
+    /// });
+    /// # let _ = print_person;    // used
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`ToLua`]: crate::ToLua
+    /// [`ToLuaMulti`]: crate::ToLuaMulti
+    pub fn create_function<'lua, A, R, F>(&'lua self, func: F) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'lua>,
+        R: ToLuaMulti<'lua>,
+        F: 'static + MaybeSend + Fn(&'lua Lua, A) -> Result<R>,
+    {
+        self.create_callback(Box::new(move |lua, args| {
+            #[cfg(feature = "lua54")]
+            lua.check_unused_args(args.len(), A::MAX_ARGS)?;
+            func(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+        }))
+    }
 
     /// Wraps a Rust mutable closure, creating a callable Lua function handle to it.
     ///
@@ -1646,8 +3042,13 @@ impl Lua {
     /// Wraps a Lua function into a new thread (or coroutine).
     ///
     /// Equivalent to `coroutine.create`.
+    ///
+    /// If thread tracking is enabled, the thread is tracked (without preventing its garbage
+    /// collection) and listed by [`active_threads`] until it finishes.
+    ///
+    /// [`active_threads`]: #method.active_threads
     pub fn create_thread<'lua>(&'lua self, func: Function<'lua>) -> Result<Thread<'lua>> {
-        unsafe {
+        let thread = unsafe {
             let _sg = StackGuard::new(self.state);
             check_stack(self.state, 3)?;
 
@@ -1655,8 +3056,48 @@ impl Lua {
             self.push_ref(&func.0);
             ffi::lua_xmove(self.state, thread_state, 1);
 
-            Ok(Thread(self.pop_ref()))
+            Thread(self.pop_ref())
+        };
+        if unsafe { (*self.extra.get()).track_threads } {
+            self.weak_keyed_table(&ACTIVE_THREADS_KEY)?
+                .raw_set(thread.clone(), true)?;
         }
+        Ok(thread)
+    }
+
+    /// Enables tracking of threads created by [`create_thread`], to be listed by
+    /// [`active_threads`].
+    ///
+    /// Tracking is disabled by default to keep thread creation cheap. Only threads created after
+    /// enabling it are tracked.
+    ///
+    /// [`create_thread`]: #method.create_thread
+    /// [`active_threads`]: #method.active_threads
+    pub fn enable_thread_tracking(&self) {
+        unsafe { (*self.extra.get()).track_threads = true };
+    }
+
+    /// Returns all live threads created by [`create_thread`] that have not finished yet.
+    ///
+    /// Requires thread tracking to be enabled with [`enable_thread_tracking`], otherwise no
+    /// threads are returned. Threads that are dead (returned or failed with an error) are
+    /// excluded, as well as garbage collected ones. The order of the returned threads is
+    /// unspecified.
+    ///
+    /// [`create_thread`]: #method.create_thread
+    /// [`enable_thread_tracking`]: #method.enable_thread_tracking
+    pub fn active_threads(&self) -> Result<Vec<Thread>> {
+        let mut result = Vec::new();
+        for pair in self
+            .weak_keyed_table(&ACTIVE_THREADS_KEY)?
+            .pairs::<Thread, bool>()
+        {
+            let (thread, _) = pair?;
+            if thread.status() == ThreadStatus::Resumable {
+                result.push(thread);
+            }
+        }
+        Ok(result)
     }
 
     /// Wraps a Lua function into a new or recycled thread (coroutine).
@@ -1710,46 +3151,528 @@ impl Lua {
             if status != ffi::LUA_OK {
                 return;
             }
-            #[cfg(all(feature = "luajit", feature = "vendored"))]
-            ffi::lua_resetthread(self.state, thread_state);
-            #[cfg(feature = "luau")]
-            ffi::lua_resetthread(thread_state);
-            extra.recycled_thread_cache.push(thread.0.index);
-            thread.0.index = 0;
+            #[cfg(all(feature = "luajit", feature = "vendored"))]
+            ffi::lua_resetthread(self.state, thread_state);
+            #[cfg(feature = "luau")]
+            ffi::lua_resetthread(thread_state);
+            extra.recycled_thread_cache.push(thread.0.index);
+            thread.0.index = 0;
+        }
+    }
+
+    /// Builds and caches the metatable for the userdata type `T` without creating an instance.
+    ///
+    /// The metatable of a userdata type is normally built on first use, which calls
+    /// [`UserData::add_fields`] and [`UserData::add_methods`] and creates all the callbacks.
+    /// Registering types upfront moves that cost to the initialization phase.
+    /// Registering an already registered type is a no-op.
+    pub fn register_userdata<T>(&self) -> Result<()>
+    where
+        T: 'static + MaybeSend + UserData,
+    {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            check_stack(self.state, 1)?;
+            self.push_userdata_metatable::<T>()
+        }
+    }
+
+    /// Create a Lua userdata object from a custom userdata type.
+    pub fn create_userdata<T>(&self, data: T) -> Result<AnyUserData>
+    where
+        T: 'static + MaybeSend + UserData,
+    {
+        unsafe { self.make_userdata(UserDataCell::new(data)) }
+    }
+
+    /// Creates a Lua userdata object and attaches a string `tag` to it.
+    ///
+    /// Live userdata objects with a given tag can be queried with [`tagged_userdata`], which
+    /// helps to track down leaked or orphaned objects. Tags are stored in a weak-keyed table in
+    /// the Lua registry, so they don't prevent userdata from being garbage collected.
+    ///
+    /// [`tagged_userdata`]: #method.tagged_userdata
+    pub fn create_tagged_userdata<T>(&self, tag: &str, data: T) -> Result<AnyUserData>
+    where
+        T: 'static + MaybeSend + UserData,
+    {
+        let ud = self.create_userdata(data)?;
+        self.weak_keyed_table(&USERDATA_TAGS_KEY)?
+            .raw_set(ud.clone(), tag)?;
+        Ok(ud)
+    }
+
+    /// Returns all live userdata objects created by [`create_tagged_userdata`] with the given tag.
+    ///
+    /// The order of the returned objects is unspecified. Note that on Lua 5.2+ a collected
+    /// userdata with a finalizer is removed from the index only by the garbage collection cycle
+    /// following the one that finalized it.
+    ///
+    /// [`create_tagged_userdata`]: #method.create_tagged_userdata
+    pub fn tagged_userdata(&self, tag: &str) -> Result<Vec<AnyUserData>> {
+        let mut result = Vec::new();
+        for pair in self
+            .weak_keyed_table(&USERDATA_TAGS_KEY)?
+            .pairs::<AnyUserData, String>()
+        {
+            let (ud, ud_tag) = pair?;
+            if ud_tag == tag {
+                result.push(ud);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Associates Rust `data` with the Lua value `v`, without modifying the value.
+    ///
+    /// Only one piece of data of a given type can be associated with a value, associating
+    /// another one replaces it. Associations are stored in a weak-keyed table in the Lua
+    /// registry (keyed by value identity), so they don't prevent the value from being garbage
+    /// collected, and the data is dropped after the value is collected.
+    ///
+    /// Returns an error if `v` is not a table, function, thread or userdata.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let config = Value::Table(lua.create_table()?);
+    /// lua.associate(&config, "loaded from config.lua")?;
+    /// assert_eq!(lua.associated::<&str>(&config)?, Some("loaded from config.lua"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn associate<'lua, T>(&'lua self, v: &Value<'lua>, data: T) -> Result<()>
+    where
+        T: 'static + MaybeSend,
+    {
+        match v {
+            Value::Table(_) | Value::Function(_) | Value::Thread(_) | Value::UserData(_) => {}
+            _ => {
+                return Err(Error::RuntimeError(format!(
+                    "cannot associate data with a {} value",
+                    v.type_name()
+                )))
+            }
+        }
+
+        let data = self.create_userdata(AssociatedData(data))?;
+        let associations = self.weak_keyed_table(&ASSOCIATED_DATA_KEY)?;
+        let list = match associations.raw_get::<_, Option<Table>>(v.clone())? {
+            Some(list) => list,
+            None => {
+                let list = self.create_table()?;
+                associations.raw_set(v.clone(), list.clone())?;
+                list
+            }
+        };
+        for (i, ud) in list.clone().sequence_values::<AnyUserData>().enumerate() {
+            if ud?.is::<AssociatedData<T>>() {
+                return list.raw_set(i + 1, data);
+            }
+        }
+        list.raw_set(list.raw_len() + 1, data)
+    }
+
+    /// Returns a copy of the data of type `T` associated with the Lua value `v` by [`associate`].
+    ///
+    /// [`associate`]: #method.associate
+    pub fn associated<'lua, T>(&'lua self, v: &Value<'lua>) -> Result<Option<T>>
+    where
+        T: 'static + Clone,
+    {
+        let associations = self.weak_keyed_table(&ASSOCIATED_DATA_KEY)?;
+        if let Some(list) = associations.raw_get::<_, Option<Table>>(v.clone())? {
+            for ud in list.sequence_values::<AnyUserData>() {
+                let ud = ud?;
+                let data = ud.borrow::<AssociatedData<T>>().map(|data| data.0.clone());
+                if let Ok(data) = data {
+                    return Ok(Some(data));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Registers `f` to be called when the userdata `ud` is finalized.
+    ///
+    /// Rust `Drop` impls of userdata have no access to Lua, and running arbitrary Lua code during
+    /// garbage collection is fragile. This helper provides a safe pattern for finalizers that
+    /// need to update Lua state, eg. to remove the object from a registry table. `f` is called
+    /// during a garbage collection cycle after `ud` has been collected (its `Drop` impl has
+    /// already run). Errors returned from `f` and panics are caught and ignored, in the same way
+    /// as errors in finalizers.
+    ///
+    /// `f` should restrict itself to raw table operations ([`Table::raw_get`], [`Table::raw_set`],
+    /// [`Table::raw_remove`], etc.), which are protected and never call back into Lua code.
+    /// Calling Lua functions or triggering metamethods from finalizers is not recommended.
+    ///
+    /// Requires `feature = "lua54/lua53/lua52"`
+    ///
+    /// [`Table::raw_get`]: crate::Table::raw_get
+    /// [`Table::raw_set`]: crate::Table::raw_set
+    /// [`Table::raw_remove`]: crate::Table::raw_remove
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    pub fn in_finalizer<F>(&self, ud: &AnyUserData, f: F) -> Result<()>
+    where
+        F: 'static + MaybeSend + FnOnce(&Lua) -> Result<()>,
+    {
+        // The sentinel table is reachable only through the weak-keyed entry of `ud`, so it's
+        // collected (and its `__gc` callback called) after `ud` itself
+        let sentinel = self.create_table()?;
+        sentinel.set_gc_callback(move |lua| {
+            let _ = f(lua);
+        })?;
+
+        let finalizers = self.weak_keyed_table(&USERDATA_FINALIZERS_KEY)?;
+        let sentinels = match finalizers.raw_get::<_, Option<Table>>(ud.clone())? {
+            Some(sentinels) => sentinels,
+            None => {
+                let sentinels = self.create_table()?;
+                finalizers.raw_set(ud.clone(), sentinels.clone())?;
+                sentinels
+            }
+        };
+        sentinels.raw_set(sentinels.raw_len() + 1, sentinel)
+    }
+
+    // Returns the weak-keyed registry table stored under the given key
+    fn weak_keyed_table(&self, key: &'static u8) -> Result<Table> {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            check_stack(self.state, 4)?;
+
+            let key = key as *const u8 as *const c_void;
+            protect_lua!(self.state, 0, 1, |state| {
+                if ffi::lua_rawgetp(state, ffi::LUA_REGISTRYINDEX, key) != ffi::LUA_TTABLE {
+                    ffi::lua_pop(state, 1);
+                    ffi::lua_createtable(state, 0, 0);
+                    ffi::lua_createtable(state, 0, 1);
+                    ffi::lua_pushstring(state, cstr!("k"));
+                    ffi::lua_setfield(state, -2, cstr!("__mode"));
+                    ffi::lua_setmetatable(state, -2);
+                    ffi::lua_pushvalue(state, -1);
+                    ffi::lua_rawsetp(state, ffi::LUA_REGISTRYINDEX, key);
+                }
+            })?;
+            Ok(Table(self.pop_ref()))
+        }
+    }
+
+    /// Create a Lua userdata object from a custom serializable userdata type.
+    ///
+    /// Requires `feature = "serialize"`
+    #[cfg(feature = "serialize")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
+    pub fn create_ser_userdata<T>(&self, data: T) -> Result<AnyUserData>
+    where
+        T: 'static + MaybeSend + UserData + Serialize,
+    {
+        unsafe { self.make_userdata(UserDataCell::new_ser(data)) }
+    }
+
+    /// Returns a handle to the global environment.
+    pub fn globals(&self) -> Table {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+            #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+            ffi::lua_rawgeti(self.state, ffi::LUA_REGISTRYINDEX, ffi::LUA_RIDX_GLOBALS);
+            #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
+            ffi::lua_pushvalue(self.state, ffi::LUA_GLOBALSINDEX);
+            Table(self.pop_ref())
+        }
+    }
+
+    /// Calls the global function `name`, passing `args` as function arguments.
+    ///
+    /// This is a shortcut for getting the function from [`globals`] and calling it, but reports
+    /// a descriptive error naming the global and the type actually found when the value is not
+    /// a function.
+    ///
+    /// This might invoke the `__index` metamethod of the globals table.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.load("function greet(name) return 'hello, ' .. name end").exec()?;
+    /// let greeting: String = lua.call_global("greet", "world")?;
+    /// assert_eq!(greeting, "hello, world");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`globals`]: #method.globals
+    pub fn call_global<'lua, A, R>(&'lua self, name: &str, args: A) -> Result<R>
+    where
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        match self.globals().get::<_, Value>(name)? {
+            Value::Function(func) => func.call(args),
+            value => Err(Error::RuntimeError(format!(
+                "global '{}' is not a function (got {})",
+                name,
+                value.type_name()
+            ))),
+        }
+    }
+
+    /// Returns the names of all variables defined in the global environment, sorted.
+    ///
+    /// Only string keys that are set directly in the globals table are returned; fields that are
+    /// reachable through a metatable `__index` are not included.
+    /// This is useful for auditing what is visible to scripts running in a restricted environment.
+    pub fn global_names(&self) -> Result<Vec<StdString>> {
+        let mut names = Vec::new();
+        for pair in self.globals().pairs::<Value, Value>() {
+            if let (Value::String(name), _) = pair? {
+                names.push(name.to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Runs `f` and returns the names of global variables that were created during the call.
+    ///
+    /// A temporary `__newindex` metamethod is installed on the globals table to record written
+    /// keys (in the order they were first written), and the original metatable is restored
+    /// afterwards. Any existing `__newindex` metamethod is still invoked.
+    ///
+    /// Only assignments that create new globals are recorded, as Lua does not invoke
+    /// `__newindex` when updating existing fields.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let written = lua.track_global_writes(|| lua.load("a = 1; b = 2").exec())?;
+    /// assert_eq!(written, vec!["a", "b"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn track_global_writes<F>(&self, f: F) -> Result<Vec<StdString>>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let globals = self.globals();
+        let old_mt = globals.get_metatable();
+
+        let mt = self.create_table()?;
+        let old_newindex = match old_mt {
+            Some(ref old_mt) => {
+                for pair in old_mt.clone().pairs::<Value, Value>() {
+                    let (key, value) = pair?;
+                    mt.raw_set(key, value)?;
+                }
+                old_mt.raw_get::<_, Value>("__newindex")?
+            }
+            None => Nil,
+        };
+        let old_newindex = self.create_registry_value(old_newindex)?;
+
+        let written = Arc::new(Mutex::new(Vec::<StdString>::new()));
+        let recorder = written.clone();
+        let newindex = self.create_function(move |lua, (t, k, v): (Table, Value, Value)| {
+            if let Value::String(ref name) = k {
+                let name = name.to_string_lossy().into_owned();
+                let mut written = mlua_expect!(recorder.lock(), "cannot lock written globals");
+                if !written.contains(&name) {
+                    written.push(name);
+                }
+            }
+            match lua.registry_value::<Value>(&old_newindex)? {
+                Value::Function(func) => func.call((t, k, v)),
+                Value::Table(table) => table.set(k, v),
+                _ => t.raw_set(k, v),
+            }
+        })?;
+        mt.raw_set("__newindex", newindex)?;
+
+        globals.set_metatable(Some(mt));
+        {
+            // Restore the original metatable even if `f` panics
+            let _guard = MetatableGuard(globals, old_mt);
+            f()?;
         }
+
+        let written = mlua_expect!(written.lock(), "cannot lock written globals");
+        Ok(written.clone())
     }
 
-    /// Create a Lua userdata object from a custom userdata type.
-    pub fn create_userdata<T>(&self, data: T) -> Result<AnyUserData>
+    /// Runs `f` as a transaction, rolling back global state if it fails.
+    ///
+    /// Before calling `f` the contents and the metatable of the globals table are saved. If `f`
+    /// returns an error, globals added during the call are removed, modified globals get their
+    /// previous values back and the metatable is restored. The same happens if `f` panics.
+    ///
+    /// Values placed in the registry during a failed transaction are released as well: slots of
+    /// dropped [`RegistryKey`]s are reclaimed (see [`expire_registry_values`]), and keys created
+    /// during `f` that are still alive (or were leaked) resolve to `nil` afterwards.
+    ///
+    /// Changes made to values reachable from globals (e.g. fields of a global table) are not
+    /// rolled back.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let result = lua.transaction(|lua| lua.load("x = 1; error('oops')").exec());
+    /// assert!(result.is_err());
+    /// assert_eq!(lua.globals().get::<_, Value>("x")?, Value::Nil);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`RegistryKey`]: crate::RegistryKey
+    /// [`expire_registry_values`]: #method.expire_registry_values
+    pub fn transaction<'lua, R, F>(&'lua self, f: F) -> Result<R>
     where
-        T: 'static + MaybeSend + UserData,
+        F: FnOnce(&'lua Lua) -> Result<R>,
     {
-        unsafe { self.make_userdata(UserDataCell::new(data)) }
+        let globals = self.globals();
+        let metatable = globals.get_metatable();
+        let snapshot = globals
+            .clone()
+            .pairs::<Value, Value>()
+            .collect::<Result<Vec<_>>>()?;
+
+        let extra = unsafe { &mut *self.extra.get() };
+        let outermost = extra.transaction_registry_ids.is_none();
+        let registry_ids = extra.transaction_registry_ids.get_or_insert_with(Vec::new);
+        // Rolls back the transaction if `f` fails or panics
+        let mut guard = TransactionGuard {
+            globals,
+            metatable,
+            snapshot,
+            registry_ids_start: registry_ids.len(),
+            outermost,
+            finished: false,
+        };
+
+        let result = f(self);
+        if result.is_err() {
+            guard.rollback()?;
+        }
+        guard.finished = true;
+        result
     }
 
-    /// Create a Lua userdata object from a custom serializable userdata type.
+    /// Sets a resolver for undefined global variables.
     ///
-    /// Requires `feature = "serialize"`
-    #[cfg(feature = "serialize")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
-    pub fn create_ser_userdata<T>(&self, data: T) -> Result<AnyUserData>
+    /// Installs an `__index` metamethod on the globals table, so reading a missing global with a
+    /// string name calls the resolver. If it returns `Some(value)`, the value is stored in the
+    /// globals table (so subsequent accesses do not invoke the resolver) and returned. Otherwise
+    /// the previous `__index` metamethod (if any) is consulted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.set_global_resolver(|_lua, name| match name {
+    ///     "answer" => Ok(Some(Value::Integer(42))),
+    ///     _ => Ok(None),
+    /// })?;
+    /// assert_eq!(lua.load("answer").eval::<i64>()?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_global_resolver<F>(&self, f: F) -> Result<()>
     where
-        T: 'static + MaybeSend + UserData + Serialize,
+        F: 'static + MaybeSend + for<'lua> Fn(&'lua Lua, &str) -> Result<Option<Value<'lua>>>,
     {
-        unsafe { self.make_userdata(UserDataCell::new_ser(data)) }
+        let globals = self.globals();
+        let mt = match globals.get_metatable() {
+            Some(mt) => mt,
+            None => {
+                let mt = self.create_table()?;
+                globals.set_metatable(Some(mt.clone()));
+                mt
+            }
+        };
+        let old_index = self.create_registry_value(mt.raw_get::<_, Value>("__index")?)?;
+
+        let index = self.create_function(move |lua, (t, k): (Table, Value)| {
+            if let Value::String(ref name) = k {
+                if let Some(value) = f(lua, &name.to_string_lossy())? {
+                    t.raw_set(k.clone(), value.clone())?;
+                    return Ok(value);
+                }
+            }
+            match lua.registry_value::<Value>(&old_index)? {
+                Value::Function(func) => func.call((t, k)),
+                Value::Table(table) => table.get(k),
+                _ => Ok(Nil),
+            }
+        })?;
+        mt.raw_set("__index", index)
     }
 
-    /// Returns a handle to the global environment.
-    pub fn globals(&self) -> Table {
-        unsafe {
-            let _sg = StackGuard::new(self.state);
-            assert_stack(self.state, 1);
-            #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
-            ffi::lua_rawgeti(self.state, ffi::LUA_REGISTRYINDEX, ffi::LUA_RIDX_GLOBALS);
-            #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
-            ffi::lua_pushvalue(self.state, ffi::LUA_GLOBALSINDEX);
-            Table(self.pop_ref())
+    /// Registers a module resolving undefined global variables that start with `prefix`.
+    ///
+    /// Reading a missing global whose name starts with `prefix` calls `resolver` with the full
+    /// global name. Modules with matching prefixes are consulted in the order they were added,
+    /// until one of them returns `Some(value)`; the value is then stored in the globals table
+    /// (so subsequent accesses do not invoke the resolvers) and returned. This allows plugins to
+    /// claim their own namespaces of globals.
+    ///
+    /// Modules are dispatched from a single resolver installed with [`set_global_resolver`]
+    /// when the first module is added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.add_global_module("math_", |_lua, name| match name {
+    ///     "math_tau" => Ok(Some(Value::Number(std::f64::consts::TAU))),
+    ///     _ => Ok(None),
+    /// })?;
+    /// assert!(lua.load("math_tau > 6").eval::<bool>()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`set_global_resolver`]: #method.set_global_resolver
+    pub fn add_global_module<F>(&self, prefix: &str, resolver: F) -> Result<()>
+    where
+        F: 'static + MaybeSend + for<'lua> Fn(&'lua Lua, &str) -> Result<Option<Value<'lua>>>,
+    {
+        let extra = unsafe { &mut *self.extra.get() };
+        let install = extra.global_modules.is_none();
+        let modules = extra.global_modules.get_or_insert_with(Vec::new);
+        modules.push((prefix.to_string(), Arc::new(resolver)));
+
+        if install {
+            self.set_global_resolver(|lua, name| {
+                // Clone matching resolvers to allow them to modify `ExtraData`
+                let resolvers = unsafe { (*lua.extra.get()).global_modules.iter() }
+                    .flatten()
+                    .filter(|(prefix, _)| name.starts_with(prefix.as_str()))
+                    .map(|(_, resolver)| resolver.clone())
+                    .collect::<Vec<_>>();
+                for resolver in resolvers {
+                    if let Some(value) = resolver(lua, name)? {
+                        return Ok(Some(value));
+                    }
+                }
+                Ok(None)
+            })?;
         }
+        Ok(())
     }
 
     /// Returns a handle to the active `Thread`. For calls to `Lua` this will be the main Lua thread,
@@ -1964,6 +3887,105 @@ impl Lua {
         self.set_named_registry_value(name, Nil)
     }
 
+    /// Serializes all named registry values with `serializer`, to be restored with
+    /// [`load_registry`].
+    ///
+    /// Only values that can be detached from the Lua state (see [`OwnedValue::from_table`]) are
+    /// serialized: primitive values and plain tables of them. Other values (eg. functions,
+    /// userdata, or tables containing them) are skipped, and their names are returned along with
+    /// the serializer output. Entries created by Lua itself (names starting with an underscore
+    /// followed by an uppercase letter, like `_LOADED`, and metatables of the standard libraries,
+    /// like `FILE*`) and names that are not valid UTF-8 are ignored.
+    ///
+    /// The values are serialized as a map from names to values, in any serde data format.
+    ///
+    /// Requires `feature = "serialize"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.set_named_registry_value("counter", 42)?;
+    /// lua.set_named_registry_value("callback", lua.create_function(|_, ()| Ok(()))?)?;
+    /// let mut json = Vec::new();
+    /// let ((), skipped) = lua.dump_registry(&mut serde_json::Serializer::new(&mut json))?;
+    /// assert_eq!(skipped, vec!["callback".to_string()]);
+    ///
+    /// let lua2 = Lua::new();
+    /// lua2.load_registry(&mut serde_json::Deserializer::from_slice(&json))?;
+    /// assert_eq!(lua2.named_registry_value::<_, i64>("counter")?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`load_registry`]: #method.load_registry
+    /// [`OwnedValue::from_table`]: crate::OwnedValue::from_table
+    #[cfg(feature = "serialize")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
+    pub fn dump_registry<S: Serializer>(&self, serializer: S) -> Result<(S::Ok, Vec<StdString>)> {
+        // Metatables registered by the standard libraries under non-reserved names
+        const LIBRARY_NAMES: &[&str] = &["FILE*"];
+
+        let registry: Table = unsafe {
+            let _sg = StackGuard::new(self.state);
+            check_stack(self.state, 1)?;
+
+            ffi::lua_pushvalue(self.state, ffi::LUA_REGISTRYINDEX);
+            Table(self.pop_ref())
+        };
+
+        let mut values = Vec::new();
+        let mut skipped = Vec::new();
+        for pair in registry.pairs::<Value, Value>() {
+            let (name, value) = match pair? {
+                (Value::String(name), value) => match name.to_str() {
+                    Ok(name) => (name.to_owned(), value),
+                    Err(_) => continue,
+                },
+                _ => continue,
+            };
+            let mut chars = name.chars();
+            if chars.next() == Some('_')
+                && matches!(chars.next(), Some(c) if c.is_ascii_uppercase())
+            {
+                continue;
+            }
+            if LIBRARY_NAMES.contains(&name.as_str()) {
+                continue;
+            }
+            match OwnedValue::detach(value) {
+                Ok(value) => values.push((name, value)),
+                Err(_) => skipped.push(name),
+            }
+        }
+
+        let output = serializer
+            .collect_map(values.iter().map(|(name, value)| (name, value)))
+            .map_err(|err| Error::SerializeError(err.to_string()))?;
+        Ok((output, skipped))
+    }
+
+    /// Restores named registry values serialized by [`dump_registry`] from `deserializer`.
+    ///
+    /// The values can come from a different `Lua` instance. Existing values with the same names
+    /// are overwritten, other named values are left intact.
+    ///
+    /// Requires `feature = "serialize"`
+    ///
+    /// [`dump_registry`]: #method.dump_registry
+    #[cfg(feature = "serialize")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
+    pub fn load_registry<'de, D: Deserializer<'de>>(&self, deserializer: D) -> Result<()> {
+        let values = FxHashMap::<StdString, OwnedValue>::deserialize(deserializer)
+            .map_err(|err| Error::DeserializeError(err.to_string()))?;
+        for (name, value) in values {
+            self.set_named_registry_value(&name, self.transfer_value(&value)?)?;
+        }
+        Ok(())
+    }
+
     /// Place a value in the Lua registry with an auto-generated key.
     ///
     /// This value will be available to Rust from all `Lua` instances which share the same main
@@ -1989,6 +4011,7 @@ impl Lua {
             if let Some(registry_id) = unref_list2.as_mut().and_then(|x| x.pop()) {
                 // It must be safe to replace the value without triggering memory error
                 ffi::lua_rawseti(self.state, ffi::LUA_REGISTRYINDEX, registry_id as Integer);
+                self.record_registry_id(registry_id);
                 return Ok(RegistryKey {
                     registry_id,
                     unref_list,
@@ -1999,6 +4022,7 @@ impl Lua {
             let registry_id = protect_lua!(self.state, 1, 0, |state| {
                 ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
             })?;
+            self.record_registry_id(registry_id);
 
             Ok(RegistryKey {
                 registry_id,
@@ -2007,6 +4031,32 @@ impl Lua {
         }
     }
 
+    /// Places a value in the Lua registry and returns a [`RegistryRef`] to it.
+    ///
+    /// The registry slot is released as soon as the reference is dropped.
+    ///
+    /// [`RegistryRef`]: crate::RegistryRef
+    pub fn create_registry_ref<'lua, T: ToLua<'lua>>(
+        &'lua self,
+        t: T,
+    ) -> Result<RegistryRef<'lua>> {
+        let t = t.to_lua(self)?;
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            check_stack(self.state, 4)?;
+
+            self.push_value(t)?;
+            let registry_id = protect_lua!(self.state, 1, 0, |state| {
+                ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+            })?;
+
+            Ok(RegistryRef {
+                lua: self,
+                registry_id,
+            })
+        }
+    }
+
     /// Get a value from the Lua registry by its `RegistryKey`
     ///
     /// Any Lua instance which shares the underlying main state may call this method to get a value
@@ -2027,6 +4077,12 @@ impl Lua {
                 ffi::LUA_REGISTRYINDEX,
                 key.registry_id as Integer,
             );
+            // Values released by a failed transaction read as `nil`
+            let rolled_back = &ROLLED_BACK_VALUE as *const u8 as *mut c_void;
+            if ffi::lua_touserdata(self.state, -1) == rolled_back {
+                ffi::lua_pop(self.state, 1);
+                ffi::lua_pushnil(self.state);
+            }
             self.pop_value()
         };
         T::from_lua(value, self)
@@ -2105,12 +4161,27 @@ impl Lua {
                 "unref list poisoned"
             );
             let unref_list = mem::replace(&mut *unref_list, Some(Vec::new()));
-            for id in mlua_expect!(unref_list, "unref list not set") {
+            let unref_list = mlua_expect!(unref_list, "unref list not set");
+            // Released slots must not be touched by a transaction rollback
+            if let Some(ref mut ids) = (*self.extra.get()).transaction_registry_ids {
+                for id in ids.iter_mut().filter(|id| unref_list.contains(id)) {
+                    *id = ffi::LUA_NOREF;
+                }
+            }
+            for id in unref_list {
                 ffi::luaL_unref(self.state, ffi::LUA_REGISTRYINDEX, id);
             }
         }
     }
 
+    // Remembers a registry slot allocated during a transaction
+    fn record_registry_id(&self, registry_id: c_int) {
+        let extra = unsafe { &mut *self.extra.get() };
+        if let Some(ref mut ids) = extra.transaction_registry_ids {
+            ids.push(registry_id);
+        }
+    }
+
     /// Sets or replaces an application data object of type `T`.
     ///
     /// Application data could be accessed at any time by using [`Lua::app_data_ref()`] or [`Lua::app_data_mut()`]
@@ -2182,6 +4253,16 @@ impl Lua {
     // Uses 2 stack spaces, does not call checkstack
     pub(crate) unsafe fn push_value(&self, value: Value) -> Result<()> {
         match value {
+            // Move the error instead of cloning it
+            Value::Error(err) => push_gc_userdata(self.state, WrappedFailure::Error(err)),
+            value => self.push_value_ref(&value),
+        }
+    }
+
+    // Pushes a borrowed value, without moving its reference.
+    // Uses 2 stack spaces, does not call checkstack
+    pub(crate) unsafe fn push_value_ref(&self, value: &Value) -> Result<()> {
+        match *value {
             Value::Nil => {
                 ffi::lua_pushnil(self.state);
             }
@@ -2190,7 +4271,7 @@ impl Lua {
                 ffi::lua_pushboolean(self.state, if b { 1 } else { 0 });
             }
 
-            Value::LightUserData(ud) => {
+            Value::LightUserData(ref ud) => {
                 ffi::lua_pushlightuserdata(self.state, ud.0);
             }
 
@@ -2207,28 +4288,28 @@ impl Lua {
                 ffi::lua_pushvector(self.state, x, y, z);
             }
 
-            Value::String(s) => {
+            Value::String(ref s) => {
                 self.push_ref(&s.0);
             }
 
-            Value::Table(t) => {
+            Value::Table(ref t) => {
                 self.push_ref(&t.0);
             }
 
-            Value::Function(f) => {
+            Value::Function(ref f) => {
                 self.push_ref(&f.0);
             }
 
-            Value::Thread(t) => {
+            Value::Thread(ref t) => {
                 self.push_ref(&t.0);
             }
 
-            Value::UserData(ud) => {
+            Value::UserData(ref ud) => {
                 self.push_ref(&ud.0);
             }
 
-            Value::Error(err) => {
-                push_gc_userdata(self.state, WrappedFailure::Error(err))?;
+            Value::Error(ref err) => {
+                push_gc_userdata(self.state, WrappedFailure::Error(err.clone()))?;
             }
         }
 
@@ -2318,6 +4399,116 @@ impl Lua {
         }
     }
 
+    // Registers source map for the loaded chunk function.
+    // The source map is kept until the function is garbage collected.
+    pub(crate) fn set_source_map(
+        &self,
+        func: &Function,
+        source_map: SourceMapCallback,
+    ) -> Result<()> {
+        // Use the chunk name as Lua reports it in error messages (it may be truncated)
+        let short_src = match func.source_info() {
+            Some((short_src, _)) => short_src,
+            None => return Ok(()),
+        };
+
+        let maps = self.weak_keyed_table(&SOURCE_MAPS_KEY)?;
+        let live_ids = self.live_source_map_ids(&maps)?;
+        let id = unsafe {
+            let extra = &mut *self.extra.get();
+            // Drop source maps of collected chunks
+            extra.source_maps.retain(|id, _| live_ids.contains(id));
+            extra.last_source_map_id += 1;
+            extra.last_source_map_id
+        };
+        maps.raw_set(func.clone(), id)?;
+        unsafe {
+            (*self.extra.get())
+                .source_maps
+                .insert(id, (short_src, source_map))
+        };
+        Ok(())
+    }
+
+    // Returns ids of source maps of chunk functions which are not garbage collected yet
+    fn live_source_map_ids(&self, maps: &Table) -> Result<Vec<Integer>> {
+        maps.clone()
+            .pairs::<Value, Integer>()
+            .map(|pair| pair.map(|(_, id)| id))
+            .collect()
+    }
+
+    // Remaps line numbers in error messages and tracebacks using registered source maps
+    pub(crate) fn remap_error_lines(&self, err: Error) -> Error {
+        if unsafe { (*self.extra.get()).source_maps.is_empty() } {
+            return err;
+        }
+        let live_ids = self
+            .weak_keyed_table(&SOURCE_MAPS_KEY)
+            .and_then(|maps| self.live_source_map_ids(&maps));
+        let mut live_ids = match live_ids {
+            Ok(live_ids) => live_ids,
+            Err(_) => return err,
+        };
+        // Newer chunks take precedence over older ones with the same name
+        live_ids.sort_unstable_by(|a, b| b.cmp(a));
+
+        let extra = unsafe { &*self.extra.get() };
+        let remap = |s: &str| {
+            let mut s = s.to_string();
+            for id in &live_ids {
+                if let Some((short_src, source_map)) = extra.source_maps.get(id) {
+                    if s.contains(short_src.as_str()) {
+                        s = remap_source_lines(&s, short_src, source_map);
+                    }
+                }
+            }
+            s
+        };
+        match err {
+            Error::RuntimeError(msg) => Error::RuntimeError(remap(&msg)),
+            Error::CallbackError { traceback, cause } => Error::CallbackError {
+                traceback: remap(&traceback),
+                cause,
+            },
+            err => err,
+        }
+    }
+
+    // Marks the beginning of a call into Lua.
+    // The allocations counter is reset when entering the outermost call.
+    pub(crate) fn enter_call(&self) -> CallGuard {
+        unsafe {
+            let mem_info = (*self.extra.get()).mem_info;
+            if let Some(mut mem_info) = mem_info {
+                let mem_info = mem_info.as_mut();
+                if mem_info.call_depth == 0 {
+                    mem_info.allocations = 0;
+                }
+                mem_info.call_depth += 1;
+            }
+            CallGuard(mem_info)
+        }
+    }
+
+    // Converts a memory error caused by reaching the allocations limit to `Error::ResourceLimit`
+    pub(crate) fn resource_limit_error(&self, err: Error) -> Error {
+        match err {
+            Error::MemoryError(_) => unsafe {
+                match (*self.extra.get()).mem_info.map(|x| x.as_ref()) {
+                    Some(mem_info)
+                        if mem_info.allocation_limit > 0
+                            && mem_info.allocations >= mem_info.allocation_limit =>
+                    {
+                        Error::ResourceLimit
+                    }
+                    _ => err,
+                }
+            },
+            err => err,
+        }
+    }
+
     // Pushes a LuaRef value onto the stack, uses 1 stack space, does not call checkstack
     pub(crate) unsafe fn push_ref(&self, lref: &LuaRef) {
         assert!(
@@ -2395,6 +4586,23 @@ impl Lua {
         T::add_fields(&mut fields);
         T::add_methods(&mut methods);
 
+        if let Some(precision) = T::DISPLAY_PRECISION {
+            let has_tostring = methods
+                .meta_methods
+                .iter()
+                .any(|(k, _)| *k == MetaMethod::ToString);
+            if !has_tostring {
+                methods.add_meta_method(MetaMethod::ToString, move |_, this, ()| {
+                    match this.display_number() {
+                        Some(n) => Ok(format!("{:.*}", precision, n)),
+                        None => Err(Error::RuntimeError(
+                            "userdata does not provide a display number".to_string(),
+                        )),
+                    }
+                });
+            }
+        }
+
         // Prepare metatable, add meta methods first and then meta fields
         let metatable_nrec = methods.meta_methods.len() + fields.meta_fields.len();
         #[cfg(feature = "async")]
@@ -2728,7 +4936,7 @@ impl Lua {
         })?;
 
         // We set `poll` variable in the env table to be able to destroy upvalues
-        self.load(
+        self.load_internal(
             r#"
             poll = get_poll(...)
             local poll, pending, yield, unpack = poll, pending, yield, unpack
@@ -2834,6 +5042,11 @@ impl Lua {
         }
     }
 
+    #[inline]
+    fn multivalue_cache_len(&self) -> usize {
+        unsafe { (*self.extra.get()).multivalue_cache.len() }
+    }
+
     #[inline]
     pub(crate) fn cache_multivalue(&self, mut multivalue: MultiValue) {
         unsafe {
@@ -2846,6 +5059,76 @@ impl Lua {
     }
 }
 
+// Rolls back globals and registry values of an unfinished `Lua::transaction` when dropped
+struct TransactionGuard<'lua> {
+    globals: Table<'lua>,
+    metatable: Option<Table<'lua>>,
+    snapshot: Vec<(Value<'lua>, Value<'lua>)>,
+    // Position of the registry ids allocated by this transaction (nested ones share the list)
+    registry_ids_start: usize,
+    outermost: bool,
+    finished: bool,
+}
+
+impl<'lua> TransactionGuard<'lua> {
+    fn rollback(&mut self) -> Result<()> {
+        self.finished = true;
+        let lua = self.globals.0.lua;
+
+        let keys = (self.globals.clone().pairs::<Value, Value>())
+            .map(|pair| pair.map(|(k, _)| k))
+            .collect::<Result<Vec<_>>>()?;
+        for key in keys {
+            self.globals.raw_set(key, Nil)?;
+        }
+        for (key, value) in mem::take(&mut self.snapshot) {
+            self.globals.raw_set(key, value)?;
+        }
+        self.globals.set_metatable(self.metatable.take());
+
+        unsafe {
+            let extra = &mut *lua.extra.get();
+            let registry_ids = match extra.transaction_registry_ids {
+                Some(ref mut ids) => ids.split_off(self.registry_ids_start),
+                None => Vec::new(),
+            };
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, 1)?;
+            for id in registry_ids.into_iter().filter(|&id| id != ffi::LUA_NOREF) {
+                // Keep the slots occupied until the owning keys are dropped, otherwise `luaL_ref`
+                // could hand them out again. Replacing values cannot trigger memory error.
+                let placeholder = &ROLLED_BACK_VALUE as *const u8 as *mut c_void;
+                ffi::lua_pushlightuserdata(lua.state, placeholder);
+                ffi::lua_rawseti(lua.state, ffi::LUA_REGISTRYINDEX, id as Integer);
+            }
+        }
+        lua.expire_registry_values();
+        Ok(())
+    }
+}
+
+impl<'lua> Drop for TransactionGuard<'lua> {
+    fn drop(&mut self) {
+        if !self.finished {
+            // `f` panicked, there is no way to report rollback errors
+            let _ = self.rollback();
+        }
+        if self.outermost {
+            let lua = self.globals.0.lua;
+            unsafe { (*lua.extra.get()).transaction_registry_ids = None };
+        }
+    }
+}
+
+// Sets the metatable of a table back when dropped
+struct MetatableGuard<'lua>(Table<'lua>, Option<Table<'lua>>);
+
+impl<'lua> Drop for MetatableGuard<'lua> {
+    fn drop(&mut self) {
+        self.0.set_metatable(self.1.take());
+    }
+}
+
 struct StateGuard<'a>(&'a mut LuaInner, *mut ffi::lua_State);
 
 impl<'a> StateGuard<'a> {
@@ -2989,7 +5272,7 @@ where
             } else {
                 "<not enough stack space for traceback>".to_string()
             };
-            let cause = Arc::new(err);
+            let cause = Arc::new(util::resolve_error_level(state, err));
             ptr::write(
                 wrapped_error,
                 WrappedFailure::Error(Error::CallbackError { traceback, cause }),
@@ -3001,6 +5284,13 @@ where
         }
         Err(p) => {
             let wrapped_panic = get_wrapped_failure();
+            // Remember the position of the Lua code that called the panicking callback
+            if ffi::lua_checkstack(state, 1) != 0 {
+                ffi::luaL_where(state, 1);
+                let location = util::to_string(state, -1).trim_end().to_string();
+                ffi::lua_pop(state, 1);
+                extra.last_panic_location = Some(location).filter(|l| !l.is_empty());
+            }
             ptr::write(wrapped_panic, WrappedFailure::Panic(Some(p)));
             get_gc_metatable::<WrappedFailure>(state);
             ffi::lua_setmetatable(state, -2);
@@ -3009,6 +5299,45 @@ where
     }
 }
 
+// Recursive part of `Lua::deep_eq`, `visited` holds pairs of tables being compared
+fn deep_eq_values<'lua>(
+    a: &Value<'lua>,
+    b: &Value<'lua>,
+    visited: &mut Vec<(Table<'lua>, Table<'lua>)>,
+) -> Result<bool> {
+    let (ta, tb) = match (a, b) {
+        (Value::Table(ta), Value::Table(tb)) => (ta, tb),
+        _ => return Ok(a == b),
+    };
+    if ta == tb || visited.iter().any(|(x, y)| x == ta && y == tb) {
+        return Ok(true);
+    }
+    visited.push((ta.clone(), tb.clone()));
+
+    let mut count = 0;
+    for pair in ta.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        let other = tb.raw_get::<_, Value>(key)?;
+        if !deep_eq_values(&value, &other, visited)? {
+            return Ok(false);
+        }
+        count += 1;
+    }
+
+    let mut other_count = 0;
+    for pair in tb.clone().pairs::<Value, Value>() {
+        pair?;
+        other_count += 1;
+    }
+
+    Ok(count == other_count)
+}
+
+// Rust data associated with a Lua value, see `Lua::associate`
+struct AssociatedData<T>(T);
+
+impl<T> UserData for AssociatedData<T> {}
+
 // Uses 3 stack spaces
 unsafe fn load_from_std_lib(state: *mut ffi::lua_State, libs: StdLib) -> Result<()> {
     #[inline(always)]
@@ -3137,6 +5466,33 @@ unsafe fn load_from_std_lib(state: *mut ffi::lua_State, libs: StdLib) -> Result<
     Ok(())
 }
 
+// Replaces every `<short_src>:<line>:` occurrence with `<file>:<mapped line>:`
+fn remap_source_lines(s: &str, short_src: &str, source_map: &SourceMapCallback) -> StdString {
+    let mut result = StdString::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find(short_src) {
+        result.push_str(&rest[..pos]);
+        let after = &rest[pos + short_src.len()..];
+        let digits = after
+            .strip_prefix(':')
+            .map(|a| a.bytes().take_while(u8::is_ascii_digit).count())
+            .unwrap_or(0);
+        match after.get(1..1 + digits).map(str::parse::<u32>) {
+            Some(Ok(line)) if after[1 + digits..].starts_with(':') => {
+                let (file, line) = source_map(line);
+                result.push_str(&format!("{}:{}", file, line));
+                rest = &after[1 + digits..];
+            }
+            _ => {
+                result.push_str(short_src);
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
 unsafe fn ref_stack_pop(extra: &mut ExtraData) -> c_int {
     if let Some(free) = extra.ref_free.pop() {
         ffi::lua_replace(extra.ref_thread, free);
@@ -109,3 +109,90 @@ macro_rules! protect_lua {
         crate::util::protect_lua_call($state, $nargs, do_call)
     }};
 }
+
+/// Defines a fieldless enum that is passed to Lua as an integer constant.
+///
+/// The macro generates [`ToLua`] and [`FromLua`] implementations that map the enum to and from
+/// its discriminant. Converting an unknown discriminant from Lua returns an error.
+/// It also generates a `values()` associated function that returns all enum variants, and a
+/// `variants()` function that pairs them with their names (see [`Lua::create_enum`]).
+///
+/// # Examples
+///
+/// ```
+/// use mlua::{lua_int_enum, Lua, Result};
+///
+/// lua_int_enum! {
+///     #[derive(Debug, Clone, Copy, PartialEq)]
+///     pub enum Color {
+///         Red = 1,
+///         Green = 2,
+///         Blue = 4,
+///     }
+/// }
+///
+/// fn main() -> Result<()> {
+///     let lua = Lua::new();
+///     lua.globals().set("color", Color::Green)?;
+///     assert_eq!(lua.load("color").eval::<i64>()?, 2);
+///     assert_eq!(lua.load("4").eval::<Color>()?, Color::Blue);
+///     assert!(lua.load("3").eval::<Color>().is_err());
+///     assert_eq!(Color::values(), &[Color::Red, Color::Green, Color::Blue]);
+///     Ok(())
+/// }
+/// ```
+///
+/// [`ToLua`]: crate::ToLua
+/// [`FromLua`]: crate::FromLua
+/// [`Lua::create_enum`]: crate::Lua::create_enum
+#[macro_export]
+macro_rules! lua_int_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $($(#[$vmeta:meta])* $variant:ident = $value:expr),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($(#[$vmeta])* $variant = $value),*
+        }
+
+        impl $name {
+            /// Returns all variants of the enum.
+            #[allow(dead_code)]
+            pub const fn values() -> &'static [$name] {
+                &[$($name::$variant),*]
+            }
+
+            /// Returns the names of all enum variants paired with the variants.
+            #[allow(dead_code)]
+            pub const fn variants() -> &'static [(&'static str, $name)] {
+                &[$((stringify!($variant), $name::$variant)),*]
+            }
+        }
+
+        impl<'lua> $crate::ToLua<'lua> for $name {
+            fn to_lua(self, _: &'lua $crate::Lua) -> $crate::Result<$crate::Value<'lua>> {
+                Ok($crate::Value::Integer(self as $crate::Integer))
+            }
+        }
+
+        impl<'lua> $crate::FromLua<'lua> for $name {
+            fn from_lua(value: $crate::Value<'lua>, lua: &'lua $crate::Lua) -> $crate::Result<Self> {
+                let ty = value.type_name();
+                let i = <$crate::Integer as $crate::FromLua>::from_lua(value, lua)?;
+                $(
+                    if i == $name::$variant as $crate::Integer {
+                        return Ok($name::$variant);
+                    }
+                )*
+                Err($crate::Error::FromLuaConversionError {
+                    from: ty,
+                    to: stringify!($name),
+                    message: Some(format!("unknown discriminant {}", i)),
+                })
+            }
+        }
+    };
+}
@@ -6,7 +6,7 @@ use std::result::Result as StdResult;
 
 use crate::error::Result;
 use crate::lua::Lua;
-use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti};
+use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti, Value};
 
 /// Result is convertible to `MultiValue` following the common Lua idiom of returning the result
 /// on success, or in the case of an error, returning `nil` and an error message.
@@ -38,6 +38,13 @@ impl<'lua, T: FromLua<'lua>> FromLuaMulti<'lua> for T {
         lua.cache_multivalue(values);
         res
     }
+
+    #[inline]
+    fn from_lua_single(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        T::from_lua(value, lua)
+    }
+
+    const MAX_ARGS: Option<usize> = Some(1);
 }
 
 impl<'lua> ToLuaMulti<'lua> for MultiValue<'lua> {
@@ -156,6 +163,8 @@ macro_rules! impl_tuple {
                 lua.cache_multivalue(values);
                 Ok(())
             }
+
+            const MAX_ARGS: Option<usize> = Some(0);
         }
     );
 
@@ -186,6 +195,11 @@ macro_rules! impl_tuple {
                 let $last = FromLuaMulti::from_lua_multi(values, lua)?;
                 Ok(($(FromLua::from_lua($name, lua)?,)* $last,))
             }
+
+            const MAX_ARGS: Option<usize> = match <$last as FromLuaMulti<'lua>>::MAX_ARGS {
+                Some(n) => Some(n + <[&str]>::len(&[$(stringify!($name)),*])),
+                None => None,
+            };
         }
     );
 }
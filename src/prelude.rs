@@ -2,24 +2,30 @@
 
 #[doc(no_inline)]
 pub use crate::{
-    AnyUserData as LuaAnyUserData, Chunk as LuaChunk, Error as LuaError,
-    ExternalError as LuaExternalError, ExternalResult as LuaExternalResult, FromLua, FromLuaMulti,
-    Function as LuaFunction, GCMode as LuaGCMode, Integer as LuaInteger,
-    LightUserData as LuaLightUserData, Lua, LuaOptions, MetaMethod as LuaMetaMethod,
-    MultiValue as LuaMultiValue, Nil as LuaNil, Number as LuaNumber, RegistryKey as LuaRegistryKey,
-    Result as LuaResult, StdLib as LuaStdLib, String as LuaString, Table as LuaTable,
-    TableExt as LuaTableExt, TablePairs as LuaTablePairs, TableSequence as LuaTableSequence,
-    Thread as LuaThread, ThreadStatus as LuaThreadStatus, ToLua, ToLuaMulti,
-    UserData as LuaUserData, UserDataFields as LuaUserDataFields,
-    UserDataMetatable as LuaUserDataMetatable, UserDataMethods as LuaUserDataMethods,
-    Value as LuaValue,
+    AllocAction as LuaAllocAction, AnyUserData as LuaAnyUserData, Chunk as LuaChunk,
+    Error as LuaError, ExternalError as LuaExternalError, ExternalResult as LuaExternalResult,
+    FromLua, FromLuaMulti, Function as LuaFunction, GCMode as LuaGCMode, GcPhase as LuaGcPhase,
+    Integer as LuaInteger, Key as LuaKey, LightUserData as LuaLightUserData, Lua, LuaOptions,
+    MetaMethod as LuaMetaMethod, MultiValue as LuaMultiValue, Nil as LuaNil,
+    NullableTable as LuaNullableTable, Number as LuaNumber, OwnedValue as LuaOwnedValue,
+    RegistryKey as LuaRegistryKey, RegistryRef as LuaRegistryRef, Result as LuaResult,
+    StdLib as LuaStdLib, String as LuaString, StringCoercion as LuaStringCoercion,
+    Table as LuaTable, TableExt as LuaTableExt, TablePairs as LuaTablePairs,
+    TableSequence as LuaTableSequence, Thread as LuaThread, ThreadStatus as LuaThreadStatus, ToLua,
+    ToLuaMulti, Type as LuaType, TypedMetaHandler as LuaTypedMetaHandler, UserData as LuaUserData,
+    UserDataFields as LuaUserDataFields, UserDataMetatable as LuaUserDataMetatable,
+    UserDataMethods as LuaUserDataMethods, Value as LuaValue,
 };
 
 #[cfg(not(feature = "luau"))]
 #[doc(no_inline)]
 pub use crate::HookTriggers as LuaHookTriggers;
 
-#[cfg(feature = "luau")]
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+#[doc(no_inline)]
+pub use crate::FunctionParams as LuaFunctionParams;
+
+#[cfg(any(feature = "luau", feature = "lua54"))]
 #[doc(no_inline)]
 pub use crate::VmState as LuaVmState;
 
@@ -12,7 +12,7 @@ use crate::error::{Error, Result};
 use crate::ffi;
 use crate::function::Function;
 use crate::lua::Lua;
-use crate::types::{Callback, CallbackUpvalue, LuaRef, MaybeSend};
+use crate::types::{Callback, CallbackUpvalue, Integer, LuaRef, MaybeSend};
 use crate::userdata::{
     AnyUserData, MetaMethod, UserData, UserDataCell, UserDataFields, UserDataMethods,
 };
@@ -484,6 +484,35 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
         }
     }
 
+    /// Creates a read-only Lua view of a Rust slice.
+    ///
+    /// The returned userdata supports indexing (`view[i]`, 1-based, returning `nil` when out of
+    /// bounds) and the length operator (`#view`). Elements are converted on access, so the slice
+    /// is never copied into a Lua table. The userdata expires on scope drop, which makes it safe
+    /// to borrow `slice` for the lifetime of the scope.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let data = vec![1.5, 2.5, 3.5];
+    /// let sum = lua.scope(|scope| {
+    ///     lua.globals().set("data", scope.create_slice_userdata(&data)?)?;
+    ///     lua.load("local s = 0 for i = 1, #data do s = s + data[i] end return s").eval::<f64>()
+    /// })?;
+    /// assert_eq!(sum, 7.5);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_slice_userdata<T>(&self, slice: &'scope [T]) -> Result<AnyUserData<'lua>>
+    where
+        T: 'scope + Clone + for<'l> ToLua<'l>,
+    {
+        self.create_nonstatic_userdata(SliceUserData(slice))
+    }
+
     // Unsafe, because the callback can improperly capture any value with 'callback scope, such as
     // improperly capturing an argument. Since the 'callback lifetime is chosen by the user and the
     // lifetime of the callback itself is 'scope (non-'static), the borrow checker will happily pick
@@ -596,6 +625,20 @@ impl<'lua, 'scope> Drop for Scope<'lua, 'scope> {
     }
 }
 
+struct SliceUserData<'a, T>(&'a [T]);
+
+impl<'a, T: Clone + for<'l> ToLua<'l>> UserData for SliceUserData<'a, T> {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(MetaMethod::Index, |_, this, i: Integer| {
+            Ok(match i {
+                i if i >= 1 && i as usize <= this.0.len() => Some(this.0[i as usize - 1].clone()),
+                _ => None,
+            })
+        });
+        methods.add_meta_method(MetaMethod::Len, |_, this, ()| Ok(this.0.len()));
+    }
+}
+
 enum NonStaticMethod<'lua, T> {
     Method(Box<dyn Fn(&'lua Lua, &T, MultiValue<'lua>) -> Result<MultiValue<'lua>>>),
     MethodMut(Box<dyn FnMut(&'lua Lua, &mut T, MultiValue<'lua>) -> Result<MultiValue<'lua>>>),
@@ -69,6 +69,36 @@ impl<'lua> String<'lua> {
         StdString::from_utf8_lossy(self.as_bytes())
     }
 
+    /// Decodes the string bytes using the encoding with the given `label`.
+    ///
+    /// Labels are resolved following the [Encoding Standard], eg. `"latin1"` or `"shift_jis"`.
+    /// Malformed sequences are replaced with the REPLACEMENT CHARACTER. Returns an error if the
+    /// label does not name a known encoding.
+    ///
+    /// Requires `feature = "encoding"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let s = lua.create_string(b"caf\xe9")?;
+    /// assert_eq!(s.decode("latin1")?, "café");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [Encoding Standard]: https://encoding.spec.whatwg.org/#names-and-labels
+    #[cfg(feature = "encoding")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+    pub fn decode(&self, label: &str) -> Result<StdString> {
+        let encoding = encoding_rs::Encoding::for_label(label.as_bytes())
+            .ok_or_else(|| Error::RuntimeError(format!("unknown encoding '{}'", label)))?;
+        let (decoded, _) = encoding.decode_without_bom_handling(self.as_bytes());
+        Ok(decoded.into_owned())
+    }
+
     /// Get the bytes that make up this string.
     ///
     /// The returned slice will not contain the terminating nul byte, but will contain any nul
@@ -1,22 +1,42 @@
+use std::cmp::Ordering;
 use std::marker::PhantomData;
+use std::os::raw::{c_int, c_void};
+
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+use {
+    crate::lua::Lua,
+    crate::types::MaybeSend,
+    std::panic::{catch_unwind, AssertUnwindSafe},
+};
+
+#[cfg(any(
+    feature = "lua54",
+    feature = "lua53",
+    feature = "lua52",
+    feature = "serialize"
+))]
+use std::cell::RefCell;
 
 #[cfg(feature = "serialize")]
 use {
     rustc_hash::FxHashSet,
     serde::ser::{self, Serialize, SerializeMap, SerializeSeq, Serializer},
-    std::{cell::RefCell, os::raw::c_void, result::Result as StdResult},
+    std::result::Result as StdResult,
 };
 
 use crate::error::{Error, Result};
 use crate::ffi;
 use crate::function::Function;
-use crate::types::{Integer, LuaRef};
-use crate::util::{assert_stack, check_stack, StackGuard};
-use crate::value::{FromLua, FromLuaMulti, Nil, ToLua, ToLuaMulti, Value};
+use crate::types::{Integer, LightUserData, LuaRef, Number, PlainFunction};
+use crate::util::{assert_stack, check_stack, push_string, StackGuard};
+use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti, Type, Value};
 
 #[cfg(feature = "async")]
 use {futures_core::future::LocalBoxFuture, futures_util::future};
 
+// Metatable field of ordered tables holding the sequence of keys in insertion order
+pub(crate) const ORDERED_TABLE_KEYS: &str = "__mlua_ordered_keys";
+
 /// Handle to an internal Lua table.
 #[derive(Clone, Debug)]
 pub struct Table<'lua>(pub(crate) LuaRef<'lua>);
@@ -60,6 +80,10 @@ impl<'lua> Table<'lua> {
         let lua = self.0.lua;
         let key = key.to_lua(lua)?;
         let value = value.to_lua(lua)?;
+        lua.trace_api(|| {
+            let (key, value) = (key.to_trace_string(), value.to_trace_string());
+            format!("Table::set({}, {})", key, value)
+        });
 
         unsafe {
             let _sg = StackGuard::new(lua.state);
@@ -99,6 +123,7 @@ impl<'lua> Table<'lua> {
     pub fn get<K: ToLua<'lua>, V: FromLua<'lua>>(&self, key: K) -> Result<V> {
         let lua = self.0.lua;
         let key = key.to_lua(lua)?;
+        lua.trace_api(|| format!("Table::get({})", key.to_trace_string()));
 
         let value = unsafe {
             let _sg = StackGuard::new(lua.state);
@@ -113,6 +138,119 @@ impl<'lua> Table<'lua> {
         V::from_lua(value, lua)
     }
 
+    /// Gets the function stored under `key`.
+    ///
+    /// This is a shortcut for [`get`] with a [`Function`] target, but reports a descriptive
+    /// error naming the field and the type actually found when the value is not callable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let handlers = lua.create_table()?;
+    /// handlers.set("on_event", lua.create_function(|_, ()| Ok(()))?)?;
+    ///
+    /// let on_event = handlers.get_function("on_event")?;
+    /// on_event.call::<_, ()>(())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: #method.get
+    pub fn get_function(&self, key: &str) -> Result<Function<'lua>> {
+        match self.get::<_, Value>(key)? {
+            Value::Function(func) => Ok(func),
+            value => Err(Error::RuntimeError(format!(
+                "field '{}' is not a function (got {})",
+                key,
+                value.type_name()
+            ))),
+        }
+    }
+
+    /// Gets the table stored under `key`.
+    ///
+    /// This is a shortcut for [`get`] with a [`Table`] target, but reports a descriptive error
+    /// naming the field and the type actually found when the value is not a table.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config: Table = lua.load("{server = {port = 8080}}").eval()?;
+    ///
+    /// let port: u16 = config.get_table("server")?.get("port")?;
+    /// assert_eq!(port, 8080);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: #method.get
+    pub fn get_table(&self, key: &str) -> Result<Table<'lua>> {
+        match self.get::<_, Value>(key)? {
+            Value::Table(table) => Ok(table),
+            value => Err(Error::RuntimeError(format!(
+                "field '{}' is not a table (got {})",
+                key,
+                value.type_name()
+            ))),
+        }
+    }
+
+    /// Validates the table fields against a schema.
+    ///
+    /// Each schema entry is a `(key, expected type, required)` tuple. Fields are read using
+    /// [`get`], a `nil` value is treated as missing. Returns an error describing the first
+    /// missing required key or field of unexpected type. Integers are accepted where a
+    /// [`Type::Number`] is expected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table, Type};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config: Table = lua.load("{ name = 'server', port = 8080 }").eval()?;
+    /// config.validate(&[
+    ///     ("name", Type::String, true),
+    ///     ("port", Type::Integer, true),
+    ///     ("timeout", Type::Number, false),
+    /// ])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: #method.get
+    /// [`Type::Number`]: crate::Type::Number
+    pub fn validate(&self, schema: &[(&str, Type, bool)]) -> Result<()> {
+        for &(key, expected, required) in schema {
+            let value = self.get::<_, Value>(key)?;
+            if let Value::Nil = value {
+                if required {
+                    return Err(Error::RuntimeError(format!(
+                        "missing required key '{}' (expected {})",
+                        key,
+                        expected.name()
+                    )));
+                }
+                continue;
+            }
+            if !expected.accepts(value.value_type()) {
+                return Err(Error::RuntimeError(format!(
+                    "invalid type of key '{}' (expected {}, got {})",
+                    key,
+                    expected.name(),
+                    value.type_name()
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Checks whether the table contains a non-nil value for `key`.
     pub fn contains_key<K: ToLua<'lua>>(&self, key: K) -> Result<bool> {
         let lua = self.0.lua;
@@ -185,6 +323,101 @@ impl<'lua> Table<'lua> {
         Ok(false)
     }
 
+    /// Compares two tables by their top-level key-value pairs, without invoking metamethods.
+    ///
+    /// Tables are equal if they have the same set of keys, mapping to equal values.
+    /// Nested tables (and other reference types) are compared by identity and not recursively.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table1 = lua.create_sequence_from(vec![1, 2, 3])?;
+    /// let table2 = lua.create_sequence_from(vec![1, 2, 3])?;
+    ///
+    /// assert!(table1 != table2);
+    /// assert!(table1.shallow_eq(&table2)?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn shallow_eq(&self, other: &Table<'lua>) -> Result<bool> {
+        if self == other {
+            return Ok(true);
+        }
+
+        let mut count = 0;
+        for pair in self.clone().pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            if other.raw_get::<_, Value>(key)? != value {
+                return Ok(false);
+            }
+            count += 1;
+        }
+
+        // Check that `other` does not have extra keys
+        let mut other_count = 0;
+        for pair in other.clone().pairs::<Value, Value>() {
+            pair?;
+            other_count += 1;
+            if other_count > count {
+                return Ok(false);
+            }
+        }
+
+        Ok(count == other_count)
+    }
+
+    /// Registers Rust functions in the table under the given names.
+    ///
+    /// All functions are created first and then stored in the table (without invoking
+    /// metamethods) in a single protected call. This is a shorthand for defining modules with
+    /// many functions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, MultiValue, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// fn hello<'lua>(lua: &'lua Lua, _: MultiValue<'lua>) -> Result<MultiValue<'lua>> {
+    ///     lua.pack_multi("hello")
+    /// }
+    ///
+    /// let module = lua.create_table()?;
+    /// module.set_functions(&[("hello", hello)])?;
+    /// lua.globals().set("module", module)?;
+    /// assert_eq!(lua.load("module.hello()").eval::<String>()?, "hello");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_functions(&self, funcs: &[(&str, PlainFunction)]) -> Result<()> {
+        let lua = self.0.lua;
+        let funcs = funcs
+            .iter()
+            .map(|&(name, func)| Ok((name, lua.create_function(func)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            let nvalues = (funcs.len() * 2 + 1) as c_int;
+            check_stack(lua.state, nvalues + 3)?;
+
+            lua.push_ref(&self.0);
+            for (name, func) in &funcs {
+                push_string(lua.state, name)?;
+                lua.push_ref(&func.0);
+            }
+            let count = funcs.len();
+            protect_lua!(lua.state, nvalues, 0, |state| {
+                for _ in 0..count {
+                    ffi::lua_rawset(state, 1);
+                }
+            })
+        }
+    }
+
     /// Sets a key-value pair without invoking metamethods.
     pub fn raw_set<K: ToLua<'lua>, V: ToLua<'lua>>(&self, key: K, value: V) -> Result<()> {
         let lua = self.0.lua;
@@ -247,6 +480,176 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Appends all values from the iterator to the end of the table's array part.
+    ///
+    /// The values are stored starting from index `raw_len() + 1`, without invoking metamethods.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table = lua.create_sequence_from(vec![1, 2])?;
+    /// table.extend(3..=5)?;
+    /// assert_eq!(table.raw_sequence_values().collect::<Result<Vec<i32>>>()?, vec![1, 2, 3, 4, 5]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn extend<T, I>(&self, iter: I) -> Result<()>
+    where
+        T: ToLua<'lua>,
+        I: IntoIterator<Item = T>,
+    {
+        let lua = self.0.lua;
+        let size = self.raw_len();
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, 5)?;
+
+            lua.push_ref(&self.0);
+            for (i, v) in iter.into_iter().enumerate() {
+                lua.push_value(v.to_lua(lua)?)?;
+                protect_lua!(lua.state, 2, 1, |state| {
+                    ffi::lua_rawseti(state, -2, size + 1 + i as Integer);
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sorts the sequence part of the table using a Rust comparator.
+    ///
+    /// The values are read into a `Vec`, sorted with a stable sort and written back, all without
+    /// invoking metamethods. Unlike Lua's `table.sort`, this does not require the `table`
+    /// standard library. Only values up to the first `nil` (see [`raw_sequence_values`]) are
+    /// sorted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table = lua.create_sequence_from(vec![3, 1, 2])?;
+    /// table.sort(|a: &i64, b: &i64| b.cmp(a))?;
+    /// assert_eq!(table.raw_get::<_, i64>(1)?, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`raw_sequence_values`]: #method.raw_sequence_values
+    pub fn sort<T, F>(&self, cmp: F) -> Result<()>
+    where
+        T: FromLua<'lua> + ToLua<'lua>,
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        let mut values = self
+            .clone()
+            .raw_sequence_values::<T>()
+            .collect::<Result<Vec<_>>>()?;
+        values.sort_by(cmp);
+        for (i, value) in values.into_iter().enumerate() {
+            self.raw_set(i as Integer + 1, value)?;
+        }
+        Ok(())
+    }
+
+    /// Swaps the values at indices `i` and `j`, without invoking metamethods.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table = lua.create_sequence_from(vec![1, 2, 3])?;
+    /// table.swap(1, 3)?;
+    /// assert_eq!(table.raw_get::<_, i64>(1)?, 3);
+    /// assert_eq!(table.raw_get::<_, i64>(3)?, 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn swap(&self, i: usize, j: usize) -> Result<()> {
+        let a = self.raw_get::<_, Value>(i)?;
+        let b = self.raw_get::<_, Value>(j)?;
+        self.raw_set(i, b)?;
+        self.raw_set(j, a)
+    }
+
+    /// Moves `count` entries starting at index `from` to index `to`, without invoking
+    /// metamethods.
+    ///
+    /// This is the equivalent of Lua's `table.move(t, from, from + count - 1, to)`: values are
+    /// copied so the destination range may overlap the source range, and source entries outside
+    /// the destination range are left as is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table = lua.create_sequence_from(vec![1, 2, 3, 4])?;
+    /// table.move_entries(1, 3, 2)?;
+    /// let values = table.raw_sequence_values::<i64>().collect::<Result<Vec<_>>>()?;
+    /// assert_eq!(values, vec![1, 1, 2, 3]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn move_entries(&self, from: usize, count: usize, to: usize) -> Result<()> {
+        let copy = |i: usize| -> Result<()> {
+            let value = self.raw_get::<_, Value>(from + i)?;
+            self.raw_set(to + i, value)
+        };
+        // Copy backwards if the destination overlaps the end of the source range
+        if to > from && to < from + count {
+            (0..count).rev().try_for_each(copy)
+        } else {
+            (0..count).try_for_each(copy)
+        }
+    }
+
+    /// Retains only the entries specified by the predicate.
+    ///
+    /// Removes all pairs `(k, v)` for which `f(&k, &v)` returns `false`, by setting them to nil
+    /// (without invoking metamethods). The table is traversed before any removal happens, so
+    /// the predicate may freely read the table.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table = lua.create_table_from(vec![("a", 1), ("b", 2), ("c", 3)])?;
+    /// table.retain(|_: &Value, v: &i32| Ok(*v > 1))?;
+    /// assert!(!table.contains_key("a")?);
+    /// assert!(table.contains_key("b")? && table.contains_key("c")?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn retain<K, V, F>(&self, mut f: F) -> Result<()>
+    where
+        K: FromLua<'lua>,
+        V: FromLua<'lua>,
+        F: FnMut(&K, &V) -> Result<bool>,
+    {
+        let lua = self.0.lua;
+        let pairs = self
+            .clone()
+            .pairs::<Value, Value>()
+            .collect::<Result<Vec<_>>>()?;
+        for (key, value) in pairs {
+            let k = K::from_lua(key.clone(), lua)?;
+            let v = V::from_lua(value, lua)?;
+            if !f(&k, &v)? {
+                self.raw_set(key, Nil)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Removes a key from the table.
     ///
     /// If `key` is an integer, mlua shifts down the elements from `table[key+1]`,
@@ -310,6 +713,146 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Copies the sequence part of the table into a preallocated slice of numbers.
+    ///
+    /// Elements are read with `lua_rawgeti` (without invoking metamethods) starting from index 1,
+    /// until either `out` is full or a `nil` value is found. Returns the number of copied
+    /// elements. Fails if a non-numeric element is encountered.
+    ///
+    /// This is considerably faster than converting the table to a `Vec<f64>` as no intermediate
+    /// [`Value`]s are created.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table = lua.create_sequence_from(vec![1.0, 2.5, 4.0])?;
+    /// let mut buf = [0.0; 8];
+    /// let n = table.copy_into_slice(&mut buf)?;
+    /// assert_eq!(&buf[..n], &[1.0, 2.5, 4.0]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Value`]: crate::Value
+    pub fn copy_into_slice(&self, out: &mut [Number]) -> Result<usize> {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, 2)?;
+
+            lua.push_ref(&self.0);
+            for (i, slot) in out.iter_mut().enumerate() {
+                ffi::lua_rawgeti(lua.state, -1, i as Integer + 1);
+                match ffi::lua_type(lua.state, -1) {
+                    ffi::LUA_TNUMBER => *slot = ffi::lua_tonumber(lua.state, -1),
+                    ffi::LUA_TNIL => return Ok(i),
+                    _ => {
+                        return Err(Error::FromLuaConversionError {
+                            from: lua.pop_value().type_name(),
+                            to: "Number",
+                            message: Some(format!("expected number at index {}", i + 1)),
+                        })
+                    }
+                }
+                ffi::lua_pop(lua.state, 1);
+            }
+            Ok(out.len())
+        }
+    }
+
+    /// Unpacks the sequence part of the table into multiple values, like Lua `table.unpack` does.
+    ///
+    /// Elements with indices from 1 to the table length are converted as if they were multiple
+    /// values returned from a function, so tuples can be used as the target type.
+    ///
+    /// This might invoke the `__len` and `__index` metamethods.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load(r#"{1, "two", 3.0}"#).eval()?;
+    /// let (a, b, c): (i64, String, f64) = table.unpack()?;
+    /// assert_eq!((a, b.as_str(), c), (1, "two", 3.0));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn unpack<T: FromLuaMulti<'lua>>(&self) -> Result<T> {
+        let lua = self.0.lua;
+        let mut values = Vec::new();
+        for i in 1..=self.len()? {
+            values.push(self.get::<_, Value>(i)?);
+        }
+        T::from_lua_multi(MultiValue::from_vec(values), lua)
+    }
+
+    /// Removes all entries from the table, returning them as a vector of pairs.
+    ///
+    /// The pairs are collected in traversal order (like [`pairs`], without invoking
+    /// metamethods). All of them are converted before the table is cleared, so on a conversion
+    /// error the table is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let queue: Table = lua.load(r#"{"job1", "job2"}"#).eval()?;
+    /// let mut jobs = queue.drain::<i64, String>()?;
+    /// jobs.sort();
+    /// assert_eq!(jobs, vec![(1, "job1".to_string()), (2, "job2".to_string())]);
+    /// assert_eq!(queue.raw_len(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`pairs`]: #method.pairs
+    pub fn drain<K: FromLua<'lua>, V: FromLua<'lua>>(&self) -> Result<Vec<(K, V)>> {
+        let lua = self.0.lua;
+        let mut keys = Vec::new();
+        let mut pairs = Vec::new();
+        for pair in self.clone().pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            keys.push(key.clone());
+            pairs.push((K::from_lua(key, lua)?, V::from_lua(value, lua)?));
+        }
+        for key in keys {
+            self.raw_set(key, Nil)?;
+        }
+        Ok(pairs)
+    }
+
+    /// Returns the total number of entries in the table, including both sequence and hash parts.
+    ///
+    /// Unlike [`len`] and [`raw_len`], which return the length of the sequence part, this
+    /// traverses the whole table (without invoking metamethods) and counts all key-value pairs.
+    ///
+    /// [`len`]: #method.len
+    /// [`raw_len`]: #method.raw_len
+    pub fn count(&self) -> Result<usize> {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, 4)?;
+
+            lua.push_ref(&self.0);
+            ffi::lua_pushnil(lua.state);
+            let mut count = 0;
+            // Keys come from `lua_next` itself, so traversal cannot fail
+            while ffi::lua_next(lua.state, -2) != 0 {
+                ffi::lua_pop(lua.state, 1);
+                count += 1;
+            }
+            Ok(count)
+        }
+    }
+
     /// Returns a reference to the metatable of this table, or `None` if no metatable is set.
     ///
     /// Unlike the `getmetatable` Lua function, this method ignores the `__metatable` field.
@@ -348,6 +891,52 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Sets a Rust callback to be called when this table is garbage collected.
+    ///
+    /// The callback is attached as a `__gc` metamethod to a new metatable of the table. If the
+    /// table already has a metatable, its fields are copied to the new one, so the table keeps
+    /// its behavior, but it no longer shares the metatable with other tables (eg. instances of
+    /// the same class). This way the callback is only called for this table.
+    ///
+    /// Returns an error if the metatable already has a `__gc` field, an existing finalizer is
+    /// never replaced.
+    ///
+    /// The callback is called at most once. Panics inside the callback are caught and ignored,
+    /// in the same way as errors in finalizers.
+    ///
+    /// Requires `feature = "lua54/lua53/lua52"`
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    pub fn set_gc_callback<F>(&self, callback: F) -> Result<()>
+    where
+        F: 'static + MaybeSend + FnOnce(&Lua),
+    {
+        let lua = self.0.lua;
+        let metatable = lua.create_table()?;
+        if let Some(shared) = self.get_metatable() {
+            if shared.raw_get::<_, Value>("__gc")? != Value::Nil {
+                return Err(Error::RuntimeError(
+                    "table metatable already has a __gc metamethod".to_string(),
+                ));
+            }
+            for pair in shared.pairs::<Value, Value>() {
+                let (key, value) = pair?;
+                metatable.raw_set(key, value)?;
+            }
+        }
+
+        let callback = RefCell::new(Some(callback));
+        let gc = lua.create_function(move |lua, _: Value| {
+            if let Some(callback) = callback.borrow_mut().take() {
+                let _ = catch_unwind(AssertUnwindSafe(|| callback(lua)));
+            }
+            Ok(())
+        })?;
+        metatable.raw_set("__gc", gc)?;
+        // Lua marks the table for finalization only when setting a metatable with `__gc` field
+        self.set_metatable(Some(metatable));
+        Ok(())
+    }
+
     /// Sets `readonly` attribute on the table.
     ///
     /// Requires `feature = "luau"`
@@ -376,6 +965,132 @@ impl<'lua> Table<'lua> {
         unsafe { lua.ref_thread_exec(|refthr| ffi::lua_getreadonly(refthr, self.0.index) != 0) }
     }
 
+    /// Returns the entries of a table created by [`Lua::create_ordered_table`] in insertion order.
+    ///
+    /// Fails if the table was not created by [`Lua::create_ordered_table`].
+    ///
+    /// [`Lua::create_ordered_table`]: crate::Lua::create_ordered_table
+    pub fn pairs_ordered<K: FromLua<'lua>, V: FromLua<'lua>>(&self) -> Result<Vec<(K, V)>> {
+        let lua = self.0.lua;
+        let mt = self.get_metatable();
+        let (storage, keys) = match mt {
+            Some(ref mt) => match (mt.raw_get("__index")?, mt.raw_get(ORDERED_TABLE_KEYS)?) {
+                (Value::Table(storage), Value::Table(keys)) => (storage, keys),
+                _ => return Err(Error::RuntimeError("table is not ordered".to_string())),
+            },
+            None => return Err(Error::RuntimeError("table is not ordered".to_string())),
+        };
+
+        let mut pairs = Vec::new();
+        for key in keys.raw_sequence_values::<Value>() {
+            let key = key?;
+            let value = storage.raw_get::<_, Value>(key.clone())?;
+            pairs.push((K::from_lua(key, lua)?, V::from_lua(value, lua)?));
+        }
+        Ok(pairs)
+    }
+
+    /// Returns a view of the table that keeps keys explicitly set to `nil`.
+    ///
+    /// See [`NullableTable`] for more details.
+    pub fn nullable(self) -> NullableTable<'lua> {
+        NullableTable(self)
+    }
+
+    /// Returns a read-only proxy of the table.
+    ///
+    /// Reads from the proxy are forwarded to this table via `__index`, while any assignment
+    /// raises an error. The original table is not modified and changes to it are visible through
+    /// the proxy. Nested tables are returned as is and can still be modified, use
+    /// [`freeze_deep`] to freeze them as well.
+    ///
+    /// The proxy itself is empty: the length operator works through the `__len` metamethod
+    /// (not available for tables in Lua 5.1/LuaJIT), but raw access and `next` see no entries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let data = lua.create_table_from(vec![("answer", 42)])?;
+    /// lua.globals().set("data", data.freeze()?)?;
+    ///
+    /// assert_eq!(lua.load("data.answer").eval::<i64>()?, 42);
+    /// assert!(lua.load("data.answer = 0").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`freeze_deep`]: #method.freeze_deep
+    pub fn freeze(&self) -> Result<Table<'lua>> {
+        self.freeze_with_cache(None)
+    }
+
+    /// Returns a read-only proxy of the table, also freezing nested tables.
+    ///
+    /// Works like [`freeze`], but table values read through the proxy are frozen too (each
+    /// nested table gets a single proxy, cached for subsequent reads).
+    ///
+    /// [`freeze`]: #method.freeze
+    pub fn freeze_deep(&self) -> Result<Table<'lua>> {
+        let cache = self.0.lua.create_table()?;
+        self.freeze_with_cache(Some(cache))
+    }
+
+    fn freeze_with_cache(&self, cache: Option<Table<'lua>>) -> Result<Table<'lua>> {
+        let lua = self.0.lua;
+
+        // The original table and the cache are kept in the proxy metatable (hidden from Lua by
+        // `__metatable`), so they are collected together with the proxy.
+        let mt = lua.create_table_with_capacity(0, 6)?;
+        mt.raw_set("__mlua_original", self.clone())?;
+        match cache {
+            None => mt.raw_set("__index", self.clone())?,
+            Some(cache) => {
+                mt.raw_set("__mlua_cache", cache)?;
+                let index = lua.create_function(|_, (proxy, key): (Table, Value)| {
+                    let mt = proxy.frozen_metatable()?;
+                    let original: Table = mt.raw_get("__mlua_original")?;
+                    match original.get::<_, Value>(key)? {
+                        Value::Table(t) => {
+                            let cache: Table = mt.raw_get("__mlua_cache")?;
+                            if let Some(frozen) = cache.raw_get::<_, Option<Table>>(t.clone())? {
+                                return Ok(Value::Table(frozen));
+                            }
+                            let frozen = t.freeze_with_cache(Some(cache.clone()))?;
+                            cache.raw_set(t, frozen.clone())?;
+                            Ok(Value::Table(frozen))
+                        }
+                        value => Ok(value),
+                    }
+                })?;
+                mt.raw_set("__index", index)?;
+            }
+        }
+        let newindex = lua.create_function(|_, ()| -> Result<()> {
+            Err(Error::RuntimeError(
+                "attempt to modify a frozen table".to_string(),
+            ))
+        })?;
+        mt.raw_set("__newindex", newindex)?;
+        let len = lua.create_function(|_, proxy: Table| {
+            let original: Table = proxy.frozen_metatable()?.raw_get("__mlua_original")?;
+            original.len()
+        })?;
+        mt.raw_set("__len", len)?;
+        mt.raw_set("__metatable", false)?;
+
+        let proxy = lua.create_table()?;
+        proxy.set_metatable(Some(mt));
+        Ok(proxy)
+    }
+
+    fn frozen_metatable(&self) -> Result<Table<'lua>> {
+        self.get_metatable()
+            .ok_or_else(|| Error::RuntimeError("frozen table has no metatable".to_string()))
+    }
+
     /// Consume this table and return an iterator over the pairs of the table.
     ///
     /// This works like the Lua `pairs` function, but does not invoke the `__pairs` metamethod.
@@ -528,6 +1243,82 @@ impl<'lua> AsRef<Table<'lua>> for Table<'lua> {
     }
 }
 
+static NULL_SENTINEL: u8 = 0;
+
+/// A view of a [`Table`] that distinguishes keys set to `nil` from absent keys.
+///
+/// In Lua setting a key to `nil` removes it from the table. A `NullableTable` instead stores
+/// `nil` values as a sentinel light userdata, so the key stays present: [`contains_key`] reports
+/// it, while [`get`] translates the sentinel back to `nil`. Use [`remove`] to actually delete a
+/// key.
+///
+/// Lua code accessing the underlying table sees the sentinel, which is the same as
+/// [`NullableTable::null`].
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Nil, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// let table = lua.create_table()?.nullable();
+/// table.set("middle_name", Nil)?;
+/// assert!(table.contains_key("middle_name")?);
+/// assert_eq!(table.get::<_, Option<String>>("middle_name")?, None);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`contains_key`]: #method.contains_key
+/// [`get`]: #method.get
+/// [`remove`]: #method.remove
+#[derive(Clone, Debug)]
+pub struct NullableTable<'lua>(Table<'lua>);
+
+impl<'lua> NullableTable<'lua> {
+    /// Returns the sentinel value used to store explicit `nil`s.
+    pub fn null() -> Value<'static> {
+        Value::LightUserData(LightUserData(&NULL_SENTINEL as *const u8 as *mut c_void))
+    }
+
+    /// Sets a key-value pair, storing `nil` values as the sentinel.
+    ///
+    /// This might invoke the `__newindex` metamethod.
+    pub fn set<K: ToLua<'lua>, V: ToLua<'lua>>(&self, key: K, value: V) -> Result<()> {
+        let value = match value.to_lua(self.0 .0.lua)? {
+            Nil => Self::null(),
+            value => value,
+        };
+        self.0.set(key, value)
+    }
+
+    /// Gets the value associated to `key`, translating the sentinel to `nil`.
+    ///
+    /// This might invoke the `__index` metamethod.
+    pub fn get<K: ToLua<'lua>, V: FromLua<'lua>>(&self, key: K) -> Result<V> {
+        let lua = self.0 .0.lua;
+        match self.0.get::<_, Value>(key)? {
+            value if value == Self::null() => V::from_lua(Nil, lua),
+            value => V::from_lua(value, lua),
+        }
+    }
+
+    /// Checks whether the table contains `key`, including keys explicitly set to `nil`.
+    pub fn contains_key<K: ToLua<'lua>>(&self, key: K) -> Result<bool> {
+        self.0.contains_key(key)
+    }
+
+    /// Removes `key` from the table.
+    pub fn remove<K: ToLua<'lua>>(&self, key: K) -> Result<()> {
+        self.0.set(key, Nil)
+    }
+
+    /// Returns the underlying table.
+    pub fn into_inner(self) -> Table<'lua> {
+        self.0
+    }
+}
+
 /// An extension trait for `Table`s that provides a variety of convenient functionality.
 pub trait TableExt<'lua> {
     /// Calls the table as function assuming it has `__call` metamethod.
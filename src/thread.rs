@@ -1,11 +1,16 @@
 use std::cmp;
-use std::os::raw::c_int;
+#[cfg(feature = "lua54")]
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::sync::mpsc::Sender;
 
 use crate::error::{Error, Result};
 use crate::ffi;
 use crate::types::LuaRef;
-use crate::util::{check_stack, error_traceback, pop_error, StackGuard};
-use crate::value::{FromLuaMulti, ToLuaMulti};
+use crate::util::{
+    check_stack, error_traceback, pop_error, push_string, StackGuard, THREAD_NAMES_KEY,
+};
+use crate::value::{FromLua, FromLuaMulti, MultiValue, ToLuaMulti};
 
 #[cfg(any(
     feature = "lua54",
@@ -18,7 +23,7 @@ use crate::function::Function;
 use {
     crate::{
         lua::{Lua, ASYNC_POLL_PENDING},
-        value::{MultiValue, Value},
+        value::Value,
     },
     futures_core::{future::Future, stream::Stream},
     std::{
@@ -152,6 +157,37 @@ impl<'lua> Thread<'lua> {
         R::from_lua_multi(results, lua)
     }
 
+    /// Sets a name for the thread, to help identify it in error tracebacks.
+    ///
+    /// Error messages of runtime errors raised inside the thread will include the name.
+    /// Names are stored in a weak-keyed table in the Lua registry, so they don't prevent threads
+    /// from being garbage collected.
+    pub fn set_name(&self, name: &str) -> Result<()> {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, 6)?;
+
+            lua.push_ref(&self.0);
+            push_string(lua.state, name)?;
+            protect_lua!(lua.state, 2, 0, |state| {
+                let names_key = &THREAD_NAMES_KEY as *const u8 as *const c_void;
+                if ffi::lua_rawgetp(state, ffi::LUA_REGISTRYINDEX, names_key) != ffi::LUA_TTABLE {
+                    ffi::lua_pop(state, 1);
+                    ffi::lua_createtable(state, 0, 1);
+                    ffi::lua_createtable(state, 0, 1);
+                    ffi::lua_pushstring(state, cstr!("k"));
+                    ffi::lua_setfield(state, -2, cstr!("__mode"));
+                    ffi::lua_setmetatable(state, -2);
+                    ffi::lua_pushvalue(state, -1);
+                    ffi::lua_rawsetp(state, ffi::LUA_REGISTRYINDEX, names_key);
+                }
+                ffi::lua_insert(state, -3);
+                ffi::lua_rawset(state, -3);
+            })
+        }
+    }
+
     /// Gets the status of the thread.
     pub fn status(&self) -> ThreadStatus {
         let lua = self.0.lua;
@@ -170,6 +206,22 @@ impl<'lua> Thread<'lua> {
         }
     }
 
+    /// Returns `true` if the thread can yield.
+    ///
+    /// A thread cannot yield if it is the main thread, or if it is running a function called
+    /// through a non-yieldable C boundary (eg. a Rust function calling back into Lua).
+    ///
+    /// Requires `feature = "lua54/lua53/luau"`
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+    pub fn is_yieldable(&self) -> bool {
+        let lua = self.0.lua;
+        unsafe {
+            let thread_state =
+                lua.ref_thread_exec(|ref_thread| ffi::lua_tothread(ref_thread, self.0.index));
+            ffi::lua_isyieldable(thread_state) != 0
+        }
+    }
+
     /// Resets a thread
     ///
     /// In [Lua 5.4]: cleans its call stack and closes all pending to-be-closed variables.
@@ -224,6 +276,102 @@ impl<'lua> Thread<'lua> {
         }
     }
 
+    /// Closes the thread, like `coroutine.close` does.
+    ///
+    /// Cleans the thread call stack and closes all pending to-be-closed variables, running their
+    /// `__close` metamethods. Returns an error in case of either the original error that stopped
+    /// the thread or errors in closing methods. After closing, the thread is dead.
+    ///
+    /// Only suspended or dead threads can be closed. Closing the running thread, or a thread that
+    /// resumed another one (eg. the main thread while a coroutine is running), returns an error.
+    ///
+    /// This is useful for cleaning up suspended coroutines that are not going to be resumed.
+    ///
+    /// Requires `feature = "lua54"`
+    #[cfg(feature = "lua54")]
+    pub fn close(&self) -> Result<()> {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, 1)?;
+
+            lua.push_ref(&self.0);
+            let thread_state = ffi::lua_tothread(lua.state, -1);
+            if thread_state == lua.state {
+                return Err(Error::RuntimeError(
+                    "cannot close a running coroutine".to_string(),
+                ));
+            }
+            // Like `coroutine.close`, only suspended or dead threads can be closed
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            if ffi::lua_status(thread_state) == ffi::LUA_OK
+                && ffi::lua_getstack(thread_state, 0, &mut ar) != 0
+            {
+                return Err(Error::RuntimeError(
+                    "cannot close a normal coroutine".to_string(),
+                ));
+            }
+
+            let status = ffi::lua_resetthread(thread_state);
+            if status != ffi::LUA_OK {
+                return Err(pop_error(thread_state, status));
+            }
+            Ok(())
+        }
+    }
+
+    /// Drives the thread to completion, sending each yielded value to the `tx` channel.
+    ///
+    /// `args` are passed as arguments to the thread function for the first resume, subsequent
+    /// resumes are done without arguments. Values returned from the thread function are discarded.
+    ///
+    /// Stops when the thread finishes, the receiver is disconnected, or an error occurs.
+    /// In the last case the error is sent to the channel as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Thread};
+    /// use std::sync::mpsc;
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let thread: Thread = lua.load(r#"
+    ///     coroutine.create(function(n)
+    ///         for i = 1, n do
+    ///             coroutine.yield(i)
+    ///         end
+    ///     end)
+    /// "#).eval()?;
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// thread.drain_into::<_, i64>(tx, 3);
+    /// assert_eq!(rx.iter().collect::<Result<Vec<_>>>()?, vec![1, 2, 3]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn drain_into<A, T>(self, tx: Sender<Result<T>>, args: A)
+    where
+        A: ToLuaMulti<'lua>,
+        T: FromLua<'lua>,
+    {
+        let lua = self.0.lua;
+        let mut args = args.to_lua_multi(lua);
+        while self.status() == ThreadStatus::Resumable {
+            let values = args.and_then(|args| self.resume::<_, MultiValue>(args));
+            args = Ok(MultiValue::new());
+            let value = match values {
+                // The thread has returned from its main function
+                Ok(_) if self.status() != ThreadStatus::Resumable => break,
+                Ok(values) => T::from_lua_multi(values, lua),
+                Err(err) => Err(err),
+            };
+            let is_err = value.is_err();
+            if tx.send(value).is_err() || is_err {
+                break;
+            }
+        }
+    }
+
     /// Converts Thread to an AsyncThread which implements [`Future`] and [`Stream`] traits.
     ///
     /// `args` are passed as arguments to the thread function for first call.
@@ -2,6 +2,7 @@ use std::cell::UnsafeCell;
 use std::hash::{Hash, Hasher};
 use std::os::raw::{c_int, c_void};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::{fmt, mem, ptr};
 
 #[cfg(feature = "lua54")]
@@ -14,9 +15,9 @@ use crate::error::Result;
 use crate::ffi;
 #[cfg(not(feature = "luau"))]
 use crate::hook::Debug;
-use crate::lua::{ExtraData, Lua};
-use crate::util::{assert_stack, StackGuard};
-use crate::value::MultiValue;
+use crate::lua::{AllocAction, ExtraData, GcPhase, Lua};
+use crate::util::{assert_stack, check_stack, StackGuard};
+use crate::value::{FromLua, MultiValue, Value};
 
 /// Type of Lua integer numbers.
 pub type Integer = ffi::lua_Integer;
@@ -30,6 +31,10 @@ pub struct LightUserData(pub *mut c_void);
 pub(crate) type Callback<'lua, 'a> =
     Box<dyn Fn(&'lua Lua, MultiValue<'lua>) -> Result<MultiValue<'lua>> + 'a>;
 
+// Plain Rust function that can be registered as a Lua callback (see `Table::set_functions`)
+pub(crate) type PlainFunction =
+    for<'lua> fn(&'lua Lua, MultiValue<'lua>) -> Result<MultiValue<'lua>>;
+
 pub(crate) struct Upvalue<T> {
     pub(crate) data: T,
     pub(crate) extra: Arc<UnsafeCell<ExtraData>>,
@@ -47,9 +52,9 @@ pub(crate) type AsyncCallbackUpvalue = Upvalue<AsyncCallback<'static, 'static>>;
 #[cfg(feature = "async")]
 pub(crate) type AsyncPollUpvalue = Upvalue<LocalBoxFuture<'static, Result<MultiValue<'static>>>>;
 
-/// Type to set next Luau VM action after executing interrupt function.
-#[cfg(any(feature = "luau", doc))]
-#[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+/// Type to set next Lua VM action after executing interrupt or hook function.
+#[cfg(any(feature = "luau", feature = "lua54", doc))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "luau", feature = "lua54"))))]
 pub enum VmState {
     Continue,
     Yield,
@@ -73,6 +78,56 @@ pub(crate) type WarnCallback = Box<dyn Fn(&Lua, &CStr, bool) -> Result<()> + Sen
 #[cfg(all(not(feature = "send"), feature = "lua54"))]
 pub(crate) type WarnCallback = Box<dyn Fn(&Lua, &CStr, bool) -> Result<()>>;
 
+#[cfg(feature = "send")]
+pub(crate) type SourceMapCallback = Arc<dyn Fn(u32) -> (String, u32) + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type SourceMapCallback = Arc<dyn Fn(u32) -> (String, u32)>;
+
+#[cfg(all(feature = "trace", feature = "send"))]
+pub(crate) type ApiTraceCallback = Arc<dyn Fn(&str) + Send>;
+
+#[cfg(all(feature = "trace", not(feature = "send")))]
+pub(crate) type ApiTraceCallback = Arc<dyn Fn(&str)>;
+
+#[cfg(feature = "send")]
+pub(crate) type TableCreateCallback = Arc<dyn Fn(usize, usize) + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type TableCreateCallback = Arc<dyn Fn(usize, usize)>;
+
+#[cfg(feature = "send")]
+pub(crate) type SlowCallCallback = Arc<dyn Fn(&str, Duration) + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type SlowCallCallback = Arc<dyn Fn(&str, Duration)>;
+
+#[cfg(feature = "send")]
+pub(crate) type SourceTransformCallback = Arc<dyn Fn(&str, &str) -> String + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type SourceTransformCallback = Arc<dyn Fn(&str, &str) -> String>;
+
+#[cfg(feature = "send")]
+pub(crate) type GcObserverCallback = Arc<dyn Fn(GcPhase, Duration) + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type GcObserverCallback = Arc<dyn Fn(GcPhase, Duration)>;
+
+#[cfg(feature = "send")]
+pub(crate) type GlobalResolverCallback =
+    Arc<dyn for<'lua> Fn(&'lua Lua, &str) -> Result<Option<Value<'lua>>> + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type GlobalResolverCallback =
+    Arc<dyn for<'lua> Fn(&'lua Lua, &str) -> Result<Option<Value<'lua>>>>;
+
+#[cfg(feature = "send")]
+pub(crate) type AllocFailureCallback = Box<dyn Fn() -> AllocAction + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type AllocFailureCallback = Box<dyn Fn() -> AllocAction>;
+
 #[cfg(feature = "send")]
 pub trait MaybeSend: Send {}
 #[cfg(feature = "send")]
@@ -148,6 +203,80 @@ impl RegistryKey {
     }
 }
 
+/// A reference to a value in the Lua registry, bound to the lifetime of the `Lua` instance.
+///
+/// This is a safe wrapper around the `luaL_ref` / `luaL_unref` lifecycle, that can be used as a
+/// building block for custom handle types. Unlike [`RegistryKey`], the reference is released
+/// immediately on drop, and cloning creates a new independent reference to the same value.
+///
+/// A `RegistryRef` must only be used with the `Lua` instance that created it (which is enforced
+/// by the lifetime).
+///
+/// [`RegistryKey`]: crate::RegistryKey
+pub struct RegistryRef<'lua> {
+    pub(crate) lua: &'lua Lua,
+    pub(crate) registry_id: c_int,
+}
+
+impl<'lua> RegistryRef<'lua> {
+    /// Returns the raw registry index of the reference.
+    pub fn id(&self) -> c_int {
+        self.registry_id
+    }
+
+    /// Returns the referenced value converted to the type `T`.
+    pub fn get<T: FromLua<'lua>>(&self) -> Result<T> {
+        let lua = self.lua;
+        let value = unsafe {
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, 1)?;
+
+            ffi::lua_rawgeti(
+                lua.state,
+                ffi::LUA_REGISTRYINDEX,
+                self.registry_id as Integer,
+            );
+            lua.pop_value()
+        };
+        T::from_lua(value, lua)
+    }
+}
+
+impl<'lua> fmt::Debug for RegistryRef<'lua> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RegistryRef({})", self.registry_id)
+    }
+}
+
+impl<'lua> Clone for RegistryRef<'lua> {
+    fn clone(&self) -> Self {
+        let lua = self.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 3);
+
+            ffi::lua_rawgeti(
+                lua.state,
+                ffi::LUA_REGISTRYINDEX,
+                self.registry_id as Integer,
+            );
+            let registry_id = mlua_expect!(
+                protect_lua!(lua.state, 1, 0, |state| {
+                    ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+                }),
+                "cannot create a registry reference"
+            );
+            RegistryRef { lua, registry_id }
+        }
+    }
+}
+
+impl<'lua> Drop for RegistryRef<'lua> {
+    fn drop(&mut self) {
+        unsafe { ffi::luaL_unref(self.lua.state, ffi::LUA_REGISTRYINDEX, self.registry_id) };
+    }
+}
+
 pub(crate) struct LuaRef<'lua> {
     pub(crate) lua: &'lua Lua,
     pub(crate) index: c_int,
@@ -4,25 +4,23 @@ use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::ops::{Deref, DerefMut};
 use std::os::raw::{c_char, c_int};
+use std::result::Result as StdResult;
 use std::string::String as StdString;
 
 #[cfg(feature = "async")]
 use std::future::Future;
 
 #[cfg(feature = "serialize")]
-use {
-    serde::ser::{self, Serialize, Serializer},
-    std::result::Result as StdResult,
-};
+use serde::ser::{self, Serialize, Serializer};
 
-use crate::error::{Error, Result};
+use crate::error::{Error, ExternalError, Result};
 use crate::ffi;
 use crate::function::Function;
 use crate::lua::Lua;
 use crate::table::{Table, TablePairs};
-use crate::types::{Callback, LuaRef, MaybeSend};
+use crate::types::{Callback, LuaRef, MaybeSend, Number};
 use crate::util::{check_stack, get_userdata, take_userdata, StackGuard};
-use crate::value::{FromLua, FromLuaMulti, ToLua, ToLuaMulti};
+use crate::value::{FromLua, FromLuaMulti, ToLua, ToLuaMulti, Type, Value};
 
 #[cfg(feature = "async")]
 use crate::types::AsyncCallback;
@@ -285,6 +283,20 @@ impl From<&str> for MetaMethod {
     }
 }
 
+/// Handler for a single operand type registered with [`UserDataMethods::add_meta_method_typed`].
+///
+/// Receives the userdata value and the second operand of the metamethod.
+#[cfg(feature = "send")]
+pub type TypedMetaHandler<T> =
+    Box<dyn for<'lua> Fn(&'lua Lua, &T, Value<'lua>) -> Result<Value<'lua>> + Send>;
+
+/// Handler for a single operand type registered with [`UserDataMethods::add_meta_method_typed`].
+///
+/// Receives the userdata value and the second operand of the metamethod.
+#[cfg(not(feature = "send"))]
+pub type TypedMetaHandler<T> =
+    Box<dyn for<'lua> Fn(&'lua Lua, &T, Value<'lua>) -> Result<Value<'lua>>>;
+
 /// Method registry for [`UserData`] implementors.
 ///
 /// [`UserData`]: crate::UserData
@@ -471,6 +483,106 @@ pub trait UserDataMethods<'lua, T: UserData> {
         F: 'static + MaybeSend + Fn(&'lua Lua, A) -> FR,
         FR: 'lua + Future<Output = Result<R>>;
 
+    /// Adds an `__eq` metamethod that compares the underlying Rust values using `PartialEq`.
+    ///
+    /// By default Lua compares userdata by identity. With this metamethod two distinct userdata
+    /// objects wrapping equal values are considered equal. Userdata of other types are never equal
+    /// to `T`.
+    fn add_value_eq(&mut self)
+    where
+        T: 'static + PartialEq,
+    {
+        self.add_meta_function(MetaMethod::Eq, |_, (a, b): (AnyUserData, AnyUserData)| {
+            let (a, b) = match (a.borrow::<T>(), b.borrow::<T>()) {
+                (Ok(a), Ok(b)) => (a, b),
+                (Err(Error::UserDataTypeMismatch), _) | (_, Err(Error::UserDataTypeMismatch)) => {
+                    return Ok(false)
+                }
+                (Err(err), _) | (_, Err(err)) => return Err(err),
+            };
+            Ok(*a == *b)
+        });
+    }
+
+    /// Add a regular method which accepts a `&T` as the first parameter and returns a domain
+    /// specific error type.
+    ///
+    /// Errors returned from the method are converted to [`Error::ExternalError`], so they are
+    /// kept intact and can be downcast back to `E` after the call fails (the error is wrapped in
+    /// [`Error::CallbackError`] when it passes through Lua).
+    ///
+    /// Refer to [`add_method`] for more information about the implementation.
+    ///
+    /// [`Error::ExternalError`]: crate::Error::ExternalError
+    /// [`Error::CallbackError`]: crate::Error::CallbackError
+    /// [`add_method`]: #method.add_method
+    fn add_method_fallible<S, A, R, E, M>(&mut self, name: &S, method: M)
+    where
+        S: AsRef<[u8]> + ?Sized,
+        A: FromLuaMulti<'lua>,
+        R: ToLuaMulti<'lua>,
+        E: ExternalError,
+        M: 'static + MaybeSend + Fn(&'lua Lua, &T, A) -> StdResult<R, E>,
+    {
+        self.add_method(name, move |lua, this, args| {
+            method(lua, this, args).map_err(ExternalError::to_lua_err)
+        });
+    }
+
+    /// Adds a fallback for methods that are not registered on the userdata.
+    ///
+    /// The fallback is installed as the `__index` metamethod, so it is consulted only when no
+    /// regular method or field is found. It receives the requested name and returns the function
+    /// to use as the method, which is useful for proxy or remote-object patterns. If it returns
+    /// `None`, indexing fails with a descriptive error instead of returning `nil`.
+    ///
+    /// Calling [`add_meta_method`] with `__index` afterwards replaces the fallback.
+    ///
+    /// [`add_meta_method`]: #method.add_meta_method
+    fn add_method_fallback<F>(&mut self, fallback: F)
+    where
+        F: 'static + MaybeSend + Fn(&'lua Lua, &T, StdString) -> Result<Option<Function<'lua>>>,
+    {
+        self.add_meta_method(
+            MetaMethod::Index,
+            move |lua, this, name: StdString| match fallback(lua, this, name.clone())? {
+                Some(func) => Ok(func),
+                None => Err(Error::RuntimeError(format!(
+                    "unknown method '{}' on userdata",
+                    name
+                ))),
+            },
+        );
+    }
+
+    /// Adds a metamethod which dispatches on the type of its second operand.
+    ///
+    /// The first handler in `handlers` whose [`Type`] accepts the second operand is called.
+    /// If none matches, `default` is called instead. This is useful for binary operators where
+    /// e.g. `vec + number` and `vec + vec` must behave differently.
+    ///
+    /// The same caveat as for [`add_meta_method`] applies: the userdata must be the first operand.
+    ///
+    /// [`add_meta_method`]: #method.add_meta_method
+    fn add_meta_method_typed<S, D>(
+        &mut self,
+        meta: S,
+        handlers: Vec<(Type, TypedMetaHandler<T>)>,
+        default: D,
+    ) where
+        T: 'static,
+        S: Into<MetaMethod>,
+        D: 'static + MaybeSend + for<'l> Fn(&'l Lua, &T, Value<'l>) -> Result<Value<'l>>,
+    {
+        self.add_meta_method(meta, move |lua, this, other: Value<'lua>| {
+            let ty = other.value_type();
+            match handlers.iter().find(|(t, _)| t.accepts(ty)) {
+                Some((_, handler)) => handler(lua, this, other),
+                None => default(lua, this, other),
+            }
+        });
+    }
+
     //
     // Below are internal methods used in generated code
     //
@@ -639,9 +751,73 @@ pub trait UserDataFields<'lua, T: UserData> {
 /// # }
 /// ```
 ///
+/// Trait objects can be exposed to Lua as well, by implementing `UserData` for the boxed trait
+/// object. Methods borrow the box and dispatch dynamically to the underlying implementation, so
+/// different types can share the same userdata type:
+///
+/// ```
+/// # use mlua::{Lua, Result, UserData, UserDataMethods};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// trait Shape: Send {
+///     fn area(&self) -> f64;
+/// }
+///
+/// struct Square(f64);
+///
+/// impl Shape for Square {
+///     fn area(&self) -> f64 {
+///         self.0 * self.0
+///     }
+/// }
+///
+/// impl UserData for Box<dyn Shape> {
+///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+///         methods.add_method("area", |_, this, ()| Ok(this.area()));
+///     }
+/// }
+///
+/// lua.globals().set("square", Box::new(Square(2.0)) as Box<dyn Shape>)?;
+/// lua.load("assert(square:area() == 4)").exec()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// To share mutable state between Lua and other Rust threads, the value can be passed to Lua
+/// wrapped in `Arc<Mutex<T>>` (or `Arc<RwLock<T>>`). Methods and fields registered for `T` then
+/// lock the mutex for the duration of each call. The lock is never waited for: if it's held
+/// elsewhere or poisoned, the call fails with [`UserDataBorrowError`] or
+/// [`UserDataBorrowMutError`].
+///
+/// ```
+/// # use std::sync::{Arc, Mutex};
+/// # use mlua::{Lua, Result, UserData, UserDataMethods};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// struct Counter(i32);
+///
+/// impl UserData for Counter {
+///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+///         methods.add_method_mut("increment", |_, this, ()| {
+///             this.0 += 1;
+///             Ok(this.0)
+///         });
+///     }
+/// }
+///
+/// let counter = Arc::new(Mutex::new(Counter(0)));
+/// lua.globals().set("counter", counter.clone())?;
+/// lua.load("counter:increment()").exec()?;
+/// assert_eq!(counter.lock().unwrap().0, 1);
+/// # Ok(())
+/// # }
+/// ```
+///
 /// [`ToLua`]: crate::ToLua
 /// [`FromLua`]: crate::FromLua
 /// [`UserDataFields`]: crate::UserDataFields
+/// [`UserDataBorrowError`]: crate::Error::UserDataBorrowError
+/// [`UserDataBorrowMutError`]: crate::Error::UserDataBorrowMutError
 /// [`UserDataMethods`]: crate::UserDataMethods
 pub trait UserData: Sized {
     /// Adds custom fields specific to this userdata.
@@ -649,6 +825,24 @@ pub trait UserData: Sized {
 
     /// Adds custom methods and operators specific to this userdata.
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(_methods: &mut M) {}
+
+    /// Number of decimal digits used by the default `__tostring` metamethod.
+    ///
+    /// When set, a `__tostring` metamethod rendering [`display_number`] with the given precision
+    /// is registered for this type, unless one is already added in [`add_methods`].
+    ///
+    /// [`display_number`]: #method.display_number
+    /// [`add_methods`]: #method.add_methods
+    const DISPLAY_PRECISION: Option<usize> = None;
+
+    /// Returns the numeric value rendered by the default `__tostring` metamethod.
+    ///
+    /// Used together with [`DISPLAY_PRECISION`].
+    ///
+    /// [`DISPLAY_PRECISION`]: #associatedconstant.DISPLAY_PRECISION
+    fn display_number(&self) -> Option<Number> {
+        None
+    }
 }
 
 // Wraps UserData in a way to always implement `serde::Serialize` trait.
@@ -296,7 +296,8 @@ pub unsafe fn push_userdata<T>(state: *mut ffi::lua_State, t: T) -> Result<()> {
     unsafe extern "C" fn destructor<T>(ud: *mut c_void) {
         let ud = ud as *mut T;
         if *(ud.offset(1) as *mut u8) == 0 {
-            ptr::drop_in_place(ud);
+            // Swallow panics from `Drop` impls, see `userdata_destructor`
+            let _ = catch_unwind(AssertUnwindSafe(|| ptr::drop_in_place(ud)));
         }
     }
 
@@ -560,9 +561,10 @@ pub unsafe fn init_userdata_metatable<T>(
 
 #[cfg(not(feature = "luau"))]
 pub unsafe extern "C" fn userdata_destructor<T>(state: *mut ffi::lua_State) -> c_int {
-    // It's probably NOT a good idea to catch Rust panics in finalizer
-    // Lua 5.4 ignores it, other versions generates `LUA_ERRGCMM` without calling message handler
-    take_userdata::<T>(state);
+    // Rust panics cannot be propagated from finalizers: Lua 5.4 ignores errors in `__gc` and
+    // other versions generate `LUA_ERRGCMM` without calling message handler. Finalizers also run
+    // from `lua_close`, so panics from `Drop` impls are swallowed to not unwind across C frames.
+    let _ = catch_unwind(AssertUnwindSafe(|| drop(take_userdata::<T>(state))));
     0
 }
 
@@ -615,7 +617,7 @@ where
             } else {
                 "<not enough stack space for traceback>".to_string()
             };
-            let cause = Arc::new(err);
+            let cause = Arc::new(resolve_error_level(state, err));
             ptr::write(
                 wrapped_error,
                 WrappedFailure::Error(Error::CallbackError { traceback, cause }),
@@ -635,6 +637,27 @@ where
     }
 }
 
+// Converts `Error::WithLevel` returned from a Rust callback to a runtime error with the position of
+// the function at the given level prepended, similar to `luaL_error`.
+// Level 1 is the current (Rust) function, so it has no position.
+pub(crate) unsafe fn resolve_error_level(state: *mut ffi::lua_State, err: Error) -> Error {
+    match err {
+        Error::WithLevel(err, level) => {
+            let mut location = String::new();
+            if level > 1 && ffi::lua_checkstack(state, 2) != 0 {
+                ffi::luaL_where(state, level - 1);
+                location = to_string(state, -1);
+                ffi::lua_pop(state, 1);
+            }
+            match *err {
+                Error::RuntimeError(msg) => Error::RuntimeError(location + &msg),
+                err => Error::RuntimeError(format!("{}{}", location, err)),
+            }
+        }
+        err => err,
+    }
+}
+
 pub unsafe extern "C" fn error_traceback(state: *mut ffi::lua_State) -> c_int {
     if ffi::lua_checkstack(state, 2) == 0 {
         // If we don't have enough stack space to even check the error type, do
@@ -643,8 +666,15 @@ pub unsafe extern "C" fn error_traceback(state: *mut ffi::lua_State) -> c_int {
     }
 
     if get_gc_userdata::<WrappedFailure>(state, -1).is_null() {
-        let s = ffi::luaL_tolstring(state, -1, ptr::null_mut());
+        let mut s = ffi::luaL_tolstring(state, -1, ptr::null_mut());
         if ffi::lua_checkstack(state, ffi::LUA_TRACEBACK_STACK) != 0 {
+            // Include the thread name (if any) to identify which coroutine failed
+            if push_thread_name(state) {
+                let name = ffi::lua_tostring(state, -1);
+                s = ffi::lua_pushfstring(state, cstr!("%s (in thread '%s')"), s, name);
+                ffi::lua_remove(state, -2);
+                ffi::lua_remove(state, -2);
+            }
             ffi::luaL_traceback(state, state, s, 0);
             ffi::lua_remove(state, -2);
         }
@@ -653,6 +683,25 @@ pub unsafe extern "C" fn error_traceback(state: *mut ffi::lua_State) -> c_int {
     1
 }
 
+// Pushes the name of the running thread (set by `Thread::set_name`) onto the stack.
+// Returns `false` and leaves the stack unchanged if the thread has no name.
+// Uses 2 stack spaces, does not call checkstack.
+pub unsafe fn push_thread_name(state: *mut ffi::lua_State) -> bool {
+    let names_key = &THREAD_NAMES_KEY as *const u8 as *const c_void;
+    if ffi::lua_rawgetp(state, ffi::LUA_REGISTRYINDEX, names_key) != ffi::LUA_TTABLE {
+        ffi::lua_pop(state, 1);
+        return false;
+    }
+    ffi::lua_pushthread(state);
+    ffi::lua_rawget(state, -2);
+    if ffi::lua_type(state, -1) != ffi::LUA_TSTRING {
+        ffi::lua_pop(state, 2);
+        return false;
+    }
+    ffi::lua_remove(state, -2);
+    true
+}
+
 // A variant of `pcall` that does not allow Lua to catch Rust panics from `callback_error`.
 pub unsafe extern "C" fn safe_pcall(state: *mut ffi::lua_State) -> c_int {
     ffi::luaL_checkstack(state, 2, ptr::null());
@@ -923,6 +972,43 @@ pub unsafe fn init_error_registry(state: *mut ffi::lua_State) -> Result<()> {
     Ok(())
 }
 
+// Toggles introspection of the error and panic metatable.
+// When enabled, the metatable is no longer hidden by `__metatable` and an `__index` metamethod
+// exposes the `type` field ("error" or "panic") of wrapped failures.
+// Uses 4 stack spaces, does not call checkstack.
+pub unsafe fn set_error_introspection(state: *mut ffi::lua_State, enabled: bool) -> Result<()> {
+    unsafe extern "C" fn error_index(state: *mut ffi::lua_State) -> c_int {
+        let ty = match get_gc_userdata::<WrappedFailure>(state, 1).as_ref() {
+            Some(WrappedFailure::Error(_)) => cstr!("error"),
+            Some(WrappedFailure::Panic(_)) => cstr!("panic"),
+            _ => return 0,
+        };
+        if ffi::lua_type(state, 2) == ffi::LUA_TSTRING
+            && CStr::from_ptr(ffi::lua_tostring(state, 2)).to_bytes() == b"type"
+        {
+            ffi::lua_pushstring(state, ty);
+            return 1;
+        }
+        0
+    }
+
+    get_gc_metatable::<WrappedFailure>(state);
+    if enabled {
+        ffi::lua_pushnil(state);
+        rawset_field(state, -2, "__metatable")?;
+        ffi::lua_pushcfunction(state, error_index);
+        rawset_field(state, -2, "__index")?;
+    } else {
+        ffi::lua_pushboolean(state, 0);
+        rawset_field(state, -2, "__metatable")?;
+        ffi::lua_pushnil(state);
+        rawset_field(state, -2, "__index")?;
+    }
+    ffi::lua_pop(state, 1);
+
+    Ok(())
+}
+
 pub(crate) enum WrappedFailure {
     None,
     Error(Error),
@@ -996,3 +1082,4 @@ static DESTRUCTED_USERDATA_METATABLE: u8 = 0;
 static ERROR_PRINT_BUFFER_KEY: u8 = 0;
 static USERDATA_METATABLE_INDEX: u8 = 0;
 static USERDATA_METATABLE_NEWINDEX: u8 = 0;
+pub(crate) static THREAD_NAMES_KEY: u8 = 0;
@@ -1,21 +1,30 @@
+use std::hash::{Hash, Hasher};
 use std::iter::{self, FromIterator};
-use std::{slice, str, vec};
+use std::os::raw::c_void;
+use std::string::String as StdString;
+use std::{mem, slice, str, vec};
+
+use num_traits::cast;
 
 #[cfg(feature = "serialize")]
 use {
+    serde::de::{self, Deserialize},
     serde::ser::{self, Serialize, Serializer},
     std::convert::TryInto,
+    std::fmt,
     std::result::Result as StdResult,
 };
 
 use crate::error::{Error, Result};
+use crate::ffi;
 use crate::function::Function;
 use crate::lua::Lua;
 use crate::string::String;
 use crate::table::Table;
 use crate::thread::Thread;
-use crate::types::{Integer, LightUserData, Number};
+use crate::types::{Integer, LightUserData, LuaRef, Number};
 use crate::userdata::AnyUserData;
+use crate::util::{assert_stack, StackGuard};
 
 /// A dynamically typed Lua value. The `String`, `Table`, `Function`, `Thread`, and `UserData`
 /// variants contain handle types into the internal Lua state. It is a logic error to mix handle
@@ -76,6 +85,37 @@ impl<'lua> Value<'lua> {
         }
     }
 
+    // Renders the value for API call tracing
+    pub(crate) fn to_trace_string(&self) -> StdString {
+        match *self {
+            Value::Nil => "nil".to_string(),
+            Value::Boolean(b) => b.to_string(),
+            Value::Integer(i) => i.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::String(ref s) => format!("{:?}", s.to_string_lossy()),
+            ref value => value.type_name().to_string(),
+        }
+    }
+
+    /// Returns the [`Type`] of this value.
+    pub const fn value_type(&self) -> Type {
+        match *self {
+            Value::Nil => Type::Nil,
+            Value::Boolean(_) => Type::Boolean,
+            Value::LightUserData(_) => Type::LightUserData,
+            Value::Integer(_) => Type::Integer,
+            Value::Number(_) => Type::Number,
+            #[cfg(feature = "luau")]
+            Value::Vector(_, _, _) => Type::Vector,
+            Value::String(_) => Type::String,
+            Value::Table(_) => Type::Table,
+            Value::Function(_) => Type::Function,
+            Value::Thread(_) => Type::Thread,
+            Value::UserData(_) => Type::UserData,
+            Value::Error(_) => Type::Error,
+        }
+    }
+
     /// Compares two values for equality.
     ///
     /// Equality comparisons do not convert strings to numbers or vice versa.
@@ -93,6 +133,298 @@ impl<'lua> Value<'lua> {
             _ => Ok(self == other.as_ref()),
         }
     }
+
+    /// Compares two numeric values (integers or floats) for equality within `epsilon` tolerance.
+    ///
+    /// Returns `false` if either of the values is not a number.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::Value;
+    /// assert!(Value::Number(0.1 + 0.2).approx_eq(&Value::Number(0.3), 1e-9));
+    /// assert!(Value::Integer(1).approx_eq(&Value::Number(1.0000001), 1e-6));
+    /// assert!(!Value::Integer(1).approx_eq(&Value::Boolean(true), 1e-6));
+    /// ```
+    pub fn approx_eq(&self, other: &Value, epsilon: f64) -> bool {
+        let as_number = |v: &Value| match *v {
+            Value::Integer(i) => Some(i as f64),
+            Value::Number(n) => Some(n),
+            _ => None,
+        };
+        match (as_number(self), as_number(other)) {
+            (Some(a), Some(b)) => a == b || (a - b).abs() <= epsilon,
+            _ => false,
+        }
+    }
+
+    /// Detaches the value from the Lua state, returning an [`OwnedValue`].
+    ///
+    /// Only primitive values (nil, booleans, numbers and strings) can be detached, string bytes
+    /// are copied. Reference types (tables, functions, threads and userdata) are bound to the Lua
+    /// state and cannot leave it, so an error is returned for them. Plain tables can be copied out
+    /// with [`OwnedValue::from_table`].
+    ///
+    /// [`OwnedValue`]: crate::OwnedValue
+    /// [`OwnedValue::from_table`]: crate::OwnedValue::from_table
+    pub fn into_owned(self) -> Result<OwnedValue> {
+        match self {
+            Value::Table(_) => Err(Error::FromLuaConversionError {
+                from: "table",
+                to: "OwnedValue",
+                message: Some("use `OwnedValue::from_table` to detach tables".to_string()),
+            }),
+            value => OwnedValue::detach(value),
+        }
+    }
+}
+
+/// Type of a Lua [`Value`], without the value itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    /// The type of `nil`.
+    Nil,
+    /// The type of `true` and `false`.
+    Boolean,
+    /// The type of a light userdata.
+    LightUserData,
+    /// The type of an integer number.
+    Integer,
+    /// The type of a floating point number.
+    Number,
+    /// The type of a Luau vector.
+    #[cfg(any(feature = "luau", doc))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    Vector,
+    /// The type of a string.
+    String,
+    /// The type of a table.
+    Table,
+    /// The type of a function.
+    Function,
+    /// The type of a thread (coroutine).
+    Thread,
+    /// The type of a userdata object.
+    UserData,
+    /// The type of a wrapped Rust error.
+    Error,
+}
+
+impl Type {
+    /// Returns the name of this type, as reported by [`Value::type_name`].
+    pub const fn name(self) -> &'static str {
+        match self {
+            Type::Nil => "nil",
+            Type::Boolean => "boolean",
+            Type::LightUserData => "lightuserdata",
+            Type::Integer => "integer",
+            Type::Number => "number",
+            #[cfg(feature = "luau")]
+            Type::Vector => "vector",
+            Type::String => "string",
+            Type::Table => "table",
+            Type::Function => "function",
+            Type::Thread => "thread",
+            Type::UserData => "userdata",
+            Type::Error => "error",
+        }
+    }
+
+    /// Returns `true` if a value of type `other` is acceptable where this type is expected.
+    ///
+    /// This is the same as equality, except that integers are also accepted as numbers.
+    pub fn accepts(self, other: Type) -> bool {
+        self == other || (self == Type::Number && other == Type::Integer)
+    }
+}
+
+/// A primitive Lua value (or a plain table of them) detached from any Lua state.
+///
+/// Unlike [`Value`], it does not hold any handles into the Lua state and can be freely sent
+/// to other threads or moved into another Lua state with [`Lua::transfer_value`]. Created by
+/// [`Value::into_owned`] or [`OwnedValue::from_table`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum OwnedValue {
+    /// The Lua value `nil`.
+    Nil,
+    /// The Lua value `true` or `false`.
+    Boolean(bool),
+    /// An integer number.
+    Integer(Integer),
+    /// A floating point number.
+    Number(Number),
+    /// A Luau vector.
+    #[cfg(any(feature = "luau", doc))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    Vector(f32, f32, f32),
+    /// A Lua string copied out of the Lua state.
+    ///
+    /// Like Lua strings, it may not be valid UTF-8.
+    String(Vec<u8>),
+    /// A plain Lua table copied out of the Lua state, as a list of key-value pairs.
+    ///
+    /// The order of entries follows Lua table traversal and is unspecified.
+    Table(Vec<(OwnedValue, OwnedValue)>),
+}
+
+impl OwnedValue {
+    /// Copies a plain table out of the Lua state, detaching nested tables too.
+    ///
+    /// Keys and values must be primitive values (see [`Value::into_owned`]) or plain tables of
+    /// them. Tables with a metatable, cyclic tables and tables containing other reference types
+    /// cannot be detached, so an error is returned for them.
+    ///
+    /// [`Value::into_owned`]: crate::Value::into_owned
+    pub fn from_table(table: Table) -> Result<OwnedValue> {
+        OwnedValue::detach(Value::Table(table))
+    }
+
+    // Detaches primitive values and plain tables of them
+    pub(crate) fn detach(value: Value) -> Result<OwnedValue> {
+        OwnedValue::detach_inner(value, &mut Vec::new())
+    }
+
+    fn detach_inner<'lua>(
+        value: Value<'lua>,
+        visited: &mut Vec<Table<'lua>>,
+    ) -> Result<OwnedValue> {
+        match value {
+            Value::Nil => Ok(OwnedValue::Nil),
+            Value::Boolean(b) => Ok(OwnedValue::Boolean(b)),
+            Value::Integer(i) => Ok(OwnedValue::Integer(i)),
+            Value::Number(n) => Ok(OwnedValue::Number(n)),
+            #[cfg(feature = "luau")]
+            Value::Vector(x, y, z) => Ok(OwnedValue::Vector(x, y, z)),
+            Value::String(s) => Ok(OwnedValue::String(s.as_bytes().to_vec())),
+            Value::Table(t) => {
+                let detach_error = |message: &str| Error::FromLuaConversionError {
+                    from: "table",
+                    to: "OwnedValue",
+                    message: Some(message.to_string()),
+                };
+                if t.get_metatable().is_some() {
+                    return Err(detach_error("table with a metatable cannot be detached"));
+                }
+                if visited.contains(&t) {
+                    return Err(detach_error("cyclic table cannot be detached"));
+                }
+                visited.push(t.clone());
+                let mut entries = Vec::new();
+                for pair in t.pairs::<Value, Value>() {
+                    let (key, value) = pair?;
+                    entries.push((
+                        OwnedValue::detach_inner(key, visited)?,
+                        OwnedValue::detach_inner(value, visited)?,
+                    ));
+                }
+                visited.pop();
+                Ok(OwnedValue::Table(entries))
+            }
+            value => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "OwnedValue",
+                message: Some("value cannot be detached from the Lua state".to_string()),
+            }),
+        }
+    }
+
+    pub const fn type_name(&self) -> &'static str {
+        match *self {
+            OwnedValue::Nil => "nil",
+            OwnedValue::Boolean(_) => "boolean",
+            OwnedValue::Integer(_) => "integer",
+            OwnedValue::Number(_) => "number",
+            #[cfg(feature = "luau")]
+            OwnedValue::Vector(_, _, _) => "vector",
+            OwnedValue::String(_) => "string",
+            OwnedValue::Table(_) => "table",
+        }
+    }
+}
+
+/// A wrapper around [`Value`] that implements `Hash` and `Eq` following Lua table key semantics.
+///
+/// Floats with an exact integer representation are normalized to integers, so `Integer(2)` and
+/// `Number(2.0)` are the same key, while distinct floats are different keys. Strings are compared
+/// by content and other reference types (tables, functions, etc.) by identity.
+///
+/// This allows using Lua values as keys of Rust collections such as `HashMap`.
+#[derive(Debug, Clone)]
+pub struct Key<'lua>(pub Value<'lua>);
+
+impl<'lua> Key<'lua> {
+    /// Returns the wrapped value.
+    pub fn into_inner(self) -> Value<'lua> {
+        self.0
+    }
+
+    // Converts a float to an integer if it has an exact integer representation.
+    fn normalize_number(n: Number) -> Option<Integer> {
+        let i = cast::<Number, Integer>(n)?;
+        if i as Number == n {
+            Some(i)
+        } else {
+            None
+        }
+    }
+
+    fn ref_pointer(lref: &LuaRef) -> *const c_void {
+        let lua = lref.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 1);
+            lua.push_ref(lref);
+            ffi::lua_topointer(lua.state, -1)
+        }
+    }
+}
+
+impl<'lua> PartialEq for Key<'lua> {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Value::Integer(a), Value::Number(b)) | (Value::Number(b), Value::Integer(a)) => {
+                Self::normalize_number(*b) == Some(*a)
+            }
+            (Value::Number(a), Value::Number(b)) => a.to_bits() == b.to_bits() || a == b,
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl<'lua> Eq for Key<'lua> {}
+
+impl<'lua> Hash for Key<'lua> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        if let Value::Number(n) = self.0 {
+            // Must produce the same hash as the equal `Value::Integer`
+            if let Some(i) = Self::normalize_number(n) {
+                return Key(Value::Integer(i)).hash(state);
+            }
+        }
+
+        mem::discriminant(&self.0).hash(state);
+        match self.0 {
+            Value::Nil => {}
+            Value::Boolean(b) => b.hash(state),
+            Value::LightUserData(ud) => ud.0.hash(state),
+            Value::Integer(i) => i.hash(state),
+            Value::Number(n) => n.to_bits().hash(state),
+            #[cfg(feature = "luau")]
+            Value::Vector(x, y, z) => (x.to_bits(), y.to_bits(), z.to_bits()).hash(state),
+            Value::String(ref s) => s.hash(state),
+            Value::Table(ref t) => Self::ref_pointer(&t.0).hash(state),
+            Value::Function(ref f) => Self::ref_pointer(&f.0).hash(state),
+            Value::Thread(ref t) => Self::ref_pointer(&t.0).hash(state),
+            Value::UserData(ref ud) => Self::ref_pointer(&ud.0).hash(state),
+            Value::Error(_) => {}
+        }
+    }
+}
+
+impl<'lua> From<Value<'lua>> for Key<'lua> {
+    fn from(value: Value<'lua>) -> Self {
+        Key(value)
+    }
 }
 
 impl<'lua> PartialEq for Value<'lua> {
@@ -152,6 +484,131 @@ impl<'lua> Serialize for Value<'lua> {
     }
 }
 
+#[cfg(feature = "serialize")]
+impl Serialize for OwnedValue {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            OwnedValue::Nil => serializer.serialize_unit(),
+            OwnedValue::Boolean(b) => serializer.serialize_bool(*b),
+            OwnedValue::Integer(i) => {
+                let i = cast(*i).ok_or_else(|| ser::Error::custom("integer out of range"))?;
+                serializer.serialize_i64(i)
+            }
+            OwnedValue::Number(n) => serializer.serialize_f64(*n),
+            #[cfg(feature = "luau")]
+            OwnedValue::Vector(x, y, z) => (x, y, z).serialize(serializer),
+            OwnedValue::String(s) => match str::from_utf8(s) {
+                Ok(s) => serializer.serialize_str(s),
+                Err(_) => serializer.serialize_bytes(s),
+            },
+            OwnedValue::Table(entries) => {
+                // Sequences (keys from 1 to n) are serialized as arrays, other tables as maps
+                let mut items = vec![None; entries.len()];
+                for (key, value) in entries {
+                    match *key {
+                        OwnedValue::Integer(i) if i >= 1 && i as usize <= items.len() => {
+                            items[i as usize - 1] = Some(value);
+                        }
+                        _ => return serializer.collect_map(entries.iter().map(|(k, v)| (k, v))),
+                    }
+                }
+                serializer.collect_seq(items.into_iter().flatten())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl<'de> Deserialize<'de> for OwnedValue {
+    fn deserialize<D>(deserializer: D) -> StdResult<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct OwnedValueVisitor;
+
+        impl<'de> de::Visitor<'de> for OwnedValueVisitor {
+            type Value = OwnedValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a primitive Lua value or a table of them")
+            }
+
+            fn visit_bool<E: de::Error>(self, b: bool) -> StdResult<OwnedValue, E> {
+                Ok(OwnedValue::Boolean(b))
+            }
+
+            fn visit_i64<E: de::Error>(self, i: i64) -> StdResult<OwnedValue, E> {
+                match cast(i) {
+                    Some(i) => Ok(OwnedValue::Integer(i)),
+                    None => Ok(OwnedValue::Number(i as Number)),
+                }
+            }
+
+            fn visit_u64<E: de::Error>(self, i: u64) -> StdResult<OwnedValue, E> {
+                match cast(i) {
+                    Some(i) => Ok(OwnedValue::Integer(i)),
+                    None => Ok(OwnedValue::Number(i as Number)),
+                }
+            }
+
+            fn visit_f64<E: de::Error>(self, n: f64) -> StdResult<OwnedValue, E> {
+                Ok(OwnedValue::Number(n))
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> StdResult<OwnedValue, E> {
+                Ok(OwnedValue::String(s.as_bytes().to_vec()))
+            }
+
+            fn visit_bytes<E: de::Error>(self, b: &[u8]) -> StdResult<OwnedValue, E> {
+                Ok(OwnedValue::String(b.to_vec()))
+            }
+
+            fn visit_unit<E: de::Error>(self) -> StdResult<OwnedValue, E> {
+                Ok(OwnedValue::Nil)
+            }
+
+            fn visit_none<E: de::Error>(self) -> StdResult<OwnedValue, E> {
+                Ok(OwnedValue::Nil)
+            }
+
+            fn visit_some<D>(self, deserializer: D) -> StdResult<OwnedValue, D::Error>
+            where
+                D: de::Deserializer<'de>,
+            {
+                OwnedValue::deserialize(deserializer)
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> StdResult<OwnedValue, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut entries = Vec::new();
+                while let Some(value) = seq.next_element()? {
+                    let key = OwnedValue::Integer(entries.len() as Integer + 1);
+                    entries.push((key, value));
+                }
+                Ok(OwnedValue::Table(entries))
+            }
+
+            fn visit_map<A>(self, mut map: A) -> StdResult<OwnedValue, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(OwnedValue::Table(entries))
+            }
+        }
+
+        deserializer.deserialize_any(OwnedValueVisitor)
+    }
+}
+
 /// Trait for types convertible to `Value`.
 pub trait ToLua<'lua> {
     /// Performs the conversion.
@@ -265,6 +722,31 @@ impl<'lua> MultiValue<'lua> {
         self.0.iter().rev()
     }
 
+    /// Returns the values starting at the 1-based index `from`, like Lua's `select(from, ...)`.
+    ///
+    /// An index of 0 is treated as 1, and an index past the last value returns an empty
+    /// `MultiValue`. Values are cloned, the original `MultiValue` is left intact.
+    pub fn select(&self, from: usize) -> MultiValue<'lua> {
+        let skip = from.saturating_sub(1).min(self.0.len());
+        MultiValue(self.0[..self.0.len() - skip].to_vec())
+    }
+
+    /// Returns the number of values, like Lua's `select('#', ...)`.
+    ///
+    /// This is the same as [`len`], trailing `nil` values are counted too.
+    ///
+    /// [`len`]: #method.len
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.0.len()
+    }
+
+    // Rotates the values in-place such that the first `n` values move to the end
+    #[inline]
+    pub(crate) fn rotate_left(&mut self, n: usize) {
+        self.0.rotate_right(n);
+    }
+
     #[inline]
     pub(crate) fn drain_all(&mut self) -> iter::Rev<vec::Drain<Value<'lua>>> {
         self.0.drain(..).rev()
@@ -305,4 +787,21 @@ pub trait FromLuaMulti<'lua>: Sized {
     /// assigning values. Similarly, if not enough values are given, conversions should assume that
     /// any missing values are nil.
     fn from_lua_multi(values: MultiValue<'lua>, lua: &'lua Lua) -> Result<Self>;
+
+    /// Performs the conversion from a single value.
+    ///
+    /// Used when exactly one value is available (eg. the only result of a function call), so
+    /// conversions of single values can skip collecting it into a [`MultiValue`].
+    #[doc(hidden)]
+    #[inline]
+    fn from_lua_single(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let mut values = lua.new_or_cached_multivalue();
+        values.push_front(value);
+        Self::from_lua_multi(values, lua)
+    }
+
+    /// The maximum number of values used by the conversion.
+    ///
+    /// `None` means that the conversion can use any number of values (or the number is unknown).
+    const MAX_ARGS: Option<usize> = None;
 }
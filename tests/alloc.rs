@@ -0,0 +1,116 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use mlua::{Function, Lua, MultiValue, Result};
+
+// Counts allocations made by the current thread
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn count_allocations(f: impl FnOnce() -> Result<()>) -> Result<usize> {
+    let start = ALLOCATIONS.with(|n| n.get());
+    f()?;
+    Ok(ALLOCATIONS.with(|n| n.get()) - start)
+}
+
+#[test]
+fn test_function_call_fixed_results() -> Result<()> {
+    let lua = Lua::new();
+
+    let sum: Function = lua.load("function(a, b) return a + b end").eval()?;
+    // Warm up
+    assert_eq!(sum.call::<_, i64>((1, 2))?, 3);
+
+    let allocations = count_allocations(|| {
+        for i in 0..10000 {
+            assert_eq!(sum.call::<_, i64>((i, 1))?, i + 1);
+        }
+        Ok(())
+    })?;
+    assert!(
+        allocations < 100,
+        "single value call made {} allocations",
+        allocations
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_function_call_into() -> Result<()> {
+    let lua = Lua::new();
+
+    let function: Function = lua.load("function(a, b) return a + b, a * b end").eval()?;
+
+    // Warm up
+    let mut results = MultiValue::new();
+    function.call_into((1, 2), &mut results)?;
+    let _: MultiValue = function.call((1, 2))?;
+
+    let call_allocations = count_allocations(|| {
+        for i in 0..10000 {
+            let results: MultiValue = function.call((i, 1))?;
+            assert_eq!(results.len(), 2);
+        }
+        Ok(())
+    })?;
+    let call_into_allocations = count_allocations(|| {
+        for i in 0..10000 {
+            results.clear();
+            function.call_into((i, 1), &mut results)?;
+            assert_eq!(results.len(), 2);
+        }
+        Ok(())
+    })?;
+    assert!(
+        call_into_allocations < call_allocations,
+        "call_into made {} allocations, call made {}",
+        call_into_allocations,
+        call_allocations
+    );
+    // The buffer is reused, so there are no allocations per call
+    assert!(
+        call_into_allocations < 100,
+        "call_into made {} allocations",
+        call_into_allocations
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_warmup() -> Result<()> {
+    let lua = Lua::new();
+    lua.warmup()?;
+
+    // Caches are populated, so the first call allocates no more than the subsequent ones
+    let callback = lua.create_function(|_, (a, b): (i64, i64)| Ok(a + b))?;
+    let first = count_allocations(|| {
+        assert_eq!(callback.call::<_, i64>((1, 2))?, 3);
+        Ok(())
+    })?;
+    let second = count_allocations(|| {
+        assert_eq!(callback.call::<_, i64>((2, 3))?, 5);
+        Ok(())
+    })?;
+    assert_eq!(first, second);
+
+    Ok(())
+}
@@ -226,6 +226,33 @@ async fn test_async_thread() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_async_lua_coroutine() -> Result<()> {
+    let lua = Lua::new();
+
+    let add_later = lua.create_async_function(|_, (a, b): (i64, i64)| async move {
+        Delay::new(Duration::from_millis(10)).await;
+        Ok(a + b)
+    })?;
+    lua.globals().set("add_later", add_later)?;
+
+    // Coroutine created on the Lua side suspends until the future resolves
+    let thread: Thread = lua
+        .load(
+            r#"
+            coroutine.create(function(a, b)
+                local sum = add_later(a, b)
+                return sum * 10
+            end)
+        "#,
+        )
+        .eval()?;
+    let res: i64 = thread.into_async((1, 2)).await?;
+    assert_eq!(res, 30);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_async_table() -> Result<()> {
     let options = LuaOptions::new().thread_cache_size(4);
@@ -26,6 +26,35 @@ fn test_chunk_path() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_run_file() -> Result<()> {
+    let lua = Lua::new();
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("script.lua");
+    fs::write(&path, "local a = 40\nreturn a + 2, 'done'")?;
+    let (n, s): (i64, String) = lua.unpack_multi(lua.run_file(&path)?)?;
+    assert_eq!(n, 42);
+    assert_eq!(s, "done");
+
+    // Chunk is named after the file
+    fs::write(&path, "error('boom')")?;
+    match lua.run_file(&path) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("script.lua:1: boom"), "{}", msg),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    match lua.run_file(temp_dir.path().join("missing.lua")) {
+        Err(Error::FileError { path, cause }) => {
+            assert!(path.ends_with("missing.lua"));
+            assert_eq!(cause.kind(), io::ErrorKind::NotFound);
+        }
+        r => panic!("expected FileError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
 #[test]
 #[cfg(feature = "macros")]
 fn test_chunk_macro() -> Result<()> {
@@ -52,3 +81,116 @@ fn test_chunk_macro() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_chunk_source_map() -> Result<()> {
+    let lua = Lua::new();
+
+    let source_map = |line: u32| ("main.fnl".to_string(), line * 10);
+
+    match lua
+        .load("local x = 1\nerror('boom')")
+        .set_name("=transpiled")?
+        .set_source_map(source_map)
+        .exec()
+    {
+        Err(Error::RuntimeError(msg)) => assert!(msg.starts_with("main.fnl:20: boom"), "{}", msg),
+        res => panic!("expected RuntimeError, got {:?}", res),
+    };
+
+    let rust_fn = lua.create_function(|_, ()| Err::<(), _>(Error::RuntimeError("fail".into())))?;
+    lua.globals().set("rust_fn", rust_fn)?;
+    match lua
+        .load("\n\nrust_fn()")
+        .set_name("=transpiled2")?
+        .set_source_map(source_map)
+        .exec()
+    {
+        Err(Error::CallbackError { traceback, .. }) => {
+            assert!(traceback.contains("main.fnl:30:"), "{}", traceback)
+        }
+        res => panic!("expected CallbackError, got {:?}", res),
+    };
+
+    // Lua truncates long chunk names and elides multi-line ones in error messages
+    for name in &[
+        format!("={}", "x".repeat(100)),
+        "line 1\nline 2".to_string(),
+    ] {
+        match lua
+            .load("error('boom')")
+            .set_name(name)?
+            .set_source_map(source_map)
+            .exec()
+        {
+            Err(Error::RuntimeError(msg)) => {
+                assert!(msg.starts_with("main.fnl:10: boom"), "{}", msg)
+            }
+            res => panic!("expected RuntimeError, got {:?}", res),
+        };
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_load_with_locals() -> Result<()> {
+    let lua = Lua::new();
+
+    let func = lua.load_with_locals("return x + y", &["x", "y"])?;
+    assert_eq!(func.call::<_, i64>((1, 2))?, 3);
+    assert_eq!(func.call::<_, i64>((10, 20))?, 30);
+
+    // Locals must not leak into globals
+    assert_eq!(lua.globals().get::<_, Option<i64>>("x")?, None);
+
+    // Line numbers are preserved
+    let func = lua.load_with_locals("local a = x\nerror('boom')", &["x"])?;
+    match func.call::<_, ()>(1) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains(":2: boom"), "{}", msg),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    match lua.load_with_locals("return 1", &["not valid"]) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    match lua.load_with_locals("return 1", &["x", "end"]) {
+        Err(Error::RuntimeError(msg)) => assert_eq!(msg, "invalid local variable name 'end'"),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_source_transform() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.set_source_transform(|name, source| {
+        let source = source.replace("__VERSION__", "42");
+        source.replace("__CHUNK__", &format!("{:?}", name))
+    });
+
+    assert_eq!(lua.load("__VERSION__ + 1").eval::<i64>()?, 43);
+    lua.load("version = __VERSION__").exec()?;
+    assert_eq!(lua.globals().get::<_, i64>("version")?, 42);
+    let f = lua.load("return __VERSION__ * 2").into_function()?;
+    assert_eq!(f.call::<_, i64>(())?, 84);
+    let chunk = lua.load("__CHUNK__").set_name("=config")?;
+    assert_eq!(chunk.eval::<String>()?, "=config");
+
+    // Chunks loaded internally are not transformed
+    lua.set_source_transform(|_, _| "error('transformed')".to_string());
+    assert!(lua.load("return 1").exec().is_err());
+    let proxy = lua.tracing_table(lua.create_table_from(vec![("x", 1)])?, |_| {})?;
+    assert_eq!(proxy.get::<_, i64>("x")?, 1);
+
+    lua.remove_source_transform();
+    match lua.load("return __VERSION__ + 1").exec() {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
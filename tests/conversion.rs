@@ -1,6 +1,8 @@
 use std::borrow::Cow;
-use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::ffi::{CStr, CString};
+use std::rc::Rc;
+use std::sync::Arc;
 
 use maplit::{btreemap, btreeset, hashmap, hashset};
 use mlua::{Error, Lua, Result};
@@ -17,6 +19,26 @@ fn test_conv_vec() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_conv_vecdeque() -> Result<()> {
+    let lua = Lua::new();
+
+    let mut v = VecDeque::new();
+    v.push_back(2);
+    v.push_back(3);
+    v.push_front(1);
+    lua.globals().set("v", v.clone())?;
+    lua.load("assert(#v == 3 and v[1] == 1 and v[2] == 2 and v[3] == 3)")
+        .exec()?;
+    let v2: VecDeque<i64> = lua.globals().get("v")?;
+    assert_eq!(v, v2);
+
+    let v3: VecDeque<i64> = lua.load("{10, 20, 30}").eval()?;
+    assert_eq!(v3.into_iter().collect::<Vec<_>>(), vec![10, 20, 30]);
+
+    Ok(())
+}
+
 #[test]
 fn test_conv_hashmap() -> Result<()> {
     let lua = Lua::new();
@@ -41,6 +63,18 @@ fn test_conv_hashset() -> Result<()> {
     let set3 = lua.load(r#"{"a", "b", "c"}"#).eval::<HashSet<String>>()?;
     assert_eq!(set3, hashset! { "a".into(), "b".into(), "c".into() });
 
+    // Set idiom, only keys mapped to a truthy value are members
+    let set4 = lua
+        .load(r#"{apple = true, banana = false, cherry = 1}"#)
+        .eval::<HashSet<String>>()?;
+    assert_eq!(set4, hashset! { "apple".into(), "cherry".into() });
+    assert!(set4.contains("apple"));
+    assert!(!set4.contains("banana"));
+
+    let table: mlua::Table = lua.load("set").eval()?;
+    assert!(table.get::<_, bool>("hello")?);
+    assert!(!table.contains_key("banana")?);
+
     Ok(())
 }
 
@@ -124,6 +158,27 @@ fn test_conv_boxed_slice() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_conv_smart_pointers() -> Result<()> {
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    globals.set("s", Box::new(String::from("boxed")))?;
+    let s: Box<String> = globals.get("s")?;
+    assert_eq!(*s, "boxed");
+
+    globals.set("n", Arc::new(42i64))?;
+    let n: Arc<i64> = globals.get("n")?;
+    assert_eq!(*n, 42);
+
+    let v = Rc::new(vec![1, 2, 3]);
+    globals.set("v", v.clone())?;
+    let v2: Rc<Vec<i32>> = globals.get("v")?;
+    assert_eq!(v, v2);
+
+    Ok(())
+}
+
 #[test]
 fn test_conv_array() -> Result<()> {
     let lua = Lua::new();
@@ -138,3 +193,72 @@ fn test_conv_array() -> Result<()> {
 
     Ok(())
 }
+
+mlua::lua_int_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Mode {
+        Read = 1,
+        Write = 2,
+        Append = 8,
+    }
+}
+
+#[test]
+fn test_conv_int_enum() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.globals().set("mode", Mode::Append)?;
+    assert_eq!(lua.load("mode").eval::<i64>()?, 8);
+    assert_eq!(lua.load("mode").eval::<Mode>()?, Mode::Append);
+    assert_eq!(lua.load("2").eval::<Mode>()?, Mode::Write);
+
+    match lua.load("3").eval::<Mode>() {
+        Err(Error::FromLuaConversionError { to: "Mode", .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    assert_eq!(Mode::values(), &[Mode::Read, Mode::Write, Mode::Append]);
+
+    Ok(())
+}
+
+#[test]
+fn test_conv_float_overflow() -> Result<()> {
+    let lua = Lua::new();
+
+    for expr in &["1e30", "-1e30", "2^63", "1/0"] {
+        match lua.load(*expr).eval::<i64>() {
+            Err(Error::FromLuaConversionError { to: "i64", .. }) => {}
+            r => panic!("expected FromLuaConversionError, got {:?}", r),
+        }
+    }
+
+    assert_eq!(lua.load("2^53").eval::<i64>()?, 1 << 53);
+    assert_eq!(lua.load("-2^63").eval::<i64>()?, i64::MIN);
+
+    Ok(())
+}
+
+mlua::lua_int_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Color {
+        Red = 1,
+        Green = 2,
+        Blue = 4,
+    }
+}
+
+#[test]
+fn test_conv_enum_namespace() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.create_enum("Color", Color::variants().iter().copied())?;
+    assert_eq!(lua.load("Color.Green").eval::<i64>()?, 2);
+    assert_eq!(lua.load("Color.Blue").eval::<Color>()?, Color::Blue);
+    assert!(lua.load("Color.Red = 0").exec().is_err());
+
+    lua.create_enum("Level", vec![("Low", 10), ("High", 20)])?;
+    assert_eq!(lua.load("Level.High - Level.Low").eval::<i64>()?, 10);
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use mlua::{Function, Lua, Result, String};
+use mlua::{Error, Function, Lua, MultiValue, Result, String, Value, Variadic};
 
 #[test]
 fn test_function() -> Result<()> {
@@ -107,3 +107,311 @@ fn test_dump() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(not(feature = "luau"))]
+#[test]
+fn test_function_bytecode_size() -> Result<()> {
+    let lua = Lua::new();
+
+    let source = "function(a, b) return a + b end";
+    let size = lua.load(source).eval::<Function>()?.bytecode_size()?;
+    assert!(size > 0);
+    assert_eq!(lua.load(source).eval::<Function>()?.bytecode_size()?, size);
+
+    let bigger = lua
+        .load("function(a, b) local t = {a, b, a * b, a - b} return t[1] + t[2] + t[3] + t[4] end")
+        .eval::<Function>()?;
+    assert!(bigger.bytecode_size()? > size);
+
+    let rust_func = lua.create_function(|_, ()| Ok(()))?;
+    assert!(rust_func.bytecode_size().is_err());
+
+    Ok(())
+}
+
+#[cfg(not(feature = "luau"))]
+#[test]
+fn test_function_same_source() -> Result<()> {
+    let lua = Lua::new();
+
+    let source = "function(a, b) return a + b end";
+    let load = || lua.load(source).eval::<Function>();
+    let (f1, f2) = (load()?, load()?);
+    let f3 = lua
+        .load("function(a, b) return a - b end")
+        .eval::<Function>()?;
+
+    assert!(f1 != f2);
+    assert!(f1.same_source(&f2)?);
+    assert!(f1.same_source(&f1)?);
+    assert!(!f1.same_source(&f3)?);
+
+    let rust_func = lua.create_function(|_, ()| Ok(()))?;
+    assert!(f1.same_source(&rust_func).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_function_call_fixed_results() -> Result<()> {
+    let lua = Lua::new();
+
+    let function: Function = lua
+        .load("function(n) local t = {} for i = 1, n do t[i] = i end return (table.unpack or unpack)(t) end")
+        .eval()?;
+
+    assert_eq!(function.call::<_, i64>(100)?, 1);
+    assert_eq!(function.call::<_, Option<i64>>(0)?, None);
+    assert_eq!(function.call::<_, (i64, i64)>(100)?, (1, 2));
+    assert_eq!(function.call::<_, (i64, Option<i64>)>(1)?, (1, None));
+    assert_eq!(function.call::<_, MultiValue>(100)?.len(), 100);
+    function.call::<_, ()>(100)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_function_max_results() -> Result<()> {
+    let lua = Lua::new();
+
+    let function: Function = lua
+        .load("function(n) local t = {} for i = 1, n do t[i] = i end return (table.unpack or unpack)(t) end")
+        .eval()?;
+
+    lua.set_max_results(Some(10));
+    assert_eq!(function.call::<_, MultiValue>(10)?.len(), 10);
+    match function.call::<_, MultiValue>(100) {
+        Err(Error::TooManyResults { count, limit }) => {
+            assert_eq!(count, 100);
+            assert_eq!(limit, 10);
+        }
+        r => panic!("expected TooManyResults, got {:?}", r),
+    }
+    match function.call::<_, Variadic<i64>>(11) {
+        Err(Error::TooManyResults { .. }) => {}
+        r => panic!("expected TooManyResults, got {:?}", r),
+    }
+    // Fixed number of results is not affected
+    assert_eq!(function.call::<_, i64>(100)?, 1);
+
+    lua.set_max_results(None);
+    assert_eq!(function.call::<_, MultiValue>(100)?.len(), 100);
+
+    Ok(())
+}
+
+#[test]
+fn test_function_call_into() -> Result<()> {
+    let lua = Lua::new();
+
+    let function: Function = lua.load("function(a, b) return a + b, a * b end").eval()?;
+
+    let mut results = MultiValue::from_vec(vec![Value::Boolean(true)]);
+    function.call_into((2, 3), &mut results)?;
+    assert_eq!(
+        results.iter().cloned().collect::<Vec<_>>(),
+        vec![Value::Boolean(true), Value::Integer(5), Value::Integer(6)]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_function_call_slice() -> Result<()> {
+    let lua = Lua::new();
+
+    let concat: Function = lua
+        .load("function(a, b, c) return a .. b .. c, select('#', a, b, c) end")
+        .eval()?;
+
+    let args = [
+        Value::String(lua.create_string("foo")?),
+        Value::Integer(1),
+        Value::String(lua.create_string("bar")?),
+    ];
+    let (s, n) = concat.call_slice::<(String, usize)>(&args)?;
+    assert_eq!(s, "foo1bar");
+    assert_eq!(n, 3);
+
+    // The arguments are only borrowed and can be used again
+    assert_eq!(concat.call_slice::<String>(&args)?, "foo1bar");
+    assert!(concat.call_slice::<String>(&args[..0]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_function_call_timed() -> Result<()> {
+    let lua = Lua::new();
+
+    let sleep = lua.create_function(|_, ms: u64| {
+        std::thread::sleep(std::time::Duration::from_millis(ms));
+        Ok(())
+    })?;
+    lua.globals().set("sleep", sleep)?;
+
+    let wait: Function = lua
+        .load(
+            r#"
+            function(ms)
+                sleep(ms)
+                return ms
+            end
+        "#,
+        )
+        .eval()?;
+
+    let (result, elapsed) = wait.call_timed::<_, i64>(50)?;
+    assert_eq!(result, 50);
+    assert!(elapsed >= std::time::Duration::from_millis(50));
+
+    Ok(())
+}
+
+#[test]
+fn test_function_call_ok_err() -> Result<()> {
+    let lua = Lua::new();
+
+    let f: Function = lua
+        .load(
+            r#"
+            function(mode)
+                if mode == "ok" then
+                    return true, 42
+                elseif mode == "value" then
+                    return 7
+                elseif mode == "fail" then
+                    return false, "something went wrong"
+                end
+                return nil, "not found"
+            end
+        "#,
+        )
+        .eval()?;
+
+    assert_eq!(f.call_ok_err::<_, i64>("ok")?, Ok(42));
+    assert_eq!(f.call_ok_err::<_, i64>("value")?, Ok(7));
+    match f.call_ok_err::<_, i64>("fail")? {
+        Err(Value::String(msg)) => assert_eq!(msg, "something went wrong"),
+        r => panic!("expected Err(String), got {:?}", r),
+    }
+    match f.call_ok_err::<_, i64>("missing")? {
+        Err(Value::String(msg)) => assert_eq!(msg, "not found"),
+        r => panic!("expected Err(String), got {:?}", r),
+    }
+
+    // Conversion errors are reported as the outer error
+    assert!(f.call_ok_err::<_, Function>("ok").is_err());
+
+    Ok(())
+}
+
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+#[test]
+fn test_function_params() -> Result<()> {
+    let lua = Lua::new();
+
+    let func: Function = lua.load("function(a, b, ...) end").eval()?;
+    let params = func.params()?;
+    assert_eq!(params.arity, Some(2));
+    assert!(params.is_vararg);
+    assert_eq!(params.names, vec!["a", "b"]);
+
+    let func: Function = lua.load("function(x) return x end").eval()?;
+    let params = func.params()?;
+    assert_eq!(params.arity, Some(1));
+    assert!(!params.is_vararg);
+    assert_eq!(params.names, vec!["x"]);
+
+    // Rust functions
+    let func = lua.create_function(|_, (_a, _b): (i32, i32)| Ok(()))?;
+    let params = func.params()?;
+    assert_eq!(params.arity, None);
+    assert!(params.is_vararg);
+    assert!(params.names.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_slow_call_threshold() -> Result<()> {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    let lua = Lua::new();
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let reports2 = reports.clone();
+    lua.set_slow_call_threshold(Duration::from_millis(1), move |name, elapsed| {
+        reports2.lock().unwrap().push((name.to_string(), elapsed));
+    });
+
+    let fast: Function = lua.load("function() return 1 end").eval()?;
+    fast.call::<_, i64>(())?;
+    assert!(reports.lock().unwrap().is_empty());
+
+    let slow = lua.create_function(|_, ()| {
+        std::thread::sleep(Duration::from_millis(5));
+        Ok(())
+    })?;
+    slow.call::<_, ()>(())?;
+    {
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].0, "[C]");
+        assert!(reports[0].1 > Duration::from_millis(1));
+    }
+
+    let slow_lua: Function = lua.load("function(f) f() end").set_name("slow")?.eval()?;
+    slow_lua.call::<_, ()>(slow.clone())?;
+    assert_eq!(reports.lock().unwrap()[1].0, r#"[string "slow"]:1"#);
+
+    lua.remove_slow_call_threshold();
+    slow.call::<_, ()>(())?;
+    assert_eq!(reports.lock().unwrap().len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_function_with_retry() -> Result<()> {
+    let lua = Lua::new();
+
+    let flaky: Function = lua
+        .load(
+            r#"
+            calls = 0
+            return function(a, b)
+                calls = calls + 1
+                if calls <= 2 then error("transient failure") end
+                if a == nil then error("fatal failure") end
+                return a + b
+            end
+        "#,
+        )
+        .eval()?;
+    let is_transient = |err: &Error| err.to_string().contains("transient");
+
+    let call = flaky.with_retry::<_, i64, _>(3, is_transient);
+    assert_eq!(call((1, 2))?, 3);
+    assert_eq!(lua.globals().get::<_, i64>("calls")?, 3);
+
+    // Errors not matching the predicate are returned immediately
+    let call_no_args = flaky.with_retry::<_, i64, _>(3, is_transient);
+    match call_no_args(()) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("fatal failure")),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    assert_eq!(lua.globals().get::<_, i64>("calls")?, 4);
+
+    // Not enough attempts
+    lua.globals().set("calls", 0)?;
+    let call = flaky.with_retry::<_, i64, _>(1, is_transient);
+    match call((1, 2)) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("transient failure")),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    assert_eq!(lua.globals().get::<_, i64>("calls")?, 2);
+
+    Ok(())
+}
@@ -8,6 +8,9 @@ use std::sync::{Arc, Mutex};
 
 use mlua::{DebugEvent, Error, HookTriggers, Lua, Result, Value};
 
+#[cfg(feature = "lua54")]
+use mlua::{ThreadStatus, VmState};
+
 #[test]
 fn test_hook_triggers_bitor() {
     let trigger = HookTriggers::on_calls()
@@ -162,6 +165,51 @@ fn test_limit_execution_instructions() -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "lua54")]
+#[test]
+fn test_yield_hook() -> Result<()> {
+    let lua = Lua::new();
+    let yields = Arc::new(AtomicI64::new(0));
+
+    let hook_yields = yields.clone();
+    lua.set_yield_hook(
+        HookTriggers::every_nth_instruction(100),
+        move |_lua, _debug| {
+            hook_yields.fetch_add(1, Ordering::Relaxed);
+            Ok(VmState::Yield)
+        },
+    )?;
+
+    let co = lua.create_thread(
+        lua.load(
+            r#"
+            counter = 0
+            while true do
+                counter = counter + 1
+            end
+        "#,
+        )
+        .into_function()?,
+    )?;
+
+    let mut last_counter = 0;
+    for i in 1..=5 {
+        co.resume::<_, ()>(())?;
+        assert_eq!(co.status(), ThreadStatus::Resumable);
+        assert_eq!(yields.load(Ordering::Relaxed), i);
+
+        let counter = lua.globals().get::<_, i64>("counter")?;
+        assert!(counter > last_counter);
+        last_counter = counter;
+    }
+
+    // Yield is ignored outside of coroutines
+    lua.load("for i = 1, 1000 do end").exec()?;
+    assert!(yields.load(Ordering::Relaxed) > 5);
+
+    Ok(())
+}
+
 #[test]
 fn test_hook_removal() -> Result<()> {
     let lua = Lua::new();
@@ -233,3 +281,39 @@ fn test_hook_swap_within_hook() -> Result<()> {
         Ok(())
     })
 }
+
+#[cfg(feature = "lua54")]
+#[test]
+fn test_local_attributes() -> Result<()> {
+    // Lua 5.4 does not keep local attributes in debug information, so they cannot be exposed.
+    // Compile-time `<const>` locals are folded away and don't appear in `debug.getlocal` at all,
+    // while other `<const>` and `<close>` locals look like regular locals.
+    let lua = unsafe { Lua::unsafe_new() };
+    let names = lua
+        .load(
+            r#"
+            local function f()
+                local folded <const> = 10
+                local runtime <const> = tostring(folded)
+                local closed <close> = nil
+                local plain = 1
+                local names = {}
+                local i = 1
+                while true do
+                    local name = debug.getlocal(1, i)
+                    if not name then break end
+                    names[#names + 1] = name
+                    i = i + 1
+                end
+                return names
+            end
+            return f()
+        "#,
+        )
+        .eval::<Vec<String>>()?;
+
+    assert!(!names.contains(&"folded".to_string()));
+    assert_eq!(&names[..3], &["runtime", "closed", "plain"]);
+
+    Ok(())
+}
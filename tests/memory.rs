@@ -1,9 +1,9 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use mlua::{GCMode, Lua, Result, UserData};
+use mlua::{GCMode, GcPhase, Lua, Result, UserData};
 
 #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
-use mlua::Error;
+use mlua::{AllocAction, Error};
 
 #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
 #[test]
@@ -33,6 +33,92 @@ fn test_memory_limit() -> Result<()> {
     Ok(())
 }
 
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+#[test]
+fn test_resource_limit() -> Result<()> {
+    let lua = Lua::new();
+
+    let f = lua
+        .load("local t = {}; for i = 1,1000 do t[i] = {} end")
+        .into_function()?;
+    f.call::<_, ()>(())
+        .expect("should trigger no resource limit");
+
+    lua.set_resource_limit(100)?;
+    match f.call::<_, ()>(()) {
+        Err(Error::ResourceLimit) => {}
+        something_else => panic!("did not trigger resource limit: {:?}", something_else),
+    };
+
+    // The counter is reset on every call
+    lua.load("local t = {}; for i = 1,10 do t[i] = {} end")
+        .exec()
+        .expect("should trigger no resource limit");
+
+    assert_eq!(lua.set_resource_limit(0)?, 100);
+    f.call::<_, ()>(())
+        .expect("should trigger no resource limit");
+
+    Ok(())
+}
+
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+#[test]
+fn test_alloc_failure_handler() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let lua = Lua::new();
+
+    let failures = Arc::new(AtomicUsize::new(0));
+    let failures2 = failures.clone();
+    lua.on_alloc_failure(move || {
+        failures2.fetch_add(1, Ordering::Relaxed);
+        AllocAction::ReturnError
+    })?;
+
+    let f = lua
+        .load("local t = {}; for i = 1,10000 do t[i] = i end")
+        .into_function()?;
+    lua.set_memory_limit(lua.used_memory() + 10000)?;
+    match f.call::<_, ()>(()) {
+        Err(Error::MemoryError(_)) => {}
+        something_else => panic!("did not trigger memory error: {:?}", something_else),
+    };
+    assert!(failures.load(Ordering::Relaxed) > 0);
+
+    lua.set_memory_limit(0)?;
+    f.call::<_, ()>(()).expect("should trigger no memory limit");
+
+    Ok(())
+}
+
+#[test]
+fn test_max_string_len() -> Result<()> {
+    let lua = Lua::new();
+    lua.set_max_string_len(1024 * 1024);
+
+    lua.create_string(&"x".repeat(1024))?;
+    match lua.create_string(&"x".repeat(1024 * 1024 + 1)) {
+        Err(mlua::Error::MemoryError(msg)) => assert!(msg.contains("exceeds the limit")),
+        r => panic!("expected MemoryError, got {:?}", r),
+    }
+
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    {
+        let len: usize = lua.load(r#"#string.rep("x", 1000)"#).eval()?;
+        assert_eq!(len, 1000);
+        match lua.load(r#"string.rep("x", 16 * 1024 * 1024)"#).exec() {
+            Err(Error::MemoryError(_)) => {}
+            r => panic!("expected MemoryError, got {:?}", r),
+        }
+    }
+
+    lua.set_max_string_len(0);
+    lua.create_string(&"x".repeat(1024 * 1024 + 1))?;
+
+    Ok(())
+}
+
 #[test]
 fn test_gc_control() -> Result<()> {
     let lua = Lua::new();
@@ -75,6 +161,40 @@ fn test_gc_control() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_gc_observer() -> Result<()> {
+    let lua = Lua::new();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let events2 = events.clone();
+    lua.set_gc_observer(move |phase, _| events2.lock().unwrap().push(phase))?;
+
+    lua.load(
+        r#"
+        local t = {}
+        for i = 1, 100000 do
+            t[i % 100 + 1] = { i, tostring(i) }
+        end
+    "#,
+    )
+    .exec()?;
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    assert!(events.lock().unwrap().contains(&GcPhase::CycleEnd));
+
+    lua.gc_step()?;
+    lua.gc_collect()?;
+    assert!(events.lock().unwrap().contains(&GcPhase::Step));
+    assert!(events.lock().unwrap().contains(&GcPhase::Collect));
+
+    lua.remove_gc_observer();
+    events.lock().unwrap().clear();
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert!(events.lock().unwrap().is_empty());
+
+    Ok(())
+}
+
 #[cfg(any(feature = "lua53", feature = "lua52"))]
 #[test]
 fn test_gc_error() {
@@ -356,3 +356,33 @@ fn test_scope_nonstatic_userdata_drop() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_scope_slice_userdata() -> Result<()> {
+    let lua = Lua::new();
+    let data = vec![0.5, 1.5, 2.5, 3.5];
+
+    lua.scope(|scope| {
+        let view = scope.create_slice_userdata(&data)?;
+        lua.globals().set("view", view)?;
+
+        let (first, third, len) = lua
+            .load("return view[1], view[3], #view")
+            .eval::<(f64, f64, usize)>()?;
+        assert_eq!(first, data[0]);
+        assert_eq!(third, data[2]);
+        assert_eq!(len, data.len());
+
+        let out_of_bounds = lua
+            .load("return view[0], view[5]")
+            .eval::<(Option<f64>, Option<f64>)>()?;
+        assert_eq!(out_of_bounds, (None, None));
+
+        Ok(())
+    })?;
+
+    // The view is no longer accessible after the scope ends
+    assert!(lua.load("return view[1]").exec().is_err());
+
+    Ok(())
+}
@@ -3,8 +3,8 @@
 use std::collections::HashMap;
 
 use mlua::{
-    DeserializeOptions, Error, Lua, LuaSerdeExt, Result as LuaResult, SerializeOptions, UserData,
-    Value,
+    DeserializeOptions, Error, Lua, LuaSerdeExt, Result as LuaResult, SerializeOptions, Table,
+    UserData, Value,
 };
 use serde::{Deserialize, Serialize};
 
@@ -144,6 +144,30 @@ fn test_serialize_failure() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[cfg(not(feature = "luau"))]
+#[test]
+fn test_serialize_function() -> Result<(), Box<dyn std::error::Error>> {
+    use mlua::Function;
+
+    let lua = Lua::new();
+
+    let func: Function = lua.load("function(a, b) return a * b + 1 end").eval()?;
+    let bytes = serde_json::to_vec(&func)?;
+
+    let func2 = Function::deserialize(&lua, &mut serde_json::Deserializer::from_slice(&bytes))?;
+    assert!(func != func2);
+    assert_eq!(func2.call::<_, i64>((6, 7))?, 43);
+
+    // Rust functions cannot be serialized
+    let func = lua.create_function(|_, ()| Ok(()))?;
+    match serde_json::to_vec(&func) {
+        Ok(v) => panic!("expected serialization error, got {:?}", v),
+        Err(serde_json::Error { .. }) => {}
+    }
+
+    Ok(())
+}
+
 #[cfg(feature = "luau")]
 #[test]
 fn test_serialize_vector() -> Result<(), Box<dyn std::error::Error>> {
@@ -502,3 +526,42 @@ fn test_from_value_with_options() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_dump_registry() -> Result<(), Box<dyn std::error::Error>> {
+    let lua = Lua::new();
+
+    lua.set_named_registry_value("answer", 42)?;
+    lua.set_named_registry_value("name", "mlua")?;
+    let config = lua
+        .load("{ debug = true, ratio = 0.5, tags = { 'a', 'b' } }")
+        .eval::<Table>()?;
+    lua.set_named_registry_value("config", config)?;
+    lua.set_named_registry_value("callback", lua.create_function(|_, ()| Ok(()))?)?;
+
+    let mut json = Vec::new();
+    let ((), skipped) = lua.dump_registry(&mut serde_json::Serializer::new(&mut json))?;
+    // Entries created by Lua itself are not reported
+    assert_eq!(skipped, vec!["callback".to_string()]);
+
+    let lua2 = Lua::new();
+    lua2.load_registry(&mut serde_json::Deserializer::from_slice(&json))?;
+    assert_eq!(lua2.named_registry_value::<_, i64>("answer")?, 42);
+    assert_eq!(lua2.named_registry_value::<_, String>("name")?, "mlua");
+    let config = lua2.named_registry_value::<_, Table>("config")?;
+    assert!(config.get::<_, bool>("debug")?);
+    assert_eq!(config.get::<_, f64>("ratio")?, 0.5);
+    assert_eq!(config.get::<_, Vec<String>>("tags")?, vec!["a", "b"]);
+    assert_eq!(
+        lua2.named_registry_value::<_, Value>("callback")?,
+        Value::Nil
+    );
+
+    let mut truncated = serde_json::Deserializer::from_slice(&json[..json.len() - 1]);
+    match lua2.load_registry(&mut truncated) {
+        Err(Error::DeserializeError(_)) => {}
+        r => panic!("expected DeserializeError, got {:?}", r),
+    }
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use std::borrow::Cow;
 use std::collections::HashSet;
 
-use mlua::{Lua, Result, String};
+use mlua::{Lua, Result, String, StringCoercion};
 
 #[test]
 fn test_string_compare() {
@@ -72,6 +72,7 @@ fn test_raw_string() -> Result<()> {
 #[test]
 fn test_string_hash() -> Result<()> {
     let lua = Lua::new();
+    lua.set_string_coercion(StringCoercion::Coercive);
 
     let set: HashSet<String> = lua.load(r#"{"hello", "world", "abc", 321}"#).eval()?;
     assert_eq!(set.len(), 4);
@@ -83,3 +84,69 @@ fn test_string_hash() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(feature = "encoding")]
+#[test]
+fn test_string_decode() -> Result<()> {
+    let lua = Lua::new();
+
+    // "Grüße, café" encoded as Latin-1
+    let s = lua.create_string(b"Gr\xfc\xdfe, caf\xe9")?;
+    assert!(s.to_str().is_err());
+    assert_eq!(s.decode("latin1")?, "Grüße, café");
+
+    // Invalid sequences are replaced
+    let s = lua.create_string(b"ok\xff")?;
+    assert_eq!(s.decode("utf-8")?, "ok\u{fffd}");
+
+    assert!(s.decode("no-such-encoding").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_string_cache() -> Result<()> {
+    // Long enough to not be interned by Lua 5.2+
+    let s = "a string that is too long to be interned by the Lua VM";
+
+    let measure = |cache: bool| -> Result<usize> {
+        let lua = Lua::new();
+        if cache {
+            lua.enable_string_cache();
+        }
+        lua.gc_stop();
+        let start = lua.used_memory();
+        let strings = (0..200)
+            .map(|_| lua.create_string(s))
+            .collect::<Result<Vec<_>>>()?;
+        let used = lua.used_memory() - start;
+        assert!(strings
+            .iter()
+            .all(|string| string.as_bytes() == s.as_bytes()));
+        Ok(used)
+    };
+
+    let uncached = measure(false)?;
+    let cached = measure(true)?;
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    assert!(
+        cached < uncached,
+        "cached: {} bytes, uncached: {} bytes",
+        cached,
+        uncached
+    );
+    #[cfg(not(any(feature = "lua54", feature = "lua53", feature = "lua52")))]
+    assert!(cached <= uncached);
+
+    // String keys go through the cache too
+    let lua = Lua::new();
+    lua.enable_string_cache();
+    let table = lua.create_table()?;
+    table.set("key", 1)?;
+    table.set("key", 2)?;
+    assert_eq!(table.get::<_, i64>("key")?, 2);
+    lua.disable_string_cache();
+    assert_eq!(table.get::<_, i64>("key")?, 2);
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use mlua::{Error, Lua, Nil, Result, Table, TableExt, Value};
+use mlua::{Error, Lua, MultiValue, Nil, NullableTable, Result, Table, TableExt, Type, Value};
 
 #[test]
 fn test_set_get() -> Result<()> {
@@ -226,6 +226,125 @@ fn test_table_eq() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_table_shallow_eq() -> Result<()> {
+    let lua = Lua::new();
+
+    let nested = lua.create_table()?;
+    let build = |value: &str| -> Result<Table> {
+        let t = lua.create_table()?;
+        t.set("a", 1)?;
+        t.set("b", value)?;
+        t.set("nested", nested.clone())?;
+        Ok(t)
+    };
+
+    let table1 = build("x")?;
+    let table2 = build("x")?;
+    let table3 = build("y")?;
+    assert!(table1 != table2);
+    assert!(table1.shallow_eq(&table2)?);
+    assert!(!table1.shallow_eq(&table3)?);
+
+    // Extra key
+    table2.set("c", true)?;
+    assert!(!table1.shallow_eq(&table2)?);
+    assert!(!table2.shallow_eq(&table1)?);
+
+    // Nested tables are compared by identity
+    table2.set("c", Nil)?;
+    table2.set("nested", lua.create_table()?)?;
+    assert!(!table1.shallow_eq(&table2)?);
+
+    Ok(())
+}
+
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+#[test]
+fn test_table_gc_callback() -> Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let lua = Lua::new();
+
+    let collected = Arc::new(AtomicBool::new(false));
+    let collected2 = collected.clone();
+    let table = lua.create_table()?;
+    table.set_gc_callback(move |_| collected2.store(true, Ordering::Relaxed))?;
+
+    lua.gc_collect()?;
+    assert!(!collected.load(Ordering::Relaxed));
+
+    drop(table);
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert!(collected.load(Ordering::Relaxed));
+
+    // Existing finalizers are not replaced
+    let table: Table = lua
+        .load("setmetatable({}, { __gc = function() end })")
+        .eval()?;
+    match table.set_gc_callback(|_| {}) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    let table2 = lua.create_table()?;
+    table2.set_gc_callback(|_| {})?;
+    assert!(table2.set_gc_callback(|_| {}).is_err());
+
+    // Tables sharing a metatable get their own callbacks
+    let class: Table = lua.load("{ __index = { name = 'class' } }").eval()?;
+    let (table1, table2) = (lua.create_table()?, lua.create_table()?);
+    table1.set_metatable(Some(class.clone()));
+    table2.set_metatable(Some(class.clone()));
+    let collected = Arc::new(AtomicBool::new(false));
+    let collected2 = collected.clone();
+    table1.set_gc_callback(move |_| collected2.store(true, Ordering::Relaxed))?;
+    table2.set_gc_callback(|_| {})?;
+    assert_eq!(class.raw_get::<_, Value>("__gc")?, Nil);
+    assert_eq!(table1.get::<_, String>("name")?, "class");
+
+    drop(table2);
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert!(!collected.load(Ordering::Relaxed));
+    drop(table1);
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert!(collected.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[test]
+fn test_tracing_table() -> Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let lua = Lua::new();
+
+    let config = lua.create_table()?;
+    config.set("width", 800)?;
+    config.set("height", 600)?;
+    config.set("unused", true)?;
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let log2 = log.clone();
+    let proxy = lua.tracing_table(config.clone(), move |key| {
+        log2.lock().unwrap().push(key.to_string())
+    })?;
+    lua.globals().set("config", proxy)?;
+
+    let area = lua.load("config.width * config.height").eval::<i64>()?;
+    assert_eq!(area, 480000);
+    assert_eq!(*log.lock().unwrap(), vec!["width", "height"]);
+
+    // Writes are forwarded to the inner table
+    lua.load("config.depth = 1").exec()?;
+    assert_eq!(config.get::<_, i64>("depth")?, 1);
+
+    Ok(())
+}
+
 #[test]
 fn test_table_error() -> Result<()> {
     let lua = Lua::new();
@@ -302,3 +421,498 @@ fn test_table_call() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_table_extend() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua.load("{1, 2}").eval()?;
+    table.extend(3..=5)?;
+    assert_eq!(
+        table
+            .clone()
+            .raw_sequence_values()
+            .collect::<Result<Vec<i64>>>()?,
+        vec![1, 2, 3, 4, 5]
+    );
+    assert_eq!(table.raw_len(), 5);
+
+    table.extend(vec!["a", "b"])?;
+    assert_eq!(table.get::<_, String>(7)?, "b");
+
+    let empty = lua.create_table()?;
+    empty.extend(Vec::<i64>::new())?;
+    assert_eq!(empty.raw_len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_retain() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua
+        .load("{1, 2, 3, 4, 5, 6, [10] = 10, [11] = 11}")
+        .eval()?;
+    table.retain(|_: &i64, v: &i64| Ok(v % 2 == 0))?;
+
+    let mut pairs = table
+        .clone()
+        .pairs::<i64, i64>()
+        .collect::<Result<Vec<_>>>()?;
+    pairs.sort();
+    assert_eq!(pairs, vec![(2, 2), (4, 4), (6, 6), (10, 10)]);
+    assert_eq!(table.get::<_, Value>(1)?, Nil);
+
+    // Errors from the predicate are propagated
+    let result = table.retain(|_: &Value, _: &Value| -> Result<bool> {
+        Err(Error::RuntimeError("stop".to_string()))
+    });
+    assert!(result.is_err());
+    assert_eq!(table.get::<_, i64>(2)?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_get_function() -> Result<()> {
+    let lua = Lua::new();
+
+    let handlers: Table = lua
+        .load("{ on_event = function(x) return x * 2 end, count = 5.5 }")
+        .eval()?;
+
+    let on_event = handlers.get_function("on_event")?;
+    assert_eq!(on_event.call::<_, i64>(21)?, 42);
+
+    match handlers.get_function("count") {
+        Err(Error::RuntimeError(msg)) => {
+            assert_eq!(msg, "field 'count' is not a function (got number)")
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    match handlers.get_function("missing") {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("(got nil)")),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_table_get_table() -> Result<()> {
+    let lua = Lua::new();
+
+    let config: Table = lua
+        .load(r#"{ server = { tls = { port = 443 } }, name = "app" }"#)
+        .eval()?;
+
+    let port: u16 = config.get_table("server")?.get_table("tls")?.get("port")?;
+    assert_eq!(port, 443);
+
+    match config.get_table("name") {
+        Err(Error::RuntimeError(msg)) => {
+            assert_eq!(msg, "field 'name' is not a table (got string)")
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    match config.get_table("missing") {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("(got nil)")),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_table_validate() -> Result<()> {
+    let lua = Lua::new();
+
+    let config: Table = lua
+        .load("{ name = 'server', port = 8080, ratio = 1, debug = true }")
+        .eval()?;
+
+    config.validate(&[
+        ("name", Type::String, true),
+        ("port", Type::Integer, true),
+        ("ratio", Type::Number, true),
+        ("debug", Type::Boolean, false),
+        ("timeout", Type::Number, false),
+    ])?;
+
+    match config.validate(&[("name", Type::String, true), ("port", Type::String, true)]) {
+        Err(Error::RuntimeError(msg)) => {
+            assert_eq!(
+                msg,
+                "invalid type of key 'port' (expected string, got integer)"
+            )
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    match config.validate(&[("timeout", Type::Number, true)]) {
+        Err(Error::RuntimeError(msg)) => {
+            assert_eq!(msg, "missing required key 'timeout' (expected number)")
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_table_count() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua
+        .load("{ 1, 2, 3, x = 'a', y = 'b', [10] = 'c', [2.5] = 'd' }")
+        .eval()?;
+    assert_eq!(table.raw_len(), 3);
+    assert_eq!(table.count()?, 7);
+
+    table.raw_set("x", Nil)?;
+    assert_eq!(table.count()?, 6);
+    assert_eq!(lua.create_table()?.count()?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_on_table_create() -> Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let lua = Lua::new();
+
+    let created = Arc::new(Mutex::new(Vec::new()));
+    let created2 = created.clone();
+    lua.on_table_create(move |narr, nrec| created2.lock().unwrap().push((narr, nrec)));
+
+    lua.create_table()?;
+    lua.create_table_with_capacity(4, 2)?;
+    lua.create_table_from(vec![("a", 1), ("b", 2)])?;
+    lua.create_sequence_from(vec![1, 2, 3])?;
+    // Tables created by Lua code are not reported
+    lua.load("local t = {}").exec()?;
+
+    assert_eq!(
+        *created.lock().unwrap(),
+        vec![(0, 0), (4, 2), (0, 2), (3, 0)]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_table_swap_and_move() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_sequence_from(vec![1, 2, 3, 4, 5])?;
+    table.swap(1, 5)?;
+    assert_eq!(
+        table
+            .clone()
+            .raw_sequence_values::<i64>()
+            .collect::<Result<Vec<_>>>()?,
+        vec![5, 2, 3, 4, 1]
+    );
+
+    // Overlapping move towards the end
+    table.move_entries(1, 3, 3)?;
+    assert_eq!(
+        table
+            .clone()
+            .raw_sequence_values::<i64>()
+            .collect::<Result<Vec<_>>>()?,
+        vec![5, 2, 5, 2, 3]
+    );
+
+    // Overlapping move towards the start
+    table.move_entries(3, 3, 1)?;
+    assert_eq!(
+        table
+            .clone()
+            .raw_sequence_values::<i64>()
+            .collect::<Result<Vec<_>>>()?,
+        vec![5, 2, 3, 2, 3]
+    );
+
+    // Move past the end extends the sequence
+    table.move_entries(1, 2, 6)?;
+    assert_eq!(
+        table
+            .raw_sequence_values::<i64>()
+            .collect::<Result<Vec<_>>>()?,
+        vec![5, 2, 3, 2, 3, 5, 2]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_table_copy_into_slice() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_sequence_from((0..1000).map(|i| i as f64))?;
+    let mut buf = vec![0.0; 1000];
+    assert_eq!(table.copy_into_slice(&mut buf)?, 1000);
+    assert_eq!(buf, lua.unpack::<Vec<f64>>(Value::Table(table))?);
+
+    // Copying stops at the first nil
+    let mut buf = [0.0; 4];
+    let short = lua.create_sequence_from(vec![1.5, 2.5])?;
+    assert_eq!(short.copy_into_slice(&mut buf)?, 2);
+    assert_eq!(&buf[..2], &[1.5, 2.5]);
+
+    let mixed: Table = lua.load("{1, 2, true}").eval()?;
+    match mixed.copy_into_slice(&mut buf) {
+        Err(Error::FromLuaConversionError {
+            from: "boolean", ..
+        }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_ordered_table() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_ordered_table()?;
+    table.set("zeta", 1)?;
+    table.set("alpha", 2)?;
+    lua.globals().set("t", table.clone())?;
+    lua.load(
+        r#"
+        t.mid = 3
+        t[10] = 4
+        t.zeta = 5
+        t.alpha = nil
+        t.alpha = 6
+    "#,
+    )
+    .exec()?;
+
+    assert_eq!(lua.load("t.zeta").eval::<i64>()?, 5);
+    assert_eq!(
+        table.pairs_ordered::<Value, i64>()?,
+        vec![
+            (Value::String(lua.create_string("zeta")?), 5),
+            (Value::String(lua.create_string("mid")?), 3),
+            (Value::Integer(10), 4),
+            (Value::String(lua.create_string("alpha")?), 6),
+        ]
+    );
+
+    assert!(lua.create_table()?.pairs_ordered::<Value, Value>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_nullable_table() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_table()?.nullable();
+    table.set("present", 1)?;
+    table.set("explicit_nil", Nil)?;
+
+    assert!(table.contains_key("present")?);
+    assert!(table.contains_key("explicit_nil")?);
+    assert!(!table.contains_key("absent")?);
+
+    assert_eq!(table.get::<_, Option<i64>>("present")?, Some(1));
+    assert_eq!(table.get::<_, Option<i64>>("explicit_nil")?, None);
+    assert_eq!(table.get::<_, Option<i64>>("absent")?, None);
+
+    // Lua sees the sentinel
+    let inner = table.clone().into_inner();
+    assert_eq!(
+        inner.get::<_, Value>("explicit_nil")?,
+        NullableTable::null()
+    );
+
+    table.remove("explicit_nil")?;
+    assert!(!table.contains_key("explicit_nil")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_set_functions() -> Result<()> {
+    fn add<'lua>(lua: &'lua Lua, args: MultiValue<'lua>) -> Result<MultiValue<'lua>> {
+        let (a, b): (i64, i64) = lua.unpack_multi(args)?;
+        lua.pack_multi(a + b)
+    }
+
+    fn upper<'lua>(lua: &'lua Lua, args: MultiValue<'lua>) -> Result<MultiValue<'lua>> {
+        let s: String = lua.unpack_multi(args)?;
+        lua.pack_multi(s.to_uppercase())
+    }
+
+    fn count<'lua>(lua: &'lua Lua, args: MultiValue<'lua>) -> Result<MultiValue<'lua>> {
+        lua.pack_multi(args.len())
+    }
+
+    let lua = Lua::new();
+    let module = lua.create_table()?;
+    module.set_functions(&[("add", add), ("upper", upper), ("count", count)])?;
+    lua.globals().set("m", module)?;
+
+    assert_eq!(lua.load("m.add(2, 3)").eval::<i64>()?, 5);
+    assert_eq!(lua.load("m.upper('abc')").eval::<String>()?, "ABC");
+    assert_eq!(lua.load("m.count(1, nil, 3)").eval::<usize>()?, 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_sort() -> Result<()> {
+    let lua = Lua::new_with(mlua::StdLib::NONE, mlua::LuaOptions::default())?;
+
+    let table: Table = lua.load("{3, 1, 2}").eval()?;
+    table.sort(|a: &i64, b: &i64| b.cmp(a))?;
+    assert_eq!(
+        table
+            .clone()
+            .sequence_values::<i64>()
+            .collect::<Result<Vec<_>>>()?,
+        vec![3, 2, 1]
+    );
+
+    let table: Table = lua.load("{'pear', 'apple', 'fig'}").eval()?;
+    table.sort(|a: &String, b: &String| a.len().cmp(&b.len()).then(a.cmp(b)))?;
+    assert_eq!(
+        table
+            .sequence_values::<String>()
+            .collect::<Result<Vec<_>>>()?,
+        vec!["fig", "pear", "apple"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_typed_table() -> Result<()> {
+    let lua = Lua::new();
+
+    let config = lua.create_typed_table(&[
+        ("port", Type::Integer),
+        ("ratio", Type::Number),
+        ("host", Type::String),
+    ])?;
+    lua.globals().set("config", config.clone())?;
+
+    lua.load("config.port = 8080; config.ratio = 1; config.host = 'localhost'")
+        .exec()?;
+    assert_eq!(config.get::<_, i64>("port")?, 8080);
+    assert_eq!(lua.load("config.host").eval::<String>()?, "localhost");
+
+    match lua.load("config.port = 'http'").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => assert_eq!(
+                msg,
+                "invalid type for field 'port' (expected integer, got string)"
+            ),
+            ref err => panic!("expected RuntimeError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+    assert_eq!(config.get::<_, i64>("port")?, 8080);
+
+    assert!(lua.load("config.unknown = 1").exec().is_err());
+    lua.load("config.host = nil").exec()?;
+    assert_eq!(config.get::<_, Value>("host")?, Nil);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_freeze() -> Result<()> {
+    let lua = Lua::new();
+
+    let data: Table = lua
+        .load("{ name = 'config', items = { 1, 2, 3 } }")
+        .eval()?;
+    lua.globals().set("frozen", data.freeze()?)?;
+    lua.globals().set("deep", data.freeze_deep()?)?;
+
+    assert_eq!(lua.load("frozen.name").eval::<String>()?, "config");
+    assert_eq!(lua.load("deep.items[2]").eval::<i64>()?, 2);
+    assert!(!lua.load("getmetatable(frozen)").eval::<bool>()?);
+
+    for code in &[
+        "frozen.name = 'changed'",
+        "frozen.new_key = 1",
+        "deep.items[1] = 10",
+    ] {
+        assert!(lua.load(*code).exec().is_err(), "`{}` must fail", code);
+    }
+    assert_eq!(data.get::<_, String>("name")?, "config");
+
+    // Shallow freeze does not protect nested tables
+    lua.load("frozen.items[1] = 10").exec()?;
+    assert_eq!(lua.load("deep.items[1]").eval::<i64>()?, 10);
+    // Nested frozen proxies are cached
+    assert!(lua.load("deep.items == deep.items").eval::<bool>()?);
+
+    // Proxies do not keep the original table alive
+    let weak: Table = lua.load("setmetatable({ {} }, { __mode = 'v' })").eval()?;
+    let proxy = weak.get::<_, Table>(1)?.freeze_deep()?;
+    drop(proxy);
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(weak.get::<_, Value>(1)?, Nil);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_unpack() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua.load(r#"{1, "two", 3.0}"#).eval()?;
+    let (a, b, c): (i64, String, f64) = table.unpack()?;
+    assert_eq!(a, 1);
+    assert_eq!(b, "two");
+    assert_eq!(c, 3.0);
+
+    // Missing values are converted from nil, extra values are discarded
+    let pair: Table = lua.load(r#"{1, "x"}"#).eval()?;
+    let (a, b, c): (i64, String, Option<f64>) = pair.unpack()?;
+    assert_eq!((a, b.as_str(), c), (1, "x", None));
+    let (a,): (i64,) = table.unpack()?;
+    assert_eq!(a, 1);
+    assert_eq!(table.unpack::<MultiValue>()?.len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_drain() -> Result<()> {
+    let lua = Lua::new();
+
+    let queue: Table = lua
+        .load(r#"{ "a", "b", "c", next = "d", [10] = "e" }"#)
+        .eval()?;
+    let mut entries = queue.drain::<Value, String>()?;
+    assert_eq!(entries.len(), 5);
+    entries.sort_by(|(_, a), (_, b)| a.cmp(b));
+    let values = entries.iter().map(|(_, v)| v.as_str()).collect::<Vec<_>>();
+    assert_eq!(values, vec!["a", "b", "c", "d", "e"]);
+    assert_eq!(entries[3].0, Value::String(lua.create_string("next")?));
+    assert_eq!(entries[4].0, Value::Integer(10));
+
+    assert_eq!(queue.raw_len(), 0);
+    assert!(queue.clone().pairs::<Value, Value>().next().is_none());
+
+    // Table is left intact on conversion errors
+    queue.raw_set(1, "x")?;
+    queue.raw_set("y", true)?;
+    assert!(queue.drain::<i64, String>().is_err());
+    assert_eq!(queue.raw_get::<_, String>(1)?, "x");
+    assert_eq!(queue.drain::<Value, Value>()?.len(), 2);
+    assert!(queue.drain::<Value, Value>()?.is_empty());
+
+    Ok(())
+}
@@ -7,8 +7,8 @@ use std::sync::Arc;
 use std::{error, f32, f64, fmt};
 
 use mlua::{
-    ChunkMode, Error, ExternalError, Function, Lua, LuaOptions, Nil, Result, StdLib, String, Table,
-    UserData, Value, Variadic,
+    ChunkMode, Error, ExternalError, FromLua, Function, Lua, LuaOptions, Nil, Result, StdLib,
+    String, StringCoercion, Table, UserData, Value, Variadic,
 };
 
 #[cfg(not(feature = "luau"))]
@@ -173,6 +173,107 @@ fn test_load_mode() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_global_names() -> Result<()> {
+    let lua = Lua::new_with(StdLib::TABLE | StdLib::STRING, LuaOptions::default())?;
+    lua.globals().set("answer", 42)?;
+    lua.globals().set(1, "not a name")?;
+
+    let names = lua.global_names()?;
+    assert!(names.contains(&"answer".to_string()));
+    assert!(names.contains(&"string".to_string()));
+    assert!(names.contains(&"table".to_string()));
+    assert!(names.contains(&"print".to_string()));
+    assert!(!names.contains(&"os".to_string()));
+    assert!(!names.contains(&"io".to_string()));
+    assert!(names.windows(2).all(|w| w[0] <= w[1]));
+
+    Ok(())
+}
+
+#[test]
+fn test_call_global() -> Result<()> {
+    let lua = Lua::new();
+    lua.load(
+        r#"
+        events = {}
+        function on_event(name, value)
+            table.insert(events, name)
+            return #events, value * 2
+        end
+    "#,
+    )
+    .exec()?;
+
+    let (count, doubled): (i64, i64) = lua.call_global("on_event", ("click", 21))?;
+    assert_eq!(count, 1);
+    assert_eq!(doubled, 42);
+    assert_eq!(lua.load("events[1]").eval::<StdString>()?, "click");
+
+    lua.globals().set("not_a_function", "oops")?;
+    match lua.call_global::<_, ()>("not_a_function", ()) {
+        Err(Error::RuntimeError(msg)) => {
+            assert_eq!(
+                msg,
+                "global 'not_a_function' is not a function (got string)"
+            )
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    match lua.call_global::<_, ()>("missing", ()) {
+        Err(Error::RuntimeError(msg)) => {
+            assert_eq!(msg, "global 'missing' is not a function (got nil)")
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_track_global_writes() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals().set("existing", 0)?;
+
+    let written = lua.track_global_writes(|| {
+        lua.load("a = 1; b = 2; a = 3; existing = 1; local c = 4")
+            .exec()
+    })?;
+    assert_eq!(written, vec!["a".to_string(), "b".to_string()]);
+    assert_eq!(lua.globals().get::<_, i64>("a")?, 3);
+    assert_eq!(lua.globals().get::<_, i64>("b")?, 2);
+    assert!(lua.globals().get_metatable().is_none());
+
+    // Existing `__newindex` is preserved and still invoked
+    lua.load(
+        r#"
+        log = {}
+        setmetatable(_G, {__newindex = function(t, k, v) table.insert(log, k); rawset(t, k, v) end})
+    "#,
+    )
+    .exec()?;
+    let written = lua.track_global_writes(|| lua.load("d = 5").exec())?;
+    assert_eq!(written, vec!["d".to_string()]);
+    assert_eq!(lua.load("log[1]").eval::<StdString>()?, "d");
+    assert_eq!(lua.globals().get::<_, i64>("d")?, 5);
+
+    // Metatable is restored when `f` fails
+    assert!(lua
+        .track_global_writes(|| lua.load("e = 1; error('boom')").exec())
+        .is_err());
+    let mt = lua.globals().get_metatable().unwrap();
+    assert!(mt.get::<_, Function>("__newindex").is_ok());
+
+    // And when `f` panics
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        lua.track_global_writes(|| -> Result<()> { panic!("boom") })
+    }));
+    assert!(result.is_err());
+    assert_eq!(lua.globals().get_metatable(), Some(mt));
+
+    Ok(())
+}
+
 #[test]
 fn test_lua_multi() -> Result<()> {
     let lua = Lua::new();
@@ -219,11 +320,35 @@ fn test_coercion() -> Result<()> {
     .exec()?;
 
     let globals = lua.globals();
-    assert_eq!(globals.get::<_, String>("int")?, "123");
+    assert!(globals.get::<_, String>("int").is_err());
     assert_eq!(globals.get::<_, i32>("str")?, 123);
     assert_eq!(globals.get::<_, i32>("num")?, 123);
     assert!(globals.get::<_, String>("func").is_err());
 
+    lua.set_string_coercion(StringCoercion::Coercive);
+    assert_eq!(globals.get::<_, String>("int")?, "123");
+    assert!(globals.get::<_, String>("func").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_string_coercion_policy() -> Result<()> {
+    let lua = Lua::new();
+    let value = Value::Integer(42);
+
+    match StdString::from_lua(value.clone(), &lua) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    lua.set_string_coercion(StringCoercion::Coercive);
+    assert_eq!(StdString::from_lua(value, &lua)?, "42");
+
+    lua.set_string_coercion(StringCoercion::Strict);
+    assert!(lua.load("42").eval::<StdString>().is_err());
+    assert_eq!(lua.load("'42'").eval::<StdString>()?, "42");
+
     Ok(())
 }
 
@@ -361,6 +486,99 @@ fn test_error() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_error_rethrow() -> Result<()> {
+    #[derive(Debug)]
+    pub struct TestError(u32);
+
+    impl fmt::Display for TestError {
+        fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+            write!(fmt, "test error {}", self.0)
+        }
+    }
+
+    impl error::Error for TestError {}
+
+    let lua = Lua::new();
+
+    let rust_error_function =
+        lua.create_function(|_, ()| -> Result<()> { Err(TestError(42).to_lua_err()) })?;
+    lua.globals()
+        .set("rust_error_function", rust_error_function)?;
+
+    // Catch the error in Lua and throw it again
+    let rethrow = lua
+        .load(
+            r#"
+        function()
+            local ok, err = pcall(rust_error_function)
+            assert(not ok)
+            error(err)
+        end
+    "#,
+        )
+        .eval::<Function>()?;
+
+    match rethrow.call::<_, ()>(()) {
+        Err(Error::CallbackError { traceback, cause }) => {
+            assert!(traceback.contains("stack traceback:"), "{}", traceback);
+            match cause.as_ref() {
+                Error::ExternalError(err) => {
+                    let err = err.downcast_ref::<TestError>().expect("TestError expected");
+                    assert_eq!(err.0, 42);
+                }
+                err => panic!("expected ExternalError, got {:?}", err),
+            }
+        }
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reuse_after_panic() -> Result<()> {
+    let lua = Lua::new();
+
+    let rust_panic = lua.create_function(|_, ()| -> Result<()> { panic!("rust panic") })?;
+    lua.globals().set("rust_panic", rust_panic.clone())?;
+    lua.load("function add(a, b) return a + b end").exec()?;
+
+    for _ in 0..10 {
+        // Panic unwinding directly out of a Rust call
+        match catch_unwind(AssertUnwindSafe(|| rust_panic.call::<_, ()>(()))) {
+            Ok(r) => panic!("no panic was detected, got {:?}", r),
+            Err(p) => assert!(*p.downcast::<&str>().unwrap() == "rust panic"),
+        }
+
+        // Panic caught by `pcall` and rethrown from nested Lua frames
+        match catch_unwind(AssertUnwindSafe(|| {
+            lua.load(
+                r#"
+                local function inner()
+                    local _, err = pcall(rust_panic)
+                    error(err)
+                end
+                inner()
+            "#,
+            )
+            .exec()
+        })) {
+            Ok(r) => panic!("no panic was detected, got {:?}", r),
+            Err(p) => assert!(*p.downcast::<&str>().unwrap() == "rust panic"),
+        }
+
+        // The state must be fully usable afterwards
+        let add: Function = lua.globals().get("add")?;
+        assert_eq!(add.call::<_, i64>((1, 2))?, 3);
+        assert_eq!(lua.load("return add(2, 3)").eval::<i64>()?, 5);
+        let t = lua.create_table_from(vec![("a", 1), ("b", 2)])?;
+        assert_eq!(t.get::<_, i64>("b")?, 2);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_panic() -> Result<()> {
     fn make_lua(options: LuaOptions) -> Result<Lua> {
@@ -495,6 +713,34 @@ fn test_panic() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_panic_location() -> Result<()> {
+    let lua = Lua::new();
+    assert_eq!(lua.last_panic_location(), None);
+
+    let boom = lua.create_function(|_, ()| -> Result<()> { panic!("boom") })?;
+    lua.globals().set("boom", boom)?;
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        lua.load(
+            r#"
+            local x = 1
+            boom()
+            return x
+        "#,
+        )
+        .set_name("=script")?
+        .exec()
+    }));
+    match result {
+        Err(p) => assert_eq!(*p.downcast::<&str>().unwrap(), "boom"),
+        Ok(r) => panic!("expected panic, got {:?}", r),
+    }
+    assert_eq!(lua.last_panic_location().as_deref(), Some("script:3:"));
+
+    Ok(())
+}
+
 #[test]
 fn test_result_conversions() -> Result<()> {
     let lua = Lua::new();
@@ -558,6 +804,7 @@ fn test_num_conversion() -> Result<()> {
 
     assert_eq!(lua.load("1.0").eval::<i64>()?, 1);
     assert_eq!(lua.load("1.0").eval::<f64>()?, 1.0);
+    lua.set_string_coercion(StringCoercion::Coercive);
     #[cfg(any(feature = "lua54", feature = "lua53"))]
     assert_eq!(lua.load("1.0").eval::<String>()?, "1.0");
     #[cfg(any(feature = "lua52", feature = "lua51", feature = "luajit"))]
@@ -780,6 +1027,33 @@ fn test_drop_registry_value() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_registry_ref() -> Result<()> {
+    let lua = Lua::new();
+
+    let t = lua.create_table()?;
+    t.set("key", 123)?;
+
+    let r1 = lua.create_registry_ref(t)?;
+    let r2 = r1.clone();
+    assert_ne!(r1.id(), r2.id());
+    assert_eq!(r1.get::<Table>()?, r2.get::<Table>()?);
+    assert_eq!(r2.get::<Table>()?.get::<_, i32>("key")?, 123);
+
+    let ids = [r1.id(), r2.id()];
+    drop(r1);
+    drop(r2);
+
+    // Released registry slots are reused
+    let r3 = lua.create_registry_ref("hello")?;
+    let r4 = lua.create_registry_ref("world")?;
+    assert!(ids.contains(&r3.id()));
+    assert!(ids.contains(&r4.id()));
+    assert_eq!(r3.get::<StdString>()?, "hello");
+
+    Ok(())
+}
+
 #[test]
 fn test_replace_registry_value() -> Result<()> {
     let lua = Lua::new();
@@ -1055,6 +1329,39 @@ fn test_chunk_env() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_chunk_env_nested() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals().set("x", "global")?;
+
+    let env = lua.create_table()?;
+    env.set("x", "env")?;
+
+    let outer: Function = lua
+        .load(
+            r#"
+            local function outer()
+                local function inner()
+                    return function() return x end
+                end
+                return inner()
+            end
+            return outer
+        "#,
+        )
+        .set_environment(env.clone())?
+        .eval()?;
+
+    let innermost: Function = outer.call(())?;
+    assert_eq!(innermost.call::<_, StdString>(())?, "env");
+
+    // Environment is shared, not copied
+    env.set("x", "changed")?;
+    assert_eq!(innermost.call::<_, StdString>(())?, "changed");
+
+    Ok(())
+}
+
 #[test]
 fn test_context_thread() -> Result<()> {
     let lua = Lua::new();
@@ -1191,6 +1498,37 @@ fn test_inspect_stack() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_error_at_level() -> Result<()> {
+    let lua = Lua::new();
+
+    let check = lua.create_function(|lua, level: i32| -> Result<()> {
+        Err(lua.error_at_level("negative number", level))
+    })?;
+    lua.globals().set("check", check)?;
+
+    let error_message = |level: i32| -> StdString {
+        match lua
+            .load(&format!("\ncheck({})", level))
+            .set_name("chunk")
+            .unwrap()
+            .exec()
+        {
+            Err(Error::CallbackError { cause, .. }) => match cause.as_ref() {
+                Error::RuntimeError(msg) => msg.clone(),
+                err => panic!("expected RuntimeError, got {:?}", err),
+            },
+            res => panic!("expected CallbackError, got {:?}", res),
+        }
+    };
+
+    assert_eq!(error_message(0), "negative number");
+    assert_eq!(error_message(1), "negative number");
+    assert_eq!(error_message(2), r#"[string "chunk"]:2: negative number"#);
+
+    Ok(())
+}
+
 #[test]
 fn test_multi_states() -> Result<()> {
     let lua = Lua::new();
@@ -1252,6 +1590,40 @@ fn test_warnings() -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "lua54")]
+#[test]
+fn test_unused_args_warning() -> Result<()> {
+    let lua = Lua::new();
+    lua.set_app_data::<Vec<StdString>>(Vec::new());
+    lua.set_warning_function(|lua, msg, _| {
+        let msg = msg.to_string_lossy().to_string();
+        lua.app_data_mut::<Vec<StdString>>().unwrap().push(msg);
+        Ok(())
+    });
+
+    let add = lua.create_function(|_, (a, b): (i64, i64)| Ok(a + b))?;
+    let sum = lua.create_function(|_, args: Variadic<i64>| Ok(args.iter().sum::<i64>()))?;
+    lua.globals().set("add", add)?;
+    lua.globals().set("sum", sum)?;
+
+    // Disabled by default
+    assert_eq!(lua.load("add(1, 2, 3)").eval::<i64>()?, 3);
+    assert!(lua.app_data_ref::<Vec<StdString>>().unwrap().is_empty());
+
+    lua.set_unused_args_warning(true);
+    assert_eq!(lua.load("add(1, 2)").eval::<i64>()?, 3);
+    assert_eq!(lua.load("sum(1, 2, 3, 4)").eval::<i64>()?, 10);
+    assert!(lua.app_data_ref::<Vec<StdString>>().unwrap().is_empty());
+
+    assert_eq!(lua.load("add(1, 2, 3)").eval::<i64>()?, 3);
+    assert_eq!(
+        *lua.app_data_ref::<Vec<StdString>>().unwrap(),
+        vec!["function called with 3 arguments, but only 2 are used".to_string()]
+    );
+
+    Ok(())
+}
+
 #[test]
 #[cfg(feature = "luajit")]
 #[should_panic]
@@ -1272,3 +1644,492 @@ fn test_luajit_cdata() {
         )
         .eval();
 }
+
+#[test]
+fn test_error_introspection() -> Result<()> {
+    let lua = Lua::new();
+
+    let fail = lua
+        .create_function(|_, ()| -> Result<()> { Err(Error::RuntimeError("oops".to_string())) })?;
+    lua.globals().set("fail", fail)?;
+
+    let check = || {
+        lua.load(
+            r#"
+        local ok, err = pcall(fail)
+        assert(not ok)
+        local mt = getmetatable(err)
+        if mt == false then
+            return "protected"
+        end
+        assert(type(mt.__tostring) == "function")
+        return err.type
+    "#,
+        )
+        .eval::<StdString>()
+    };
+
+    assert_eq!(check()?, "protected");
+
+    lua.set_error_introspection(true)?;
+    assert_eq!(check()?, "error");
+
+    lua.set_error_introspection(false)?;
+    assert_eq!(check()?, "protected");
+
+    Ok(())
+}
+
+#[test]
+fn test_transaction() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals().set("existing", 1)?;
+
+    let result = lua.transaction(|lua| lua.load("added = 1; existing = 2; error('fail')").exec());
+    assert!(result.is_err());
+    assert_eq!(lua.globals().get::<_, Value>("added")?, Value::Nil);
+    assert_eq!(lua.globals().get::<_, i64>("existing")?, 1);
+
+    let n = lua.transaction(|lua| {
+        lua.load("added = 1").exec()?;
+        lua.globals().get::<_, i64>("added")
+    })?;
+    assert_eq!(n, 1);
+    assert_eq!(lua.globals().get::<_, i64>("added")?, 1);
+
+    // Registry values created by a failed transaction are released
+    let mut leaked = None;
+    let result = lua.transaction(|lua| {
+        leaked = Some(lua.create_registry_value("leaked")?);
+        lua.create_registry_value("dropped")?;
+        Err::<(), _>(Error::RuntimeError("fail".to_string()))
+    });
+    assert!(result.is_err());
+    assert_eq!(lua.registry_value::<Value>(&leaked.unwrap())?, Value::Nil);
+
+    // Globals are restored when `f` panics
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        lua.transaction(|lua| -> Result<()> {
+            lua.globals().set("existing", 3)?;
+            panic!("boom")
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(lua.globals().get::<_, i64>("existing")?, 1);
+
+    // Slots of keys released by the rollback are not reused while the keys are alive
+    let lua = Lua::new();
+    let mut leaked = None;
+    let result = lua.transaction(|lua| {
+        leaked = Some(lua.create_registry_value("leaked")?);
+        Err::<(), _>(Error::RuntimeError("fail".to_string()))
+    });
+    assert!(result.is_err());
+    let leaked = leaked.unwrap();
+    let victim = lua.create_registry_value("victim")?;
+    drop(leaked);
+    lua.expire_registry_values();
+    let overwriter = lua.create_registry_value("overwriter")?;
+    assert_eq!(lua.registry_value::<String>(&victim)?, "victim");
+    assert_eq!(lua.registry_value::<String>(&overwriter)?, "overwriter");
+
+    Ok(())
+}
+
+#[test]
+fn test_create_namespace() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.create_namespace("geo", |geo| {
+        geo.set("earth_radius", 6371)?;
+        geo.set(
+            "distance",
+            lua.create_function(|_, (x1, y1, x2, y2): (f64, f64, f64, f64)| {
+                Ok(((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt())
+            })?,
+        )
+    })?;
+
+    assert_eq!(lua.load("geo.distance(0, 0, 3, 4)").eval::<f64>()?, 5.0);
+    assert_eq!(lua.load("geo.earth_radius").eval::<i64>()?, 6371);
+
+    match lua.load("geo.x = 1").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => assert_eq!(msg, "attempt to modify a frozen table"),
+            ref e => panic!("expected RuntimeError, got {:?}", e),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+    assert_eq!(lua.load("geo.x").eval::<Value>()?, Value::Nil);
+
+    Ok(())
+}
+
+#[test]
+fn test_global_resolver() -> Result<()> {
+    let lua = Lua::new();
+
+    let calls = Arc::new(AtomicU32::new(0));
+    let calls2 = calls.clone();
+    lua.set_global_resolver(move |lua, name| {
+        calls2.fetch_add(1, Ordering::SeqCst);
+        match name {
+            "helper" => Ok(Some(Value::Function(
+                lua.create_function(|_, x: i64| Ok(x * 2))?,
+            ))),
+            _ => Ok(None),
+        }
+    })?;
+
+    assert_eq!(lua.load("helper(21)").eval::<i64>()?, 42);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Subsequent accesses hit the cached value in globals
+    assert_eq!(lua.load("helper(1) + helper(2)").eval::<i64>()?, 6);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!(lua.globals().raw_get::<_, Function>("helper").is_ok());
+
+    // Unresolved names are not cached
+    assert_eq!(lua.load("unknown").eval::<Value>()?, Nil);
+    assert_eq!(lua.load("unknown").eval::<Value>()?, Nil);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_global_modules() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.add_global_module("gfx_", |_, name| match name {
+        "gfx_width" => Ok(Some(Value::Integer(640))),
+        _ => Ok(None),
+    })?;
+    lua.add_global_module("net_", |lua, name| match name {
+        "net_host" => Ok(Some(Value::String(lua.create_string("localhost")?))),
+        _ => Ok(None),
+    })?;
+    // Modules with matching prefixes are consulted in order
+    lua.add_global_module("gfx_", |_, name| match name {
+        "gfx_width" => Ok(Some(Value::Integer(1024))),
+        "gfx_height" => Ok(Some(Value::Integer(480))),
+        _ => Ok(None),
+    })?;
+
+    assert_eq!(lua.load("gfx_width").eval::<i64>()?, 640);
+    assert_eq!(lua.load("gfx_height").eval::<i64>()?, 480);
+    assert_eq!(lua.load("net_host").eval::<StdString>()?, "localhost");
+    assert_eq!(lua.load("net_port").eval::<Value>()?, Nil);
+    assert_eq!(lua.load("other_host").eval::<Value>()?, Nil);
+
+    // Resolved values are cached in globals
+    assert_eq!(lua.globals().raw_get::<_, i64>("gfx_width")?, 640);
+
+    Ok(())
+}
+
+#[test]
+fn test_raw_state() -> Result<()> {
+    use std::os::raw::c_int;
+
+    extern "C" {
+        fn lua_gettop(state: *mut mlua::lua_State) -> c_int;
+        fn lua_settop(state: *mut mlua::lua_State, idx: c_int);
+        fn lua_pushboolean(state: *mut mlua::lua_State, b: c_int);
+        fn lua_toboolean(state: *mut mlua::lua_State, idx: c_int) -> c_int;
+    }
+
+    let lua = Lua::new();
+
+    unsafe {
+        let state = lua.state();
+        let top = lua_gettop(state);
+        lua_pushboolean(state, 1);
+        assert_eq!(lua_gettop(state), top + 1);
+        assert_eq!(lua_toboolean(state, -1), 1);
+        // Keep the stack balanced
+        lua_settop(state, top);
+    }
+
+    lua.globals().set("x", 123)?;
+    assert_eq!(lua.load("x + 1").eval::<i64>()?, 124);
+
+    Ok(())
+}
+
+#[test]
+fn test_with_stack_guard() -> Result<()> {
+    use std::os::raw::c_int;
+
+    extern "C" {
+        fn lua_gettop(state: *mut mlua::lua_State) -> c_int;
+        fn lua_settop(state: *mut mlua::lua_State, idx: c_int);
+        fn lua_pushboolean(state: *mut mlua::lua_State, b: c_int);
+    }
+
+    let lua = Lua::new();
+    let state = unsafe { lua.state() };
+    let top = unsafe { lua_gettop(state) };
+
+    // Balanced push/pop
+    lua.with_stack_guard(0, || unsafe {
+        lua_pushboolean(state, 1);
+        lua_pushboolean(state, 0);
+        lua_settop(state, -3);
+        Ok(())
+    })?;
+    assert_eq!(unsafe { lua_gettop(state) }, top);
+
+    // Extra values are removed, keeping `change` values
+    lua.with_stack_guard(1, || unsafe {
+        lua_pushboolean(state, 1);
+        lua_pushboolean(state, 1);
+        Ok(())
+    })?;
+    assert_eq!(unsafe { lua_gettop(state) }, top + 1);
+    unsafe { lua_settop(state, top) };
+
+    // Stack is restored on error
+    let result = lua.with_stack_guard(0, || -> Result<()> {
+        unsafe { lua_pushboolean(state, 1) };
+        Err(Error::RuntimeError("fail".to_string()))
+    });
+    assert!(result.is_err());
+    assert_eq!(unsafe { lua_gettop(state) }, top);
+
+    // Over-popping panics
+    unsafe { lua_pushboolean(state, 1) };
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        lua.with_stack_guard(0, || unsafe {
+            lua_settop(state, -2);
+            Ok(())
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(unsafe { lua_gettop(state) }, top);
+
+    Ok(())
+}
+
+#[test]
+fn test_error_with_level() -> Result<()> {
+    let lua = Lua::new();
+
+    let check = lua.create_function(|_, x: i64| {
+        if x < 0 {
+            let err = Error::RuntimeError("value must be positive".to_string());
+            return Err(Error::WithLevel(Box::new(err), 2));
+        }
+        Ok(x)
+    })?;
+    lua.globals().set("check", check)?;
+
+    let result = lua
+        .load(
+            r#"
+            local x = 1
+            local y = check(-x)
+            return y
+        "#,
+        )
+        .set_name("=test")?
+        .exec();
+    match result {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => assert_eq!(msg, "test:3: value must be positive"),
+            ref err => panic!("expected RuntimeError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    // Level 1 points at the Rust callback, so no position is added
+    let result = lua
+        .create_function(|_, ()| -> Result<()> {
+            let err = Error::RuntimeError("no position".to_string());
+            Err(Error::WithLevel(Box::new(err), 1))
+        })?
+        .call::<_, ()>(());
+    match result {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => assert_eq!(msg, "no position"),
+            ref err => panic!("expected RuntimeError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_fork() -> Result<()> {
+    let lua = Lua::new();
+    lua.load(
+        r#"
+        name = "parent"
+        limits = { max = 10, ratio = 0.5 }
+        handler = function() end
+    "#,
+    )
+    .exec()?;
+
+    let child = lua.fork()?;
+    assert_eq!(child.globals().get::<_, StdString>("name")?, "parent");
+    assert_eq!(child.load("limits.max").eval::<i64>()?, 10);
+    // Functions are not copied, standard libraries are loaded anew
+    assert_eq!(child.globals().get::<_, Value>("handler")?, Nil);
+    assert_eq!(child.load("string.upper('a')").eval::<StdString>()?, "A");
+
+    // Mutations don't cross between states
+    child.load("name = 'child'; limits.max = 20").exec()?;
+    lua.globals().set("extra", true)?;
+    assert_eq!(lua.globals().get::<_, StdString>("name")?, "parent");
+    assert_eq!(lua.load("limits.max").eval::<i64>()?, 10);
+    assert_eq!(child.globals().get::<_, Value>("extra")?, Nil);
+
+    Ok(())
+}
+
+#[cfg(feature = "trace")]
+#[test]
+fn test_api_trace() -> Result<()> {
+    use std::sync::Mutex;
+
+    let lua = Lua::new();
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let log2 = log.clone();
+    lua.set_api_trace(move |line| log2.lock().unwrap().push(line.to_string()));
+
+    let globals = lua.globals();
+    globals.set("answer", 42)?;
+    assert_eq!(globals.get::<_, i64>("answer")?, 42);
+    lua.load("return ...")
+        .set_name("=chunk")?
+        .call::<_, ()>("arg")?;
+
+    lua.remove_api_trace();
+    globals.set("ignored", true)?;
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            r#"Table::set("answer", 42)"#,
+            r#"Table::get("answer")"#,
+            r#"Lua::load("=chunk")"#,
+            r#"Function::call("arg")"#,
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_deep_eq() -> Result<()> {
+    let lua = Lua::new();
+
+    let a: Value = lua.load(r#"{1, 2, {x = "a", y = {true}}, n = 3}"#).eval()?;
+    let b: Value = lua
+        .load(r#"{1.0, 2, {y = {true}, x = "a"}, n = 3.0}"#)
+        .eval()?;
+    assert!(a != b);
+    assert!(lua.deep_eq(&a, &b)?);
+
+    let c: Value = lua
+        .load(r#"{1, 2, {x = "a", y = {false}}, n = 3}"#)
+        .eval()?;
+    assert!(!lua.deep_eq(&a, &c)?);
+    let d: Value = lua.load(r#"{1, 2, {x = "a", y = {true}}}"#).eval()?;
+    assert!(!lua.deep_eq(&a, &d)?);
+    assert!(!lua.deep_eq(&d, &a)?);
+
+    // Functions are compared by identity
+    let f1: Value = lua.load("{f = print}").eval()?;
+    let f2: Value = lua.load("{f = print}").eval()?;
+    let f3: Value = lua.load("{f = function() end}").eval()?;
+    assert!(lua.deep_eq(&f1, &f2)?);
+    assert!(!lua.deep_eq(&f1, &f3)?);
+
+    // Cyclic tables
+    let (x, y): (Value, Value) = lua
+        .load(
+            r#"
+            local x = {name = "node"}
+            x.next = x
+            local y = {name = "node"}
+            y.next = y
+            return x, y
+        "#,
+        )
+        .eval()?;
+    assert!(lua.deep_eq(&x, &y)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_associate() -> Result<()> {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Origin {
+        file: StdString,
+        line: u32,
+    }
+
+    let lua = Lua::new();
+
+    let origin = Origin {
+        file: "config.lua".into(),
+        line: 10,
+    };
+    let table = Value::Table(lua.create_table()?);
+    lua.associate(&table, origin.clone())?;
+    lua.associate(&table, 42u32)?;
+    assert_eq!(lua.associated::<Origin>(&table)?, Some(origin));
+    assert_eq!(lua.associated::<u32>(&table)?, Some(42));
+    assert_eq!(lua.associated::<i64>(&table)?, None);
+
+    // Replace the associated data
+    lua.associate(&table, 43u32)?;
+    assert_eq!(lua.associated::<u32>(&table)?, Some(43));
+
+    let other = Value::Table(lua.create_table()?);
+    assert_eq!(lua.associated::<Origin>(&other)?, None);
+
+    match lua.associate(&Value::Integer(1), ()) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    // The data is dropped when the value is collected
+    let data = Arc::new(());
+    lua.associate(&table, data.clone())?;
+    assert_eq!(Arc::strong_count(&data), 2);
+    drop(table);
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(Arc::strong_count(&data), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_error_format_colored() -> Result<()> {
+    let lua = Lua::new();
+
+    let func = lua.create_function(|_, ()| Err::<(), _>(Error::RuntimeError("boom".into())))?;
+    lua.globals().set("func", func)?;
+    let err = match lua.load("func()").set_name("chunk")?.exec() {
+        Err(err @ Error::CallbackError { .. }) => err,
+        r => panic!("expected CallbackError, got {:?}", r),
+    };
+
+    let colored = err.format_colored();
+    if cfg!(feature = "color") && std::env::var_os("NO_COLOR").is_none() {
+        assert!(colored.contains("\x1b[1;31mcaused by: runtime error: boom\x1b[0m"));
+        assert!(colored.contains("\x1b[36m[string \"chunk\"]:1\x1b[0m"));
+        assert_ne!(colored, err.to_string());
+    } else {
+        assert_eq!(colored, err.to_string());
+    }
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
 use std::panic::catch_unwind;
+use std::sync::mpsc;
 
 use mlua::{Error, Function, Lua, Result, Thread, ThreadStatus};
 
@@ -153,6 +154,58 @@ fn test_thread_reset() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_thread_drain_into() -> Result<()> {
+    let lua = Lua::new();
+
+    let thread: Thread = lua
+        .load(
+            r#"
+            coroutine.create(function(n)
+                for i = 1, n do
+                    coroutine.yield(i)
+                end
+                return "done"
+            end)
+        "#,
+        )
+        .eval()?;
+
+    let (tx, rx) = mpsc::channel();
+    thread.drain_into::<_, i64>(tx, 3);
+    let values = std::thread::spawn(move || rx.iter().collect::<Vec<_>>())
+        .join()
+        .unwrap();
+    assert_eq!(
+        values.into_iter().collect::<Result<Vec<_>>>()?,
+        vec![1, 2, 3]
+    );
+
+    // Errors are sent to the channel
+    let thread: Thread = lua
+        .load(
+            r#"
+            coroutine.create(function()
+                coroutine.yield(1)
+                error("boom")
+            end)
+        "#,
+        )
+        .eval()?;
+
+    let (tx, rx) = mpsc::channel();
+    thread.drain_into::<_, i64>(tx, ());
+    let mut values = rx.iter();
+    assert_eq!(values.next().unwrap()?, 1);
+    match values.next() {
+        Some(Err(Error::RuntimeError(_))) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    assert!(values.next().is_none());
+
+    Ok(())
+}
+
 #[test]
 fn test_coroutine_from_closure() -> Result<()> {
     let lua = Lua::new();
@@ -194,3 +247,169 @@ fn test_coroutine_panic() {
         Err(p) => assert!(*p.downcast::<&str>().unwrap() == "test_panic"),
     }
 }
+
+#[test]
+fn test_thread_name() -> Result<()> {
+    let lua = Lua::new();
+
+    let func = lua
+        .load("function() error('boom') end")
+        .eval::<Function>()?;
+    let worker = lua.create_thread(func.clone())?;
+    worker.set_name("worker-1")?;
+    let unnamed = lua.create_thread(func)?;
+
+    match worker.resume::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.contains("boom (in thread 'worker-1')"), "{}", msg);
+            assert!(msg.contains("stack traceback"), "{}", msg);
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    match unnamed.resume::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => assert!(!msg.contains("in thread"), "{}", msg),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+fn test_thread_is_yieldable() -> Result<()> {
+    let lua = Lua::new();
+
+    let check = lua.create_function(|lua, ()| Ok(lua.current_thread().is_yieldable()))?;
+    lua.globals().set("check", check)?;
+
+    assert!(!lua.current_thread().is_yieldable());
+    assert!(!lua.load("check()").eval::<bool>()?);
+
+    let co = lua.create_thread(lua.load("return check()").into_function()?)?;
+    assert!(co.resume::<_, bool>(())?);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_thread_close() -> Result<()> {
+    use mlua::{MetaMethod, UserData, UserDataMethods};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let lua = Lua::new();
+
+    struct Guard(Arc<AtomicBool>);
+    impl UserData for Guard {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_meta_method(MetaMethod::Close, |_, this, ()| {
+                this.0.store(true, Ordering::Relaxed);
+                Ok(())
+            });
+        }
+    }
+
+    let closed = Arc::new(AtomicBool::new(false));
+    let func: Function = lua
+        .load(
+            r#"
+            function(guard)
+                local g <close> = guard
+                coroutine.yield()
+            end
+        "#,
+        )
+        .eval()?;
+    let thread = lua.create_thread(func)?;
+    thread.resume::<_, ()>(Guard(closed.clone()))?;
+    assert_eq!(thread.status(), ThreadStatus::Resumable);
+    assert!(!closed.load(Ordering::Relaxed));
+
+    thread.close()?;
+    assert!(closed.load(Ordering::Relaxed));
+    assert_eq!(thread.status(), ThreadStatus::Unresumable);
+
+    // Errors from `__close` are returned
+    let func: Function = lua
+        .load(
+            r#"
+            function()
+                local g <close> = setmetatable({}, {__close = function() error("close error") end})
+                coroutine.yield()
+            end
+        "#,
+        )
+        .eval()?;
+    let thread = lua.create_thread(func)?;
+    thread.resume::<_, ()>(())?;
+    match thread.close() {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("close error")),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    // Running and normal threads cannot be closed
+    let close_thread = lua
+        .create_function(|_, thread: Thread| Ok(thread.close().err().map(|err| err.to_string())))?;
+    lua.globals().set("close_thread", close_thread)?;
+    let err: String = lua
+        .load(
+            r#"
+            local a
+            a = coroutine.create(function()
+                local b = coroutine.create(function() return close_thread(a) end)
+                local _, err = coroutine.resume(b)
+                coroutine.yield(err)
+            end)
+            local _, err = coroutine.resume(a)
+            assert(coroutine.status(a) == "suspended")
+            return err
+        "#,
+        )
+        .eval()?;
+    assert!(err.contains("cannot close a normal coroutine"), "{}", err);
+
+    let err: String = lua
+        .load(
+            r#"
+            local main = coroutine.running()
+            return coroutine.wrap(function() return close_thread(main) end)()
+        "#,
+        )
+        .eval()?;
+    assert!(err.contains("cannot close a normal coroutine"), "{}", err);
+
+    Ok(())
+}
+
+#[test]
+fn test_active_threads() -> Result<()> {
+    let lua = Lua::new();
+
+    let func: Function = lua.load("function() coroutine.yield() end").eval()?;
+    // Threads are not tracked until enabled
+    let _untracked = lua.create_thread(func.clone())?;
+    assert!(lua.active_threads()?.is_empty());
+
+    lua.enable_thread_tracking();
+    let thread1 = lua.create_thread(func.clone())?;
+    let thread2 = lua.create_thread(func)?;
+
+    let active = lua.active_threads()?;
+    assert_eq!(active.len(), 2);
+    assert!(active.contains(&thread1));
+    assert!(active.contains(&thread2));
+    drop(active);
+
+    // Run the first thread to death
+    thread1.resume::<_, ()>(())?;
+    thread1.resume::<_, ()>(())?;
+    assert_eq!(thread1.status(), ThreadStatus::Unresumable);
+    drop(thread1);
+    lua.gc_collect()?;
+
+    let active = lua.active_threads()?;
+    assert_eq!(active, vec![thread2]);
+
+    Ok(())
+}
@@ -7,8 +7,8 @@ use std::{cell::RefCell, rc::Rc};
 use std::sync::atomic::{AtomicI64, Ordering};
 
 use mlua::{
-    AnyUserData, Error, ExternalError, Function, Lua, MetaMethod, Nil, Result, String, UserData,
-    UserDataFields, UserDataMethods, Value,
+    AnyUserData, Error, ExternalError, FromLua, Function, Lua, MetaMethod, Nil, Result, String,
+    ToLua, Type, TypedMetaHandler, UserData, UserDataFields, UserDataMethods, Value,
 };
 
 #[test]
@@ -34,6 +34,64 @@ fn test_user_data() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_trait_object() -> Result<()> {
+    trait Greeter: Send {
+        fn greet(&self, name: &str) -> std::string::String;
+    }
+
+    struct English;
+    struct Norwegian {
+        exclamation: bool,
+    }
+
+    impl Greeter for English {
+        fn greet(&self, name: &str) -> std::string::String {
+            format!("hello, {}", name)
+        }
+    }
+
+    impl Greeter for Norwegian {
+        fn greet(&self, name: &str) -> std::string::String {
+            let suffix = if self.exclamation { "!" } else { "" };
+            format!("hei, {}{}", name, suffix)
+        }
+    }
+
+    impl UserData for Box<dyn Greeter> {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("greet", |_, this, name: std::string::String| {
+                Ok(this.greet(&name))
+            });
+        }
+    }
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    let english: Box<dyn Greeter> = Box::new(English);
+    let norwegian: Box<dyn Greeter> = Box::new(Norwegian { exclamation: true });
+    globals.set("english", english)?;
+    globals.set("norwegian", lua.create_userdata(norwegian)?)?;
+
+    assert_eq!(
+        lua.load(r#"english:greet("world")"#)
+            .eval::<std::string::String>()?,
+        "hello, world"
+    );
+    assert_eq!(
+        lua.load(r#"norwegian:greet("verden")"#)
+            .eval::<std::string::String>()?,
+        "hei, verden!"
+    );
+
+    let ud = globals.get::<_, AnyUserData>("norwegian")?;
+    assert!(ud.is::<Box<dyn Greeter>>());
+    assert_eq!(ud.borrow::<Box<dyn Greeter>>()?.greet("Rust"), "hei, Rust!");
+
+    Ok(())
+}
+
 #[test]
 fn test_methods() -> Result<()> {
     #[cfg_attr(feature = "serialize", derive(serde::Serialize))]
@@ -190,6 +248,35 @@ fn test_metamethods() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_userdata_value_eq() -> Result<()> {
+    #[derive(PartialEq)]
+    struct Point(i32, i32);
+
+    impl UserData for Point {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_value_eq();
+        }
+    }
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals.set("p1", Point(1, 2))?;
+    globals.set("p2", Point(1, 2))?;
+    globals.set("p3", Point(3, 4))?;
+
+    lua.load(
+        r#"
+        assert(p1 == p2)
+        assert(p1 ~= p3)
+        assert(not rawequal(p1, p2))
+    "#,
+    )
+    .exec()?;
+
+    Ok(())
+}
+
 #[test]
 #[cfg(feature = "lua54")]
 fn test_metamethod_close() -> Result<()> {
@@ -645,3 +732,387 @@ fn test_userdata_wrapped() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_userdata_shared_mutex() -> Result<()> {
+    struct Counter(i32);
+
+    impl UserData for Counter {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("get", |_, this, ()| Ok(this.0));
+            methods.add_method_mut("increment", |_, this, ()| {
+                this.0 += 1;
+                Ok(this.0)
+            });
+        }
+    }
+
+    let lua = Lua::new();
+    let counter = Arc::new(Mutex::new(Counter(0)));
+    lua.globals().set("counter", counter.clone())?;
+
+    let handles = (0..4)
+        .map(|_| {
+            let counter = counter.clone();
+            std::thread::spawn(move || counter.lock().unwrap().0 += 10)
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    lua.load("for i = 1, 5 do counter:increment() end").exec()?;
+    assert_eq!(counter.lock().unwrap().0, 45);
+    assert_eq!(lua.load("counter:get()").eval::<i32>()?, 45);
+
+    // Poisoned lock must be reported as an error
+    let counter2 = counter.clone();
+    let _ = std::thread::spawn(move || {
+        let _guard = counter2.lock().unwrap();
+        panic!("poison the lock");
+    })
+    .join();
+    assert!(counter.is_poisoned());
+    match lua.load("counter:increment()").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match *cause.as_ref() {
+            Error::UserDataBorrowMutError => {}
+            ref err => panic!("expected UserDataBorrowMutError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_display_precision() -> Result<()> {
+    struct Num(f64);
+
+    impl UserData for Num {
+        const DISPLAY_PRECISION: Option<usize> = Some(2);
+
+        fn display_number(&self) -> Option<f64> {
+            Some(self.0)
+        }
+    }
+
+    struct Custom(f64);
+
+    impl UserData for Custom {
+        const DISPLAY_PRECISION: Option<usize> = Some(2);
+
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_meta_method(MetaMethod::ToString, |_, this, ()| {
+                Ok(format!("custom {}", this.0))
+            });
+        }
+
+        fn display_number(&self) -> Option<f64> {
+            Some(self.0)
+        }
+    }
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals.set("pi", Num(std::f64::consts::PI))?;
+    globals.set("custom", Custom(1.5))?;
+
+    assert_eq!(lua.load("tostring(pi)").eval::<String>()?, "3.14");
+    // An explicitly added `__tostring` takes precedence
+    assert_eq!(lua.load("tostring(custom)").eval::<String>()?, "custom 1.5");
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_drop_on_close() -> Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl UserData for DropFlag {}
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    struct PanicOnDrop;
+
+    impl UserData for PanicOnDrop {}
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            panic!("panic in finalizer");
+        }
+    }
+
+    let dropped = Arc::new(AtomicBool::new(false));
+    let lua = Lua::new();
+    lua.globals().set("panicky", PanicOnDrop)?;
+    lua.globals().set("flag", DropFlag(dropped.clone()))?;
+    lua.globals().set("panicky2", PanicOnDrop)?;
+
+    assert!(!dropped.load(Ordering::SeqCst));
+    drop(lua);
+    // Finalizers ran on close and panics in them did not escape
+    assert!(dropped.load(Ordering::SeqCst));
+
+    Ok(())
+}
+
+#[test]
+fn test_register_userdata() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static BUILD_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct MyUserData(i64);
+
+    impl UserData for MyUserData {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            BUILD_COUNT.fetch_add(1, Ordering::SeqCst);
+            methods.add_method("get", |_, this, ()| Ok(this.0));
+        }
+    }
+
+    let lua = Lua::new();
+    lua.register_userdata::<MyUserData>()?;
+    assert_eq!(BUILD_COUNT.load(Ordering::SeqCst), 1);
+
+    // Registering again and creating instances reuses the cached metatable
+    lua.register_userdata::<MyUserData>()?;
+    lua.globals().set("ud", MyUserData(42))?;
+    assert_eq!(lua.load("ud:get()").eval::<i64>()?, 42);
+    assert_eq!(BUILD_COUNT.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_typed_meta_method() -> Result<()> {
+    #[derive(Clone, Copy)]
+    struct Vec2(f64, f64);
+
+    impl UserData for Vec2 {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("x", |_, v, ()| Ok(v.0));
+            methods.add_method("y", |_, v, ()| Ok(v.1));
+
+            let add_number: TypedMetaHandler<Vec2> = Box::new(|lua, v, n| {
+                let n = f64::from_lua(n, lua)?;
+                Vec2(v.0 + n, v.1 + n).to_lua(lua)
+            });
+            let add_vec: TypedMetaHandler<Vec2> = Box::new(|lua, v, other| {
+                let other = Vec2::from_lua(other, lua)?;
+                Vec2(v.0 + other.0, v.1 + other.1).to_lua(lua)
+            });
+            methods.add_meta_method_typed(
+                MetaMethod::Add,
+                vec![(Type::Number, add_number), (Type::UserData, add_vec)],
+                |_, _, other| {
+                    Err(Error::RuntimeError(format!(
+                        "cannot add {} to Vec2",
+                        other.type_name()
+                    )))
+                },
+            );
+        }
+    }
+
+    let lua = Lua::new();
+    lua.globals().set("v", Vec2(1.0, 2.0))?;
+
+    let (x, y) = lua
+        .load("local r = v + 1 return r:x(), r:y()")
+        .eval::<(f64, f64)>()?;
+    assert_eq!((x, y), (2.0, 3.0));
+
+    let (x, y) = lua
+        .load("local r = v + v return r:x(), r:y()")
+        .eval::<(f64, f64)>()?;
+    assert_eq!((x, y), (2.0, 4.0));
+
+    match lua.load("return v + {}").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => assert_eq!(msg, "cannot add table to Vec2"),
+            ref e => panic!("expected RuntimeError, got {:?}", e),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_method_fallback() -> Result<()> {
+    struct Remote;
+
+    impl UserData for Remote {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("ping", |_, _, ()| Ok("pong"));
+            methods.add_method_fallback(|lua, _, name| {
+                let field = match name.strip_prefix("get_") {
+                    Some(field) => field.to_string(),
+                    None => return Ok(None),
+                };
+                let func = lua.create_function(move |_, (_, id): (AnyUserData, i64)| {
+                    Ok(format!("{}#{}", field, id))
+                })?;
+                Ok(Some(func))
+            });
+        }
+    }
+
+    let lua = Lua::new();
+    lua.globals().set("remote", Remote)?;
+
+    assert_eq!(lua.load("remote:ping()").eval::<String>()?, "pong");
+    assert_eq!(lua.load("remote:get_user(5)").eval::<String>()?, "user#5");
+
+    match lua.load("remote:unknown()").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => assert_eq!(msg, "unknown method 'unknown' on userdata"),
+            ref e => panic!("expected RuntimeError, got {:?}", e),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_tagged_userdata() -> Result<()> {
+    struct Connection(i64);
+
+    impl UserData for Connection {}
+
+    let lua = Lua::new();
+
+    let conn1 = lua.create_tagged_userdata("db", Connection(1))?;
+    let conn2 = lua.create_tagged_userdata("db", Connection(2))?;
+    let _other = lua.create_tagged_userdata("http", Connection(3))?;
+    lua.create_userdata(Connection(4))?;
+
+    let mut ids = lua
+        .tagged_userdata("db")?
+        .iter()
+        .map(|ud| Ok(ud.borrow::<Connection>()?.0))
+        .collect::<Result<Vec<_>>>()?;
+    ids.sort_unstable();
+    assert_eq!(ids, vec![1, 2]);
+    assert!(lua.tagged_userdata("none")?.is_empty());
+
+    drop(conn1);
+    // Finalized userdata are removed from weak keys on the next cycle
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+
+    let db = lua.tagged_userdata("db")?;
+    assert_eq!(db.len(), 1);
+    assert_eq!(db[0], conn2);
+
+    Ok(())
+}
+
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+#[test]
+fn test_userdata_in_finalizer() -> Result<()> {
+    struct Connection;
+
+    impl UserData for Connection {}
+
+    let lua = Lua::new();
+    let connections = lua.create_table()?;
+    lua.globals().set("connections", connections.clone())?;
+
+    for id in 1..=2 {
+        let conn = lua.create_userdata(Connection)?;
+        connections.raw_set(id, format!("connection {}", id))?;
+        lua.in_finalizer(&conn, move |lua| {
+            let connections: mlua::Table = lua.globals().raw_get("connections")?;
+            connections.raw_set(id, Nil)
+        })?;
+        if id == 2 {
+            lua.globals().set("conn", conn)?;
+        }
+    }
+
+    // The finalizer runs on the cycle following the userdata collection
+    for _ in 0..3 {
+        lua.gc_collect()?;
+    }
+    assert_eq!(connections.raw_get::<_, Option<String>>(1)?, None);
+    assert_eq!(connections.raw_get::<_, String>(2)?, "connection 2");
+
+    lua.globals().raw_remove("conn")?;
+    for _ in 0..3 {
+        lua.gc_collect()?;
+    }
+    assert_eq!(connections.raw_len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_fallible_method() -> Result<()> {
+    #[derive(Debug, PartialEq)]
+    enum AccountError {
+        InsufficientFunds { balance: i64, requested: i64 },
+    }
+
+    impl std::fmt::Display for AccountError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                AccountError::InsufficientFunds { balance, requested } => write!(
+                    f,
+                    "insufficient funds: balance {}, requested {}",
+                    balance, requested
+                ),
+            }
+        }
+    }
+
+    impl std::error::Error for AccountError {}
+
+    struct Account(i64);
+
+    impl UserData for Account {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method_fallible("check_withdraw", |_, this, amount: i64| {
+                if amount > this.0 {
+                    return Err(AccountError::InsufficientFunds {
+                        balance: this.0,
+                        requested: amount,
+                    });
+                }
+                Ok(this.0 - amount)
+            });
+        }
+    }
+
+    let lua = Lua::new();
+    lua.globals().set("account", Account(100))?;
+
+    let rest: i64 = lua.load("account:check_withdraw(30)").eval()?;
+    assert_eq!(rest, 70);
+
+    match lua.load("account:check_withdraw(130)").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::ExternalError(ref err) => {
+                assert_eq!(
+                    err.downcast_ref::<AccountError>(),
+                    Some(&AccountError::InsufficientFunds {
+                        balance: 100,
+                        requested: 130
+                    })
+                );
+            }
+            ref err => panic!("expected ExternalError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
@@ -1,4 +1,6 @@
-use mlua::{Lua, Result, Value};
+use std::collections::HashMap;
+
+use mlua::{Error, Key, Lua, MultiValue, OwnedValue, Result, Table, Value, Variadic};
 
 #[test]
 fn test_value_eq() -> Result<()> {
@@ -55,3 +57,155 @@ fn test_value_eq() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_value_into_owned() -> Result<()> {
+    let lua = Lua::new();
+
+    let s = lua.create_string("hello")?;
+    assert_eq!(
+        Value::String(s).into_owned()?,
+        OwnedValue::String(b"hello".to_vec())
+    );
+    assert_eq!(Value::Integer(42).into_owned()?, OwnedValue::Integer(42));
+    assert_eq!(Value::Nil.into_owned()?, OwnedValue::Nil);
+
+    let t = lua.create_table()?;
+    match Value::Table(t).into_owned() {
+        Err(Error::FromLuaConversionError { from: "table", .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    let f = lua.create_function(|_, ()| Ok(()))?;
+    match Value::Function(f).into_owned() {
+        Err(Error::FromLuaConversionError {
+            from: "function", ..
+        }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    // Owned values can be sent to other threads
+    let v = lua.load("'from lua'").eval::<Value>()?.into_owned()?;
+    let v = std::thread::spawn(move || v).join().unwrap();
+    assert_eq!(v, OwnedValue::String(b"from lua".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn test_transfer_value() -> Result<()> {
+    let lua_a = Lua::new();
+    let lua_b = Lua::new();
+
+    let table = lua_a
+        .load("{ 1, 2.5, { x = 10, y = { 20, 30 } }, name = 'nested' }")
+        .eval::<Table>()?;
+    let owned = OwnedValue::from_table(table)?;
+
+    lua_b.globals().set("data", lua_b.transfer_value(&owned)?)?;
+    lua_b
+        .load(
+            r#"
+            assert(#data == 3)
+            assert(data[1] == 1 and data[2] == 2.5)
+            assert(data[3].x == 10)
+            assert(data[3].y[1] == 20 and data[3].y[2] == 30)
+            assert(data.name == "nested")
+        "#,
+        )
+        .exec()?;
+
+    // Tables with functions, metatables or cycles cannot be detached
+    let bad = [
+        "{ f = print }",
+        "setmetatable({}, {})",
+        "(function() local t = {}; t.self = t; return t end)()",
+    ];
+    for code in bad.iter() {
+        match OwnedValue::from_table(lua_a.load(*code).eval::<Table>()?) {
+            Err(Error::FromLuaConversionError { .. }) => {}
+            r => panic!("expected FromLuaConversionError, got {:?}", r),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_value_approx_eq() -> Result<()> {
+    let lua = Lua::new();
+
+    assert!(Value::Number(0.1 + 0.2).approx_eq(&Value::Number(0.3), 1e-9));
+    assert!(Value::Number(0.1 + 0.2) != Value::Number(0.3));
+    assert!(!Value::Number(0.31).approx_eq(&Value::Number(0.3), 1e-9));
+    assert!(Value::Integer(3).approx_eq(&Value::Number(3.0), 0.0));
+
+    let result = lua.load("math.sqrt(2) * math.sqrt(2)").eval::<Value>()?;
+    assert!(result.approx_eq(&Value::Integer(2), 1e-9));
+
+    let s = Value::String(lua.create_string("0.3")?);
+    assert!(!s.approx_eq(&Value::Number(0.3), 1e-9));
+    assert!(!Value::Nil.approx_eq(&Value::Nil, 1e-9));
+
+    Ok(())
+}
+
+#[test]
+#[allow(clippy::mutable_key_type)]
+fn test_value_key() -> Result<()> {
+    let lua = Lua::new();
+
+    let mut map = HashMap::new();
+    map.insert(Key(Value::Integer(2)), "two");
+    map.insert(Key(Value::Number(2.5)), "two and a half");
+    map.insert(Key(Value::String(lua.create_string("a")?)), "a");
+
+    assert_eq!(map.get(&Key(Value::Number(2.0))), Some(&"two"));
+    assert_eq!(map.get(&Key(Value::Integer(2))), Some(&"two"));
+    assert_eq!(map.get(&Key(Value::Number(2.5))), Some(&"two and a half"));
+    assert_eq!(map.get(&Key(Value::Number(2.0000001))), None);
+    assert_eq!(
+        map.get(&Key(Value::String(lua.create_string("a")?))),
+        Some(&"a")
+    );
+
+    // Inserting an equal key replaces the value
+    map.insert(Key(Value::Number(2.0)), "still two");
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.get(&Key(Value::Integer(2))), Some(&"still two"));
+
+    // Tables are compared by identity
+    let t1 = lua.create_table()?;
+    let t2 = lua.create_table()?;
+    map.insert(Key(Value::Table(t1.clone())), "t1");
+    assert_eq!(map.get(&Key(Value::Table(t1))), Some(&"t1"));
+    assert_eq!(map.get(&Key(Value::Table(t2))), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_multi_value_select() -> Result<()> {
+    let lua = Lua::new();
+
+    let values: MultiValue = lua.load("return 1, 2, nil, 4").eval()?;
+    assert_eq!(values.count(), 4);
+
+    let tail = values.select(2);
+    assert_eq!(tail.count(), 3);
+    assert_eq!(
+        tail.into_vec(),
+        vec![Value::Integer(2), Value::Nil, Value::Integer(4)]
+    );
+
+    assert_eq!(values.select(1).count(), 4);
+    assert_eq!(values.select(4).into_vec(), vec![Value::Integer(4)]);
+    assert!(values.select(5).is_empty());
+
+    // Forwarding variadics from a callback
+    let skip_first = lua.create_function(|_, args: MultiValue| Ok(args.select(2)))?;
+    let rest: Variadic<i64> = skip_first.call((10, 20, 30))?;
+    assert_eq!(rest.to_vec(), vec![20, 30]);
+
+    Ok(())
+}